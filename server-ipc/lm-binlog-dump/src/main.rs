@@ -0,0 +1,113 @@
+//! Host tool dumping a `lm-binlogs` directory (as written by the `lm` sysmodule example) as text or JSON lines
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use lm_binlog::binlog::{BinLogReader, BINLOG_EXTENSION};
+use lm_binlog::fmt::{PacketJson, PacketText};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+fn usage() -> ExitCode {
+    eprintln!("Usage: lm-binlog-dump [--json] <lm-binlogs directory or .nxbinlog file>...");
+    ExitCode::FAILURE
+}
+
+// Program log directories are named after the program ID, like "0x0100000000001000"
+fn get_program_id(path: &Path) -> Option<u64> {
+    let dir_name = path.parent()?.file_name()?.to_str()?;
+    u64::from_str_radix(dir_name.strip_prefix("0x")?, 16).ok()
+}
+
+fn collect_binlogs(path: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // File names are zero-padded boot and segment indices, so this also sorts them chronologically
+        entries.sort();
+
+        for entry in entries {
+            collect_binlogs(&entry, out)?;
+        }
+    } else if path.extension().is_some_and(|ext| ext == BINLOG_EXTENSION) {
+        out.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn dump_binlog(path: &Path, format: OutputFormat, out: &mut impl Write) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let program_id = get_program_id(path);
+
+    let reader = match BinLogReader::new(&data) {
+        Ok(reader) => reader,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return Ok(());
+        }
+    };
+
+    for record in reader {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let tick = record.tick;
+
+        match record.parse_packet() {
+            Ok(packet) => match format {
+                OutputFormat::Text => writeln!(out, "{}", PacketText { packet: &packet, program_id, tick })?,
+                OutputFormat::Json => writeln!(out, "{}", PacketJson { packet: &packet, program_id, tick })?,
+            },
+            Err(e) => eprintln!("{}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut format = OutputFormat::Text;
+    let mut inputs = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => format = OutputFormat::Json,
+            "-h" | "--help" => return usage(),
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+    if inputs.is_empty() {
+        return usage();
+    }
+
+    let mut binlogs = Vec::new();
+    for input in &inputs {
+        if let Err(e) = collect_binlogs(input, &mut binlogs) {
+            eprintln!("{}: {}", input.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    for binlog in &binlogs {
+        if let Err(e) = dump_binlog(binlog, format, &mut out) {
+            eprintln!("{}: {}", binlog.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match out.flush() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::FAILURE,
+    }
+}
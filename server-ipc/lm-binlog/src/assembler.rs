@@ -0,0 +1,403 @@
+//! Reassembly of log messages split across several packets
+//!
+//! Long messages are sent as a packet with the head flag, optional continuation packets, and a last one with the tail flag.
+//! Every packet has its own header and chunks, and the text of the message is spread across their text log chunks.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::packet::{
+    LogDataChunkIter, LogDataChunkKey, LogPacketFlags, LogPacketHeader, ParseError, Result,
+    LOG_PACKET_HEADER_SIZE,
+};
+
+/// Limit on the data buffered by several assemblers together, since every one of them only knows about its own messages
+pub struct PendingBudget {
+    used_size: AtomicUsize,
+    max_size: usize,
+}
+
+impl PendingBudget {
+    pub const fn new(max_size: usize) -> Self {
+        Self {
+            used_size: AtomicUsize::new(0),
+            max_size,
+        }
+    }
+
+    pub fn get_used_size(&self) -> usize {
+        self.used_size.load(Ordering::Relaxed)
+    }
+
+    fn try_reserve(&self, size: usize) -> bool {
+        self.used_size
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used_size| {
+                Some(used_size + size).filter(|&new_size| new_size <= self.max_size)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, size: usize) {
+        self.used_size.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+struct PendingMessage {
+    thread_id: u64,
+    // Raw packets (headers included) received so far, back to back
+    packets: Vec<u8>,
+}
+
+/// Buffers split messages until their tail packet arrives, per sending thread
+pub struct PacketAssembler<'a> {
+    pending: Vec<PendingMessage>,
+    pending_size: usize,
+    max_pending_size: usize,
+    budget: &'a PendingBudget,
+}
+
+impl<'a> PacketAssembler<'a> {
+    /// Creates an assembler which will never buffer more than `max_pending_size` bytes, nor go over the shared budget
+    ///
+    /// Messages which would go over either limit are emitted incomplete (without the tail flag) to make room, oldest first.
+    /// When there is nothing of its own left to emit, new messages are emitted right away, packet by packet.
+    pub const fn new(max_pending_size: usize, budget: &'a PendingBudget) -> Self {
+        Self {
+            pending: Vec::new(),
+            pending_size: 0,
+            max_pending_size,
+            budget,
+        }
+    }
+
+    pub fn get_pending_size(&self) -> usize {
+        self.pending_size
+    }
+
+    /// Feeds a received packet, calling `emit` for every message which gets completed by it
+    ///
+    /// Packets which can't be parsed, complete single-packet messages and continuations without a head are emitted as they are.
+    pub fn push(&mut self, packet_buf: &[u8], mut emit: impl FnMut(&[u8])) {
+        let header = match LogPacketHeader::parse(packet_buf) {
+            Ok(header) => header,
+            Err(_) => return emit(packet_buf),
+        };
+
+        let pending_idx = self
+            .pending
+            .iter()
+            .position(|pending| pending.thread_id == header.thread_id);
+
+        if header.flags.is_head() {
+            // A new message from this thread means the previous one will never be finished
+            if let Some(idx) = pending_idx {
+                self.flush_at(idx, &mut emit);
+            }
+            if header.flags.is_tail() || !self.reserve(packet_buf.len(), &mut emit) {
+                return emit(packet_buf);
+            }
+
+            self.pending.push(PendingMessage {
+                thread_id: header.thread_id,
+                packets: Vec::from(packet_buf),
+            });
+            self.pending_size += packet_buf.len();
+            return;
+        }
+
+        let idx = match pending_idx {
+            Some(idx) => idx,
+            None => return emit(packet_buf),
+        };
+
+        if (self.pending_size + packet_buf.len() > self.max_pending_size) || !self.budget.try_reserve(packet_buf.len()) {
+            // Emit what we have so far, this continuation will be emitted on its own
+            self.flush_at(idx, &mut emit);
+            return emit(packet_buf);
+        }
+
+        // Don't let the buffer double its capacity, the budget is meant to bound what actually gets allocated
+        let packets = &mut self.pending[idx].packets;
+        packets.reserve_exact(packet_buf.len());
+        packets.extend_from_slice(packet_buf);
+        self.pending_size += packet_buf.len();
+        if header.flags.is_tail() {
+            self.flush_at(idx, &mut emit);
+        }
+    }
+
+    /// Emits every buffered message as it is, meant to be used when the sender goes away
+    pub fn flush_all(&mut self, mut emit: impl FnMut(&[u8])) {
+        while !self.pending.is_empty() {
+            self.flush_at(0, &mut emit);
+        }
+    }
+
+    // Emits the oldest messages until `size` more bytes can be buffered, returns whether they can
+    fn reserve(&mut self, size: usize, emit: &mut impl FnMut(&[u8])) -> bool {
+        loop {
+            if (self.pending_size + size <= self.max_pending_size) && self.budget.try_reserve(size) {
+                return true;
+            }
+            if self.pending.is_empty() {
+                return false;
+            }
+            self.flush_at(0, emit);
+        }
+    }
+
+    fn flush_at(&mut self, idx: usize, emit: &mut impl FnMut(&[u8])) {
+        let pending = self.pending.remove(idx);
+        self.pending_size -= pending.packets.len();
+        self.budget.release(pending.packets.len());
+
+        match merge_packets(&pending.packets) {
+            Ok(merged) => emit(&merged),
+            // Shouldn't happen since every buffered packet had a valid header, but don't lose anything
+            Err(_) => emit(&pending.packets),
+        }
+    }
+}
+
+impl Drop for PacketAssembler<'_> {
+    fn drop(&mut self) {
+        // Whatever wasn't flushed is gone, so give its share back
+        self.budget.release(self.pending_size);
+    }
+}
+
+/// Merges back-to-back raw packets into a single packet
+///
+/// The header of the first packet is kept, with the tail flag of the last one. Text log chunks are concatenated,
+/// and for any other chunk only its first occurrence is kept.
+pub fn merge_packets(mut packets: &[u8]) -> Result<Vec<u8>> {
+    let mut first_header: Option<LogPacketHeader> = None;
+    let mut is_tail = false;
+    let mut chunks: Vec<(u8, &[u8])> = Vec::new();
+    let mut text: Vec<u8> = Vec::new();
+    let mut has_text = false;
+
+    while !packets.is_empty() {
+        let header = LogPacketHeader::parse(packets)?;
+        let payload_end = LOG_PACKET_HEADER_SIZE + header.payload_size as usize;
+        let payload = packets
+            .get(LOG_PACKET_HEADER_SIZE..payload_end)
+            .ok_or(ParseError::InvalidPayloadSize(header.payload_size))?;
+
+        for chunk in LogDataChunkIter::new(payload) {
+            let chunk = chunk?;
+            if chunk.key == LogDataChunkKey::TextLog as u8 {
+                if !has_text {
+                    // Keep the text where its first chunk was
+                    chunks.push((chunk.key, &[]));
+                    has_text = true;
+                }
+                text.extend_from_slice(chunk.data);
+            } else if !chunks.iter().any(|(key, _)| *key == chunk.key) {
+                chunks.push((chunk.key, chunk.data));
+            }
+        }
+
+        first_header.get_or_insert(header);
+        is_tail = header.flags.is_tail();
+        packets = &packets[payload_end..];
+    }
+
+    let mut header = first_header.ok_or(ParseError::UnexpectedEnd)?;
+
+    let mut payload = Vec::new();
+    for (key, data) in chunks {
+        let data = if key == LogDataChunkKey::TextLog as u8 {
+            text.as_slice()
+        } else {
+            data
+        };
+        write_uleb128(&mut payload, key as u32);
+        write_uleb128(&mut payload, data.len() as u32);
+        payload.extend_from_slice(data);
+    }
+
+    header.flags = LogPacketFlags(if is_tail {
+        header.flags.0 | LogPacketFlags::TAIL
+    } else {
+        header.flags.0 & !LogPacketFlags::TAIL
+    });
+    header.payload_size = payload.len() as u32;
+
+    let mut header_buf = [0u8; LOG_PACKET_HEADER_SIZE];
+    header.write_to(&mut header_buf);
+
+    let mut merged = Vec::with_capacity(LOG_PACKET_HEADER_SIZE + payload.len());
+    merged.extend_from_slice(&header_buf);
+    merged.extend_from_slice(&payload);
+    Ok(merged)
+}
+
+pub(crate) fn write_uleb128(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::tests::{make_packet, text_packet};
+    use crate::packet::LogPacket;
+    use alloc::vec;
+
+    const HEAD: u8 = LogPacketFlags::HEAD;
+    const TAIL: u8 = LogPacketFlags::TAIL;
+
+    // Pushes the packets, collecting everything emitted
+    fn push_all(assembler: &mut PacketAssembler, packets: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut emitted = Vec::new();
+        for packet in packets {
+            assembler.push(packet, |packet_buf| emitted.push(Vec::from(packet_buf)));
+        }
+        emitted
+    }
+
+    fn get_text(packet_buf: &[u8]) -> Vec<u8> {
+        Vec::from(LogPacket::parse(packet_buf).unwrap().text_log.unwrap())
+    }
+
+    fn get_flags(packet_buf: &[u8]) -> LogPacketFlags {
+        LogPacketHeader::parse(packet_buf).unwrap().flags
+    }
+
+    #[test]
+    fn single_packets_pass_through() {
+        let budget = PendingBudget::new(0x1000);
+        let mut assembler = PacketAssembler::new(0x1000, &budget);
+        let whole = text_packet(1, HEAD | TAIL, b"whole");
+        let orphan = text_packet(1, TAIL, b"orphan");
+        let garbage = [1u8, 2, 3];
+        assert_eq!(push_all(&mut assembler, &[&whole, &orphan, &garbage]), vec![whole, orphan, Vec::from(garbage)]);
+        assert_eq!(budget.get_used_size(), 0);
+    }
+
+    #[test]
+    fn merges_split_messages() {
+        let budget = PendingBudget::new(0x1000);
+        let mut assembler = PacketAssembler::new(0x1000, &budget);
+        let head = make_packet(1, HEAD, &[(LogDataChunkKey::ModuleName as u8, b"first"), (LogDataChunkKey::TextLog as u8, b"Hello, ")]);
+        let middle = make_packet(1, 0, &[(LogDataChunkKey::ModuleName as u8, b"second"), (LogDataChunkKey::TextLog as u8, b"split ")]);
+        let tail = text_packet(1, TAIL, b"world");
+
+        assert!(push_all(&mut assembler, &[&head, &middle]).is_empty());
+        assert_eq!(assembler.get_pending_size(), head.len() + middle.len());
+        assert_eq!(budget.get_used_size(), head.len() + middle.len());
+
+        let emitted = push_all(&mut assembler, &[&tail]);
+        assert_eq!(emitted.len(), 1);
+        let merged = LogPacket::parse(&emitted[0]).unwrap();
+        assert_eq!(merged.text_log, Some(&b"Hello, split world"[..]));
+        // Only the first occurrence of other chunks is kept
+        assert_eq!(merged.module_name, Some(&b"first"[..]));
+        let flags = merged.header.unwrap().flags;
+        assert!(flags.is_head() && flags.is_tail());
+        assert_eq!(assembler.get_pending_size(), 0);
+        assert_eq!(budget.get_used_size(), 0);
+    }
+
+    #[test]
+    fn threads_are_kept_apart() {
+        let budget = PendingBudget::new(0x1000);
+        let mut assembler = PacketAssembler::new(0x1000, &budget);
+        let emitted = push_all(
+            &mut assembler,
+            &[&text_packet(1, HEAD, b"a1 "), &text_packet(2, HEAD, b"b1 "), &text_packet(1, TAIL, b"a2"), &text_packet(2, TAIL, b"b2")],
+        );
+        let texts: Vec<_> = emitted.iter().map(|packet_buf| get_text(packet_buf)).collect();
+        assert_eq!(texts, vec![b"a1 a2".to_vec(), b"b1 b2".to_vec()]);
+    }
+
+    #[test]
+    fn new_head_flushes_unfinished_message() {
+        let budget = PendingBudget::new(0x1000);
+        let mut assembler = PacketAssembler::new(0x1000, &budget);
+        let emitted = push_all(&mut assembler, &[&text_packet(1, HEAD, b"lost "), &text_packet(1, HEAD, b"new")]);
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(get_text(&emitted[0]), b"lost ");
+        assert!(!get_flags(&emitted[0]).is_tail());
+        assert_eq!(budget.get_used_size(), assembler.get_pending_size());
+    }
+
+    #[test]
+    fn own_limit_emits_oldest_first() {
+        let head_1 = text_packet(1, HEAD, b"one ");
+        let head_2 = text_packet(2, HEAD, b"two ");
+        let budget = PendingBudget::new(0x1000);
+        let mut assembler = PacketAssembler::new(head_1.len() + head_2.len(), &budget);
+
+        assert!(push_all(&mut assembler, &[&head_1, &head_2]).is_empty());
+        let emitted = push_all(&mut assembler, &[&text_packet(3, HEAD, b"six ")]);
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(get_text(&emitted[0]), b"one ");
+
+        // A continuation which doesn't fit flushes its own message and goes out on its own
+        let continuation = text_packet(2, TAIL, b"too long for the limit");
+        let emitted = push_all(&mut assembler, &[&continuation]);
+        assert_eq!(emitted.len(), 2);
+        assert_eq!(get_text(&emitted[0]), b"two ");
+        assert_eq!(emitted[1], continuation);
+        assert_eq!(budget.get_used_size(), assembler.get_pending_size());
+    }
+
+    #[test]
+    fn shared_budget() {
+        let head = text_packet(1, HEAD, b"head ");
+        let budget = PendingBudget::new(2 * head.len());
+        let mut first = PacketAssembler::new(0x1000, &budget);
+        let mut second = PacketAssembler::new(0x1000, &budget);
+
+        assert!(push_all(&mut first, &[&head, &text_packet(2, HEAD, b"head ")]).is_empty());
+        assert_eq!(budget.get_used_size(), 2 * head.len());
+
+        // Nothing of its own to make room with, so the packet goes out right away
+        let other_head = text_packet(3, HEAD, b"other");
+        assert_eq!(push_all(&mut second, &[&other_head]), vec![other_head.clone()]);
+        assert_eq!(second.get_pending_size(), 0);
+
+        // Once the first one makes room, the second one can buffer again, and then evicts its own messages
+        first.flush_all(|_| {});
+        assert_eq!(budget.get_used_size(), 0);
+        assert!(push_all(&mut second, &[&head, &text_packet(2, HEAD, b"head ")]).is_empty());
+        assert_eq!(push_all(&mut first, &[&head]), vec![head.clone()]);
+        let emitted = push_all(&mut second, &[&text_packet(3, HEAD, b"head ")]);
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(LogPacketHeader::parse(&emitted[0]).unwrap().thread_id, 1);
+        assert_eq!(budget.get_used_size(), 2 * head.len());
+
+        // Sessions going away give their share back, even without flushing
+        drop(second);
+        assert_eq!(budget.get_used_size(), 0);
+    }
+
+    #[test]
+    fn flush_all() {
+        let budget = PendingBudget::new(0x1000);
+        let mut assembler = PacketAssembler::new(0x1000, &budget);
+        assert!(push_all(&mut assembler, &[&text_packet(1, HEAD, b"a"), &text_packet(2, HEAD, b"b"), &text_packet(2, 0, b"c")]).is_empty());
+
+        let mut emitted = Vec::new();
+        assembler.flush_all(|packet_buf| emitted.push(get_text(packet_buf)));
+        assert_eq!(emitted, vec![b"a".to_vec(), b"bc".to_vec()]);
+        assert_eq!(budget.get_used_size(), 0);
+    }
+
+    #[test]
+    fn merge_invalid_packets() {
+        assert_eq!(merge_packets(&[]), Err(ParseError::UnexpectedEnd));
+        let mut packet = text_packet(1, HEAD, b"text");
+        packet.pop();
+        assert_eq!(merge_packets(&packet), Err(ParseError::InvalidPayloadSize(6)));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::packet::{read_u32, read_u64, LogPacket, ParseError, Result};
+
+/// Extension used for binary log files
+pub const BINLOG_EXTENSION: &str = "nxbinlog";
+
+pub const LOG_BINARY_HEADER_MAGIC: u32 = 0x70687068;
+pub const LOG_BINARY_HEADER_SIZE: usize = 8;
+
+/// Version 1: the header is followed by a single raw log packet
+pub const VERSION_SINGLE_PACKET: u32 = 1;
+/// Version 2: the header is followed by any number of records, each one being a `LogRecordHeader` and a raw log packet
+pub const VERSION_FRAMED_RECORDS: u32 = 2;
+
+pub const CURRENT_VERSION: u32 = VERSION_FRAMED_RECORDS;
+
+pub const LOG_RECORD_HEADER_SIZE: usize = 0x10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct LogBinaryHeader {
+    pub magic: u32,
+    pub version: u32,
+}
+
+impl LogBinaryHeader {
+    pub const fn new(magic: u32, version: u32) -> Self {
+        Self { magic, version }
+    }
+
+    pub const fn current() -> Self {
+        Self::new(LOG_BINARY_HEADER_MAGIC, CURRENT_VERSION)
+    }
+
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < LOG_BINARY_HEADER_SIZE {
+            return Err(ParseError::UnexpectedEnd);
+        }
+
+        let header = Self::new(read_u32(&buf[0x0..]), read_u32(&buf[0x4..]));
+        if header.magic != LOG_BINARY_HEADER_MAGIC {
+            return Err(ParseError::InvalidMagic(header.magic));
+        }
+        if (header.version != VERSION_SINGLE_PACKET) && (header.version != VERSION_FRAMED_RECORDS) {
+            return Err(ParseError::UnsupportedVersion(header.version));
+        }
+        Ok(header)
+    }
+
+    pub fn to_bytes(&self) -> [u8; LOG_BINARY_HEADER_SIZE] {
+        let mut bytes = [0u8; LOG_BINARY_HEADER_SIZE];
+        bytes[0x0..0x4].copy_from_slice(&self.magic.to_le_bytes());
+        bytes[0x4..0x8].copy_from_slice(&self.version.to_le_bytes());
+        bytes
+    }
+}
+
+/// Frame preceding every record in multi-record files
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct LogRecordHeader {
+    pub packet_size: u32,
+    pub reserved: u32,
+    /// System tick when the record was written
+    pub tick: u64,
+}
+
+impl LogRecordHeader {
+    pub const fn new(packet_size: u32, tick: u64) -> Self {
+        Self {
+            packet_size,
+            reserved: 0,
+            tick,
+        }
+    }
+
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < LOG_RECORD_HEADER_SIZE {
+            return Err(ParseError::UnexpectedEnd);
+        }
+
+        Ok(Self {
+            packet_size: read_u32(&buf[0x0..]),
+            reserved: read_u32(&buf[0x4..]),
+            tick: read_u64(&buf[0x8..]),
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; LOG_RECORD_HEADER_SIZE] {
+        let mut bytes = [0u8; LOG_RECORD_HEADER_SIZE];
+        bytes[0x0..0x4].copy_from_slice(&self.packet_size.to_le_bytes());
+        bytes[0x4..0x8].copy_from_slice(&self.reserved.to_le_bytes());
+        bytes[0x8..0x10].copy_from_slice(&self.tick.to_le_bytes());
+        bytes
+    }
+}
+
+/// Record stored in a binlog file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BinLogRecord<'a> {
+    /// System tick when the record was written, not available in single-packet files
+    pub tick: Option<u64>,
+    /// Raw packet data (header and payload)
+    pub packet_buf: &'a [u8],
+}
+
+impl<'a> BinLogRecord<'a> {
+    pub fn parse_packet(&self) -> Result<LogPacket<'a>> {
+        LogPacket::parse(self.packet_buf)
+    }
+}
+
+/// Reader over the records of a whole binlog file
+pub struct BinLogReader<'a> {
+    header: LogBinaryHeader,
+    data: &'a [u8],
+}
+
+impl<'a> BinLogReader<'a> {
+    pub fn new(file_data: &'a [u8]) -> Result<Self> {
+        let header = LogBinaryHeader::parse(file_data)?;
+        Ok(Self {
+            header,
+            data: &file_data[LOG_BINARY_HEADER_SIZE..],
+        })
+    }
+
+    pub const fn get_header(&self) -> LogBinaryHeader {
+        self.header
+    }
+}
+
+impl<'a> Iterator for BinLogReader<'a> {
+    type Item = Result<BinLogRecord<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        if self.header.version == VERSION_SINGLE_PACKET {
+            let record = BinLogRecord {
+                tick: None,
+                packet_buf: self.data,
+            };
+            self.data = &[];
+            return Some(Ok(record));
+        }
+
+        let record = LogRecordHeader::parse(self.data).and_then(|record_header| {
+            let packet_end = LOG_RECORD_HEADER_SIZE + record_header.packet_size as usize;
+            let packet_buf = self
+                .data
+                .get(LOG_RECORD_HEADER_SIZE..packet_end)
+                .ok_or(ParseError::UnexpectedEnd)?;
+            self.data = &self.data[packet_end..];
+            Ok(BinLogRecord {
+                tick: Some(record_header.tick),
+                packet_buf,
+            })
+        });
+        if record.is_err() {
+            // The last record might be truncated (the console might have been turned off while writing it), stop there
+            self.data = &[];
+        }
+        Some(record)
+    }
+}
@@ -0,0 +1,272 @@
+//! Human-readable and JSON formatting of decoded packets
+//!
+//! Everything here formats straight into a `core::fmt::Write`, so no allocations are needed.
+
+use core::fmt;
+
+use crate::packet::{LogPacket, LogSeverity};
+use crate::record::LogRecord;
+
+/// Displays raw bytes as UTF-8, replacing invalid sequences
+pub struct Lossy<'a>(pub &'a [u8]);
+
+impl fmt::Display for Lossy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            f.write_str(chunk.valid())?;
+            if !chunk.invalid().is_empty() {
+                f.write_str("\u{FFFD}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Displays raw bytes as a quoted and escaped JSON string
+pub struct JsonStr<'a>(pub &'a [u8]);
+
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for chunk in self.0.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match c {
+                    '"' => f.write_str("\\\"")?,
+                    '\\' => f.write_str("\\\\")?,
+                    '\n' => f.write_str("\\n")?,
+                    '\r' => f.write_str("\\r")?,
+                    '\t' => f.write_str("\\t")?,
+                    c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                    c => fmt::Write::write_char(f, c)?,
+                }
+            }
+            if !chunk.invalid().is_empty() {
+                f.write_str("\\ufffd")?;
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+fn severity_name(packet: &LogPacket) -> &'static str {
+    packet
+        .get_severity()
+        .map(LogSeverity::name)
+        .unwrap_or("Unknown")
+}
+
+// Log texts usually come with their own trailing newline
+fn trim_newline(text: &[u8]) -> &[u8] {
+    text.strip_suffix(b"\n").unwrap_or(text)
+}
+
+/// Formats a packet as a single line of text
+pub struct PacketText<'a, 'b> {
+    pub packet: &'b LogPacket<'a>,
+    pub program_id: Option<u64>,
+    /// System tick of the record containing the packet, if known
+    pub tick: Option<u64>,
+}
+
+impl fmt::Display for PacketText<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packet = self.packet;
+        if let Some(tick) = self.tick {
+            write!(f, "{:016X} ", tick)?;
+        }
+        write!(f, "[{}", severity_name(packet))?;
+        if let Some(program_id) = self.program_id {
+            write!(f, " | 0x{:016X}", program_id)?;
+        }
+        if let Some(header) = packet.header {
+            write!(f, " | pid {} tid {}", header.process_id, header.thread_id)?;
+        }
+        if let Some(module_name) = packet.module_name {
+            write!(f, " | {}", Lossy(module_name))?;
+        }
+        if let Some(thread_name) = packet.thread_name {
+            write!(f, " | thread {}", Lossy(thread_name))?;
+        }
+        if let Some(file_name) = packet.file_name {
+            write!(f, " | {}", Lossy(file_name))?;
+            if let Some(line_number) = packet.line_number {
+                write!(f, ":{}", line_number)?;
+            }
+        }
+        if let Some(function_name) = packet.function_name {
+            write!(f, " | {}", Lossy(function_name))?;
+        }
+        f.write_str("]")?;
+
+        if packet.session_begin {
+            f.write_str(" <session begin>")?;
+        }
+        if packet.session_end {
+            f.write_str(" <session end>")?;
+        }
+        if let Some(drop_count) = packet.drop_count {
+            write!(f, " <{} packets dropped>", drop_count)?;
+        }
+        if let Some(text_log) = packet.text_log {
+            write!(f, " {}", Lossy(trim_newline(text_log)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a packet as a single-line JSON object
+pub struct PacketJson<'a, 'b> {
+    pub packet: &'b LogPacket<'a>,
+    pub program_id: Option<u64>,
+    /// System tick of the record containing the packet, if known
+    pub tick: Option<u64>,
+}
+
+impl fmt::Display for PacketJson<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packet = self.packet;
+        write!(f, "{{\"severity\":\"{}\"", severity_name(packet))?;
+        if let Some(program_id) = self.program_id {
+            write!(f, ",\"program_id\":\"0x{:016X}\"", program_id)?;
+        }
+        if let Some(tick) = self.tick {
+            write!(f, ",\"tick\":{}", tick)?;
+        }
+        if let Some(header) = packet.header {
+            write!(
+                f,
+                ",\"process_id\":{},\"thread_id\":{},\"verbosity\":{},\"head\":{},\"tail\":{}",
+                header.process_id,
+                header.thread_id,
+                header.verbosity,
+                header.flags.is_head(),
+                header.flags.is_tail()
+            )?;
+        }
+        if packet.session_begin {
+            f.write_str(",\"session_begin\":true")?;
+        }
+        if packet.session_end {
+            f.write_str(",\"session_end\":true")?;
+        }
+
+        let str_fields = [
+            ("module_name", packet.module_name),
+            ("process_name", packet.process_name),
+            ("thread_name", packet.thread_name),
+            ("file_name", packet.file_name),
+            ("function_name", packet.function_name),
+            ("text", packet.text_log.map(trim_newline)),
+        ];
+        for (name, value) in str_fields {
+            if let Some(value) = value {
+                write!(f, ",\"{}\":{}", name, JsonStr(value))?;
+            }
+        }
+
+        if let Some(line_number) = packet.line_number {
+            write!(f, ",\"line_number\":{}", line_number)?;
+        }
+        if let Some(drop_count) = packet.drop_count {
+            write!(f, ",\"drop_count\":{}", drop_count)?;
+        }
+        if let Some(user_system_clock) = packet.user_system_clock {
+            write!(f, ",\"user_system_clock\":{}", user_system_clock)?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// Formats a history record as a single line of text, like `PacketText`
+pub struct RecordText<'a, 'b>(pub &'b LogRecord<'a>);
+
+impl fmt::Display for RecordText<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let record = self.0;
+        let severity = record.get_severity().map(LogSeverity::name).unwrap_or("Unknown");
+        write!(
+            f,
+            "{:016X} [{} | 0x{:016X} | pid {} tid {}",
+            record.tick, severity, record.program_id, record.process_id, record.thread_id
+        )?;
+        if !record.module_name.is_empty() {
+            write!(f, " | {}", Lossy(record.module_name))?;
+        }
+        if !record.thread_name.is_empty() {
+            write!(f, " | thread {}", Lossy(record.thread_name))?;
+        }
+        if !record.file_name.is_empty() {
+            write!(f, " | {}:{}", Lossy(record.file_name), record.line_number)?;
+        }
+        if !record.function_name.is_empty() {
+            write!(f, " | {}", Lossy(record.function_name))?;
+        }
+        write!(f, "] {}", Lossy(trim_newline(record.text)))
+    }
+}
+
+/// Fixed-size text buffer to format into without allocating, silently truncating whatever doesn't fit
+pub struct FixedBuffer<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> FixedBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only whole UTF-8 characters are ever written
+        core::str::from_utf8(self.as_bytes()).unwrap_or_default()
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether something had to be truncated
+    pub const fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+}
+
+impl<const N: usize> Default for FixedBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for FixedBuffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut copy_len = s.len().min(N - self.len);
+        while !s.is_char_boundary(copy_len) {
+            copy_len -= 1;
+        }
+
+        self.buf[self.len..self.len + copy_len].copy_from_slice(&s.as_bytes()[..copy_len]);
+        self.len += copy_len;
+        if copy_len < s.len() {
+            self.truncated = true;
+        }
+        Ok(())
+    }
+}
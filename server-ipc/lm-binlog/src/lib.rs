@@ -0,0 +1,14 @@
+//! Decoding support for the log packets received by the `lm` sysmodule example and the `.nxbinlog` files it writes
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod assembler;
+pub mod binlog;
+pub mod fmt;
+pub mod packet;
+pub mod record;
+pub mod ring;
+pub mod stream;
+pub mod time;
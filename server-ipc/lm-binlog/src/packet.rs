@@ -0,0 +1,475 @@
+use core::fmt;
+
+/// Size of the header preceding every log packet
+pub const LOG_PACKET_HEADER_SIZE: usize = 0x18;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The buffer ended before the expected amount of data could be read
+    UnexpectedEnd,
+    /// The payload size in the packet header does not match the remaining data
+    InvalidPayloadSize(u32),
+    /// A chunk had a size which is not valid for its key
+    InvalidChunkSize(u8, usize),
+    /// A ULEB128 value did not fit in 32 bits
+    InvalidLeb128,
+    /// The binlog header magic is not the expected one
+    InvalidMagic(u32),
+    /// The binlog header version is not supported
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of data"),
+            Self::InvalidPayloadSize(size) => write!(f, "invalid payload size 0x{:X}", size),
+            Self::InvalidChunkSize(key, size) => {
+                write!(f, "invalid size 0x{:X} for chunk with key {}", size, key)
+            }
+            Self::InvalidLeb128 => write!(f, "invalid ULEB128 value"),
+            Self::InvalidMagic(magic) => write!(f, "invalid binlog magic 0x{:08X}", magic),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported binlog version {}", version)
+            }
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, ParseError>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct LogPacketFlags(pub u8);
+
+impl LogPacketFlags {
+    pub const HEAD: u8 = 1 << 0;
+    pub const TAIL: u8 = 1 << 1;
+    pub const LITTLE_ENDIAN: u8 = 1 << 2;
+
+    /// Whether this is the first packet of a (possibly split) log message
+    pub const fn is_head(self) -> bool {
+        (self.0 & Self::HEAD) != 0
+    }
+
+    /// Whether this is the last packet of a (possibly split) log message
+    pub const fn is_tail(self) -> bool {
+        (self.0 & Self::TAIL) != 0
+    }
+
+    pub const fn is_little_endian(self) -> bool {
+        (self.0 & Self::LITTLE_ENDIAN) != 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogSeverity {
+    Trace = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+    Fatal = 4,
+}
+
+impl LogSeverity {
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Trace),
+            1 => Some(Self::Info),
+            2 => Some(Self::Warn),
+            3 => Some(Self::Error),
+            4 => Some(Self::Fatal),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Trace => "Trace",
+            Self::Info => "Info",
+            Self::Warn => "Warn",
+            Self::Error => "Error",
+            Self::Fatal => "Fatal",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LogPacketHeader {
+    pub process_id: u64,
+    pub thread_id: u64,
+    pub flags: LogPacketFlags,
+    pub severity: u8,
+    pub verbosity: u8,
+    pub payload_size: u32,
+}
+
+impl LogPacketHeader {
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        if buf.len() < LOG_PACKET_HEADER_SIZE {
+            return Err(ParseError::UnexpectedEnd);
+        }
+
+        Ok(Self {
+            process_id: read_u64(&buf[0x0..]),
+            thread_id: read_u64(&buf[0x8..]),
+            flags: LogPacketFlags(buf[0x10]),
+            // 0x11 is padding
+            severity: buf[0x12],
+            verbosity: buf[0x13],
+            payload_size: read_u32(&buf[0x14..]),
+        })
+    }
+
+    pub fn write_to(&self, buf: &mut [u8; LOG_PACKET_HEADER_SIZE]) {
+        buf[0x0..0x8].copy_from_slice(&self.process_id.to_le_bytes());
+        buf[0x8..0x10].copy_from_slice(&self.thread_id.to_le_bytes());
+        buf[0x10] = self.flags.0;
+        buf[0x11] = 0;
+        buf[0x12] = self.severity;
+        buf[0x13] = self.verbosity;
+        buf[0x14..0x18].copy_from_slice(&self.payload_size.to_le_bytes());
+    }
+
+    /// Gets the severity as a known value, if it is one
+    pub const fn get_severity(&self) -> Option<LogSeverity> {
+        LogSeverity::from_u8(self.severity)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LogDataChunkKey {
+    LogSessionBegin = 0,
+    LogSessionEnd = 1,
+    TextLog = 2,
+    LineNumber = 3,
+    FileName = 4,
+    FunctionName = 5,
+    ModuleName = 6,
+    ThreadName = 7,
+    LogPacketDropCount = 8,
+    UserSystemClock = 9,
+    ProcessName = 10,
+}
+
+impl LogDataChunkKey {
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::LogSessionBegin),
+            1 => Some(Self::LogSessionEnd),
+            2 => Some(Self::TextLog),
+            3 => Some(Self::LineNumber),
+            4 => Some(Self::FileName),
+            5 => Some(Self::FunctionName),
+            6 => Some(Self::ModuleName),
+            7 => Some(Self::ThreadName),
+            8 => Some(Self::LogPacketDropCount),
+            9 => Some(Self::UserSystemClock),
+            10 => Some(Self::ProcessName),
+            _ => None,
+        }
+    }
+}
+
+/// Raw chunk, as found in the packet payload
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LogDataChunk<'a> {
+    pub key: u8,
+    pub data: &'a [u8],
+}
+
+/// Iterator over the chunks of a packet payload
+///
+/// Chunk keys and sizes are ULEB128-encoded, which is compatible with the plain `u8` encoding used by older senders.
+pub struct LogDataChunkIter<'a> {
+    payload: &'a [u8],
+}
+
+impl<'a> LogDataChunkIter<'a> {
+    pub const fn new(payload: &'a [u8]) -> Self {
+        Self { payload }
+    }
+
+    fn next_chunk(&mut self) -> Result<LogDataChunk<'a>> {
+        let mut offset = 0;
+        let key = read_uleb128(self.payload, &mut offset)?;
+        let size = read_uleb128(self.payload, &mut offset)? as usize;
+        let data = self
+            .payload
+            .get(offset..offset + size)
+            .ok_or(ParseError::UnexpectedEnd)?;
+        self.payload = &self.payload[offset + size..];
+
+        // Keys are small values, any larger one will just be reported as an unknown chunk
+        Ok(LogDataChunk {
+            key: key.min(u8::MAX as u32) as u8,
+            data,
+        })
+    }
+}
+
+impl<'a> Iterator for LogDataChunkIter<'a> {
+    type Item = Result<LogDataChunk<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.payload.is_empty() {
+            return None;
+        }
+
+        let chunk = self.next_chunk();
+        if chunk.is_err() {
+            // Don't keep on parsing garbage
+            self.payload = &[];
+        }
+        Some(chunk)
+    }
+}
+
+/// Decoded log packet, borrowing its strings from the source buffer
+///
+/// String fields are kept as raw bytes since senders are not guaranteed to send valid UTF-8.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogPacket<'a> {
+    pub header: Option<LogPacketHeader>,
+    pub session_begin: bool,
+    pub session_end: bool,
+    pub text_log: Option<&'a [u8]>,
+    pub line_number: Option<u32>,
+    pub file_name: Option<&'a [u8]>,
+    pub function_name: Option<&'a [u8]>,
+    pub module_name: Option<&'a [u8]>,
+    pub thread_name: Option<&'a [u8]>,
+    pub process_name: Option<&'a [u8]>,
+    pub drop_count: Option<u64>,
+    pub user_system_clock: Option<u64>,
+}
+
+impl<'a> LogPacket<'a> {
+    /// Parses a single packet (header and payload) which must span the whole buffer
+    pub fn parse(buf: &'a [u8]) -> Result<Self> {
+        let (packet, rest) = Self::parse_prefix(buf)?;
+        if !rest.is_empty() {
+            return Err(ParseError::InvalidPayloadSize(
+                packet.header.map(|h| h.payload_size).unwrap_or(0),
+            ));
+        }
+        Ok(packet)
+    }
+
+    /// Parses a packet from the start of the buffer, returning the remaining data after it
+    pub fn parse_prefix(buf: &'a [u8]) -> Result<(Self, &'a [u8])> {
+        let header = LogPacketHeader::parse(buf)?;
+        let payload_end = LOG_PACKET_HEADER_SIZE + header.payload_size as usize;
+        let payload = buf
+            .get(LOG_PACKET_HEADER_SIZE..payload_end)
+            .ok_or(ParseError::InvalidPayloadSize(header.payload_size))?;
+
+        let mut packet = Self::parse_payload(payload)?;
+        packet.header = Some(header);
+        Ok((packet, &buf[payload_end..]))
+    }
+
+    /// Parses the chunks of a packet payload, without any header
+    pub fn parse_payload(payload: &'a [u8]) -> Result<Self> {
+        let mut packet = Self::default();
+        for chunk in LogDataChunkIter::new(payload) {
+            packet.apply_chunk(chunk?)?;
+        }
+        Ok(packet)
+    }
+
+    fn apply_chunk(&mut self, chunk: LogDataChunk<'a>) -> Result<()> {
+        let key = match LogDataChunkKey::from_u8(chunk.key) {
+            Some(key) => key,
+            // Newer senders might add keys we don't know about, just ignore them
+            None => return Ok(()),
+        };
+
+        match key {
+            LogDataChunkKey::LogSessionBegin => self.session_begin = true,
+            LogDataChunkKey::LogSessionEnd => self.session_end = true,
+            LogDataChunkKey::TextLog => self.text_log = Some(chunk.data),
+            LogDataChunkKey::LineNumber => self.line_number = Some(read_sized_u32(&chunk)?),
+            LogDataChunkKey::FileName => self.file_name = Some(chunk.data),
+            LogDataChunkKey::FunctionName => self.function_name = Some(chunk.data),
+            LogDataChunkKey::ModuleName => self.module_name = Some(chunk.data),
+            LogDataChunkKey::ThreadName => self.thread_name = Some(chunk.data),
+            LogDataChunkKey::ProcessName => self.process_name = Some(chunk.data),
+            LogDataChunkKey::LogPacketDropCount => self.drop_count = Some(read_sized_u64(&chunk)?),
+            LogDataChunkKey::UserSystemClock => {
+                self.user_system_clock = Some(read_sized_u64(&chunk)?)
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_severity(&self) -> Option<LogSeverity> {
+        self.header.and_then(|h| h.get_severity())
+    }
+}
+
+fn read_sized_u32(chunk: &LogDataChunk) -> Result<u32> {
+    match chunk.data.len() {
+        4 => Ok(read_u32(chunk.data)),
+        size => Err(ParseError::InvalidChunkSize(chunk.key, size)),
+    }
+}
+
+fn read_sized_u64(chunk: &LogDataChunk) -> Result<u64> {
+    match chunk.data.len() {
+        4 => Ok(read_u32(chunk.data) as u64),
+        8 => Ok(read_u64(chunk.data)),
+        size => Err(ParseError::InvalidChunkSize(chunk.key, size)),
+    }
+}
+
+pub(crate) fn read_u32(buf: &[u8]) -> u32 {
+    u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
+
+pub(crate) fn read_u64(buf: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn read_uleb128(buf: &[u8], offset: &mut usize) -> Result<u32> {
+    let mut value: u32 = 0;
+    for i in 0..5 {
+        let byte = *buf.get(*offset).ok_or(ParseError::UnexpectedEnd)?;
+        *offset += 1;
+
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if (byte & 0x80) == 0 {
+            return Ok(value);
+        }
+    }
+    Err(ParseError::InvalidLeb128)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    pub(crate) const PROCESS_ID: u64 = 0x51;
+
+    /// Builds a raw packet with the given chunks, encoding keys and sizes as ULEB128
+    pub(crate) fn make_packet(thread_id: u64, flags: u8, chunks: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for &(key, data) in chunks {
+            crate::assembler::write_uleb128(&mut payload, key as u32);
+            crate::assembler::write_uleb128(&mut payload, data.len() as u32);
+            payload.extend_from_slice(data);
+        }
+
+        let header = LogPacketHeader {
+            process_id: PROCESS_ID,
+            thread_id,
+            flags: LogPacketFlags(flags | LogPacketFlags::LITTLE_ENDIAN),
+            severity: LogSeverity::Warn as u8,
+            verbosity: 1,
+            payload_size: payload.len() as u32,
+        };
+        let mut packet = alloc::vec![0u8; LOG_PACKET_HEADER_SIZE];
+        header.write_to((&mut packet[..]).try_into().unwrap());
+        packet.extend_from_slice(&payload);
+        packet
+    }
+
+    pub(crate) fn text_packet(thread_id: u64, flags: u8, text: &[u8]) -> Vec<u8> {
+        make_packet(thread_id, flags, &[(LogDataChunkKey::TextLog as u8, text)])
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let packet = text_packet(7, LogPacketFlags::HEAD, b"hi");
+        let header = LogPacketHeader::parse(&packet).unwrap();
+        assert_eq!(header.process_id, PROCESS_ID);
+        assert_eq!(header.thread_id, 7);
+        assert!(header.flags.is_head() && !header.flags.is_tail() && header.flags.is_little_endian());
+        assert_eq!(header.get_severity(), Some(LogSeverity::Warn));
+        assert_eq!(header.payload_size, 4);
+
+        let mut header_buf = [0u8; LOG_PACKET_HEADER_SIZE];
+        header.write_to(&mut header_buf);
+        assert_eq!(&header_buf[..], &packet[..LOG_PACKET_HEADER_SIZE]);
+
+        assert_eq!(LogPacketHeader::parse(&packet[..LOG_PACKET_HEADER_SIZE - 1]), Err(ParseError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn parse_chunks() {
+        let packet = make_packet(
+            1,
+            LogPacketFlags::HEAD | LogPacketFlags::TAIL,
+            &[
+                (LogDataChunkKey::LogSessionBegin as u8, &[1]),
+                (LogDataChunkKey::TextLog as u8, b"text"),
+                (LogDataChunkKey::LineNumber as u8, &42u32.to_le_bytes()),
+                (LogDataChunkKey::FileName as u8, b"main.rs"),
+                (LogDataChunkKey::FunctionName as u8, b"main"),
+                (LogDataChunkKey::ModuleName as u8, b"module"),
+                (LogDataChunkKey::ThreadName as u8, b"thread"),
+                (LogDataChunkKey::ProcessName as u8, b"process"),
+                (LogDataChunkKey::LogPacketDropCount as u8, &3u64.to_le_bytes()),
+                // 4-byte values are accepted for 64-bit chunks too
+                (LogDataChunkKey::UserSystemClock as u8, &1_700_000_000u32.to_le_bytes()),
+                // Unknown keys are skipped
+                (200, b"unknown"),
+            ],
+        );
+        let packet = LogPacket::parse(&packet).unwrap();
+        assert!(packet.session_begin && !packet.session_end);
+        assert_eq!(packet.text_log, Some(&b"text"[..]));
+        assert_eq!(packet.line_number, Some(42));
+        assert_eq!(packet.file_name, Some(&b"main.rs"[..]));
+        assert_eq!(packet.function_name, Some(&b"main"[..]));
+        assert_eq!(packet.module_name, Some(&b"module"[..]));
+        assert_eq!(packet.thread_name, Some(&b"thread"[..]));
+        assert_eq!(packet.process_name, Some(&b"process"[..]));
+        assert_eq!(packet.drop_count, Some(3));
+        assert_eq!(packet.user_system_clock, Some(1_700_000_000));
+        assert_eq!(packet.get_severity(), Some(LogSeverity::Warn));
+    }
+
+    #[test]
+    fn multi_byte_chunk_sizes() {
+        let text = alloc::vec![b'a'; 300];
+        let packet = text_packet(1, LogPacketFlags::HEAD | LogPacketFlags::TAIL, &text);
+        // 300 needs two ULEB128 bytes
+        assert_eq!(&packet[LOG_PACKET_HEADER_SIZE..LOG_PACKET_HEADER_SIZE + 3], &[2, 0xAC, 0x02]);
+        assert_eq!(LogPacket::parse(&packet).unwrap().text_log, Some(&text[..]));
+
+        // Older senders with plain u8 keys and sizes are read the same way
+        assert_eq!(LogPacket::parse_payload(&[2, 2, b'h', b'i']).unwrap().text_log, Some(&b"hi"[..]));
+    }
+
+    #[test]
+    fn invalid_packets() {
+        let mut packet = text_packet(1, LogPacketFlags::HEAD, b"text");
+        packet.push(0);
+        assert_eq!(LogPacket::parse(&packet), Err(ParseError::InvalidPayloadSize(6)));
+        assert_eq!(LogPacket::parse_prefix(&packet).unwrap().1, &[0]);
+        packet.truncate(packet.len() - 2);
+        assert_eq!(LogPacket::parse(&packet), Err(ParseError::InvalidPayloadSize(6)));
+
+        let line_number = [LogDataChunkKey::LineNumber as u8, 2, 0, 0];
+        assert_eq!(LogPacket::parse_payload(&line_number), Err(ParseError::InvalidChunkSize(3, 2)));
+        let drop_count = [LogDataChunkKey::LogPacketDropCount as u8, 1, 0];
+        assert_eq!(LogPacket::parse_payload(&drop_count), Err(ParseError::InvalidChunkSize(8, 1)));
+
+        assert_eq!(LogPacket::parse_payload(&[2, 5, b'a']), Err(ParseError::UnexpectedEnd));
+        assert_eq!(LogPacket::parse_payload(&[2, 0x80]), Err(ParseError::UnexpectedEnd));
+        assert_eq!(LogPacket::parse_payload(&[2, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]), Err(ParseError::InvalidLeb128));
+    }
+
+    #[test]
+    fn chunk_iter_stops_after_error() {
+        let mut chunks = LogDataChunkIter::new(&[2, 1, b'a', 2, 9, b'b', 2, 1, b'c']);
+        assert_eq!(chunks.next(), Some(Ok(LogDataChunk { key: 2, data: b"a" })));
+        assert_eq!(chunks.next(), Some(Err(ParseError::UnexpectedEnd)));
+        assert_eq!(chunks.next(), None);
+    }
+}
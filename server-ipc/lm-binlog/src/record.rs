@@ -0,0 +1,257 @@
+//! Compact encoding of decoded log messages, as kept in the `lm` history buffer
+//!
+//! Every record is a fixed header followed by the module, thread, file and function names and the text,
+//! each of them as a `u16` length and its bytes.
+
+use crate::packet::{read_u32, read_u64, LogPacket, LogPacketHeader, LogSeverity, ParseError, Result};
+
+pub const LOG_RECORD_FIXED_SIZE: usize = 0x28;
+
+/// Longest text kept per record, longer texts are truncated
+pub const MAX_TEXT_SIZE: usize = 0x400;
+/// Longest name (module, thread, file or function) kept per record
+pub const MAX_NAME_SIZE: usize = 0x80;
+
+/// Size of a record holding the longest texts and names, useful for encoding buffers
+pub const MAX_LOG_RECORD_SIZE: usize = LOG_RECORD_FIXED_SIZE + 5 * 2 + 4 * MAX_NAME_SIZE + MAX_TEXT_SIZE;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LogRecord<'a> {
+    /// System tick when the message was received
+    pub tick: u64,
+    pub program_id: u64,
+    pub process_id: u64,
+    pub thread_id: u64,
+    pub severity: u8,
+    pub verbosity: u8,
+    /// Zero when not present
+    pub line_number: u32,
+    pub module_name: &'a [u8],
+    pub thread_name: &'a [u8],
+    pub file_name: &'a [u8],
+    pub function_name: &'a [u8],
+    pub text: &'a [u8],
+}
+
+fn truncate(data: &[u8], max_size: usize) -> &[u8] {
+    &data[..data.len().min(max_size)]
+}
+
+impl<'a> LogRecord<'a> {
+    pub fn from_packet(packet: &LogPacket<'a>, program_id: u64, tick: u64) -> Self {
+        let header = packet.header.unwrap_or(LogPacketHeader {
+            process_id: 0,
+            thread_id: 0,
+            flags: Default::default(),
+            severity: LogSeverity::Info as u8,
+            verbosity: 0,
+            payload_size: 0,
+        });
+
+        Self {
+            tick,
+            program_id,
+            process_id: header.process_id,
+            thread_id: header.thread_id,
+            severity: header.severity,
+            verbosity: header.verbosity,
+            line_number: packet.line_number.unwrap_or(0),
+            module_name: truncate(packet.module_name.unwrap_or(&[]), MAX_NAME_SIZE),
+            thread_name: truncate(packet.thread_name.unwrap_or(&[]), MAX_NAME_SIZE),
+            file_name: truncate(packet.file_name.unwrap_or(&[]), MAX_NAME_SIZE),
+            function_name: truncate(packet.function_name.unwrap_or(&[]), MAX_NAME_SIZE),
+            text: truncate(packet.text_log.unwrap_or(&[]), MAX_TEXT_SIZE),
+        }
+    }
+
+    pub fn get_severity(&self) -> Option<LogSeverity> {
+        LogSeverity::from_u8(self.severity)
+    }
+
+    fn get_strings(&self) -> [&'a [u8]; 5] {
+        [self.module_name, self.thread_name, self.file_name, self.function_name, self.text]
+    }
+
+    pub fn get_encoded_size(&self) -> usize {
+        LOG_RECORD_FIXED_SIZE + self.get_strings().iter().map(|s| 2 + s.len()).sum::<usize>()
+    }
+
+    /// Encodes the record into the start of `out`, returning the encoded size (or `None` if it doesn't fit)
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let size = self.get_encoded_size();
+        if out.len() < size {
+            return None;
+        }
+
+        out[0x0..0x8].copy_from_slice(&self.tick.to_le_bytes());
+        out[0x8..0x10].copy_from_slice(&self.program_id.to_le_bytes());
+        out[0x10..0x18].copy_from_slice(&self.process_id.to_le_bytes());
+        out[0x18..0x20].copy_from_slice(&self.thread_id.to_le_bytes());
+        out[0x20] = self.severity;
+        out[0x21] = self.verbosity;
+        out[0x22..0x24].fill(0);
+        out[0x24..0x28].copy_from_slice(&self.line_number.to_le_bytes());
+
+        let mut offset = LOG_RECORD_FIXED_SIZE;
+        for s in self.get_strings() {
+            out[offset..offset + 2].copy_from_slice(&(s.len() as u16).to_le_bytes());
+            out[offset + 2..offset + 2 + s.len()].copy_from_slice(s);
+            offset += 2 + s.len();
+        }
+        Some(offset)
+    }
+
+    pub fn decode(buf: &'a [u8]) -> Result<Self> {
+        if buf.len() < LOG_RECORD_FIXED_SIZE {
+            return Err(ParseError::UnexpectedEnd);
+        }
+
+        let mut strings: [&'a [u8]; 5] = [&[]; 5];
+        let mut offset = LOG_RECORD_FIXED_SIZE;
+        for s in strings.iter_mut() {
+            let len_buf = buf.get(offset..offset + 2).ok_or(ParseError::UnexpectedEnd)?;
+            let len = u16::from_le_bytes([len_buf[0], len_buf[1]]) as usize;
+            *s = buf
+                .get(offset + 2..offset + 2 + len)
+                .ok_or(ParseError::UnexpectedEnd)?;
+            offset += 2 + len;
+        }
+        let [module_name, thread_name, file_name, function_name, text] = strings;
+
+        Ok(Self {
+            tick: read_u64(&buf[0x0..]),
+            program_id: read_u64(&buf[0x8..]),
+            process_id: read_u64(&buf[0x10..]),
+            thread_id: read_u64(&buf[0x18..]),
+            severity: buf[0x20],
+            verbosity: buf[0x21],
+            line_number: read_u32(&buf[0x24..]),
+            module_name,
+            thread_name,
+            file_name,
+            function_name,
+            text,
+        })
+    }
+}
+
+/// Iterator over the records in a buffer returned by `RecordRing::copy_records`
+pub struct LogRecordIter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> LogRecordIter<'a> {
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for LogRecordIter<'a> {
+    type Item = Result<LogRecord<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let data = self.data;
+        let record = (|| {
+            let size_buf = data.get(..4).ok_or(ParseError::UnexpectedEnd)?;
+            let size = read_u32(size_buf) as usize;
+            let record_buf = data.get(4..4 + size).ok_or(ParseError::UnexpectedEnd)?;
+            self.data = &data[4 + size..];
+            LogRecord::decode(record_buf)
+        })();
+        if record.is_err() {
+            self.data = &[];
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::tests::{make_packet, PROCESS_ID};
+    use crate::packet::{LogDataChunkKey, LogPacketFlags};
+    use crate::ring::RecordRing;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    fn record() -> LogRecord<'static> {
+        LogRecord {
+            tick: 0x1234,
+            program_id: 0x0100000000001000,
+            process_id: PROCESS_ID,
+            thread_id: 7,
+            severity: LogSeverity::Error as u8,
+            verbosity: 1,
+            line_number: 42,
+            module_name: b"module",
+            thread_name: b"thread",
+            file_name: b"main.rs",
+            function_name: b"main",
+            text: b"text",
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let record = record();
+        let mut buf = [0u8; MAX_LOG_RECORD_SIZE];
+        let size = record.encode(&mut buf).unwrap();
+        assert_eq!(size, record.get_encoded_size());
+        assert_eq!(size, LOG_RECORD_FIXED_SIZE + 5 * 2 + 6 + 6 + 7 + 4 + 4);
+        assert_eq!(LogRecord::decode(&buf[..size]), Ok(record));
+
+        assert_eq!(record.encode(&mut buf[..size - 1]), None);
+        assert_eq!(LogRecord::decode(&buf[..size - 1]), Err(ParseError::UnexpectedEnd));
+        assert_eq!(LogRecord::decode(&buf[..LOG_RECORD_FIXED_SIZE - 1]), Err(ParseError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn from_packet_truncates() {
+        let long_name = vec![b'n'; MAX_NAME_SIZE + 1];
+        let long_text = vec![b't'; MAX_TEXT_SIZE + 1];
+        let packet_buf = make_packet(
+            7,
+            LogPacketFlags::HEAD | LogPacketFlags::TAIL,
+            &[(LogDataChunkKey::ModuleName as u8, &long_name), (LogDataChunkKey::TextLog as u8, &long_text)],
+        );
+        let packet = LogPacket::parse(&packet_buf).unwrap();
+        let record = LogRecord::from_packet(&packet, 0x0100000000001000, 0x1234);
+        assert_eq!(record.module_name, &long_name[..MAX_NAME_SIZE]);
+        assert_eq!(record.text, &long_text[..MAX_TEXT_SIZE]);
+        assert_eq!((record.process_id, record.thread_id, record.get_severity()), (PROCESS_ID, 7, Some(LogSeverity::Warn)));
+        assert_eq!(record.line_number, 0);
+        assert!(record.get_encoded_size() <= MAX_LOG_RECORD_SIZE);
+
+        // Without a header the message is taken as info
+        let record = LogRecord::from_packet(&LogPacket::default(), 0, 0);
+        assert_eq!(record.get_severity(), Some(LogSeverity::Info));
+    }
+
+    #[test]
+    fn iterate_ring_records() {
+        let mut ring = RecordRing::<0x200>::new();
+        let mut buf = [0u8; MAX_LOG_RECORD_SIZE];
+        for tick in 0..3 {
+            let record = LogRecord { tick, ..record() };
+            let size = record.encode(&mut buf).unwrap();
+            ring.push(&buf[..size]);
+        }
+
+        let mut out = [0u8; 0x200];
+        let (size, count) = ring.copy_records(&mut out);
+        let ticks: Vec<_> = LogRecordIter::new(&out[..size]).map(|record| record.unwrap().tick).collect();
+        assert_eq!(ticks, vec![0, 1, 2]);
+        assert_eq!(count, 3);
+
+        // A cut record ends the iteration
+        let mut records = LogRecordIter::new(&out[..size - 1]);
+        assert!(records.next().unwrap().is_ok());
+        assert!(records.next().unwrap().is_ok());
+        assert_eq!(records.next(), Some(Err(ParseError::UnexpectedEnd)));
+        assert_eq!(records.next(), None);
+    }
+}
@@ -0,0 +1,244 @@
+//! Fixed-size FIFO of variable-sized records, overwriting the oldest ones when full
+
+pub const RECORD_SIZE_PREFIX_SIZE: usize = 4;
+
+/// Ring of records, each one stored as a `u32` size followed by its data (possibly wrapping around the end of the storage)
+pub struct RecordRing<const N: usize> {
+    storage: [u8; N],
+    // Offset of the oldest record
+    start: usize,
+    used_size: usize,
+    record_count: usize,
+    drop_count: u32,
+}
+
+impl<const N: usize> RecordRing<N> {
+    pub const fn new() -> Self {
+        Self {
+            storage: [0; N],
+            start: 0,
+            used_size: 0,
+            record_count: 0,
+            drop_count: 0,
+        }
+    }
+
+    pub const fn get_record_count(&self) -> usize {
+        self.record_count
+    }
+
+    pub const fn get_used_size(&self) -> usize {
+        self.used_size
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.record_count == 0
+    }
+
+    /// Gets the amount of records which were overwritten (or didn't fit at all) since the last call to this function
+    pub fn take_drop_count(&mut self) -> u32 {
+        core::mem::take(&mut self.drop_count)
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.used_size = 0;
+        self.record_count = 0;
+    }
+
+    fn read_at(&self, offset: usize, out: &mut [u8]) {
+        let offset = offset % N;
+        let first_len = out.len().min(N - offset);
+        out[..first_len].copy_from_slice(&self.storage[offset..offset + first_len]);
+        let rest_len = out.len() - first_len;
+        out[first_len..].copy_from_slice(&self.storage[..rest_len]);
+    }
+
+    fn write_at(&mut self, offset: usize, data: &[u8]) {
+        let offset = offset % N;
+        let first_len = data.len().min(N - offset);
+        self.storage[offset..offset + first_len].copy_from_slice(&data[..first_len]);
+        let rest_len = data.len() - first_len;
+        self.storage[..rest_len].copy_from_slice(&data[first_len..]);
+    }
+
+    fn read_record_size(&self, offset: usize) -> usize {
+        let mut size_buf = [0u8; RECORD_SIZE_PREFIX_SIZE];
+        self.read_at(offset, &mut size_buf);
+        u32::from_le_bytes(size_buf) as usize
+    }
+
+    fn drop_oldest(&mut self) {
+        let size = RECORD_SIZE_PREFIX_SIZE + self.read_record_size(self.start);
+        self.start = (self.start + size) % N;
+        self.used_size -= size;
+        self.record_count -= 1;
+    }
+
+    /// Pushes a record, overwriting the oldest ones until there is enough room for it
+    ///
+    /// Records which can't fit at all are dropped and `false` is returned.
+    pub fn push(&mut self, record: &[u8]) -> bool {
+        self.push_parts(&[record])
+    }
+
+    /// Pushes a single record made of several parts, which saves callers from putting them together in a buffer first
+    pub fn push_parts(&mut self, parts: &[&[u8]]) -> bool {
+        let record_len = parts.iter().map(|part| part.len()).sum::<usize>();
+        let size = RECORD_SIZE_PREFIX_SIZE + record_len;
+        if size > N {
+            self.drop_count = self.drop_count.saturating_add(1);
+            return false;
+        }
+
+        while self.used_size + size > N {
+            self.drop_oldest();
+            self.drop_count = self.drop_count.saturating_add(1);
+        }
+
+        let mut offset = self.start + self.used_size;
+        self.write_at(offset, &(record_len as u32).to_le_bytes());
+        offset += RECORD_SIZE_PREFIX_SIZE;
+        for part in parts {
+            self.write_at(offset, part);
+            offset += part.len();
+        }
+        self.used_size += size;
+        self.record_count += 1;
+        true
+    }
+
+    /// Copies the newest records which fit into `out`, in the same format as they are stored (`u32` size and data)
+    ///
+    /// Returns the copied size and the amount of records copied.
+    pub fn copy_records(&self, out: &mut [u8]) -> (usize, usize) {
+        let mut offset = self.start;
+        let mut size = self.used_size;
+        let mut count = self.record_count;
+        while size > out.len() {
+            let record_size = RECORD_SIZE_PREFIX_SIZE + self.read_record_size(offset);
+            offset += record_size;
+            size -= record_size;
+            count -= 1;
+        }
+
+        self.read_at(offset, &mut out[..size]);
+        (size, count)
+    }
+
+    /// Removes the oldest record, copying as much of it as fits into `out`
+    ///
+    /// Returns the full size of the record, which might be bigger than the copied size.
+    pub fn pop(&mut self, out: &mut [u8]) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let size = self.read_record_size(self.start);
+        let copy_size = size.min(out.len());
+        self.read_at(self.start + RECORD_SIZE_PREFIX_SIZE, &mut out[..copy_size]);
+        self.drop_oldest();
+        Some(size)
+    }
+}
+
+impl<const N: usize> Default for RecordRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_record<const N: usize>(ring: &mut RecordRing<N>) -> Option<([u8; 0x10], usize)> {
+        let mut out = [0u8; 0x10];
+        ring.pop(&mut out).map(|size| (out, size))
+    }
+
+    #[test]
+    fn fifo() {
+        let mut ring = RecordRing::<0x40>::new();
+        assert!(ring.is_empty());
+        assert!(ring.push(b"first"));
+        assert!(ring.push_parts(&[b"sec", b"ond"]));
+        assert_eq!(ring.get_record_count(), 2);
+        assert_eq!(ring.get_used_size(), 2 * RECORD_SIZE_PREFIX_SIZE + 5 + 6);
+
+        let (out, size) = pop_record(&mut ring).unwrap();
+        assert_eq!(&out[..size], b"first");
+        let (out, size) = pop_record(&mut ring).unwrap();
+        assert_eq!(&out[..size], b"second");
+        assert!(pop_record(&mut ring).is_none());
+        assert_eq!(ring.take_drop_count(), 0);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut ring = RecordRing::<0x20>::new();
+        for i in 0..20u8 {
+            // Every record is 0xC bytes, which doesn't divide the storage size
+            assert!(ring.push(&[i; 8]));
+            let (out, size) = pop_record(&mut ring).unwrap();
+            assert_eq!(&out[..size], &[i; 8]);
+        }
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn overwrites_oldest() {
+        let mut ring = RecordRing::<0x20>::new();
+        for i in 0..4u8 {
+            assert!(ring.push(&[i; 8]));
+        }
+        // Only two 0xC-byte records fit
+        assert_eq!(ring.get_record_count(), 2);
+        assert_eq!(ring.take_drop_count(), 2);
+        assert_eq!(ring.take_drop_count(), 0);
+        assert_eq!(&pop_record(&mut ring).unwrap().0[..8], &[2; 8]);
+        assert_eq!(&pop_record(&mut ring).unwrap().0[..8], &[3; 8]);
+    }
+
+    #[test]
+    fn too_big_records_are_dropped() {
+        let mut ring = RecordRing::<0x20>::new();
+        assert!(ring.push(b"kept"));
+        assert!(!ring.push(&[0; 0x20 - RECORD_SIZE_PREFIX_SIZE + 1]));
+        assert_eq!(ring.take_drop_count(), 1);
+        assert_eq!(ring.get_record_count(), 1);
+        assert!(ring.push(&[0; 0x20 - RECORD_SIZE_PREFIX_SIZE - 8]));
+    }
+
+    #[test]
+    fn pop_reports_full_size() {
+        let mut ring = RecordRing::<0x40>::new();
+        ring.push(&[7; 0x18]);
+        let (out, size) = pop_record(&mut ring).unwrap();
+        assert_eq!(size, 0x18);
+        assert_eq!(out, [7; 0x10]);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn copy_newest_records() {
+        let mut ring = RecordRing::<0x40>::new();
+        for i in 0..3u8 {
+            ring.push(&[i; 4]);
+        }
+
+        let mut out = [0u8; 0x40];
+        assert_eq!(ring.copy_records(&mut out), (3 * 8, 3));
+        // Only the newest ones which fit whole
+        let mut out = [0u8; 0x14];
+        assert_eq!(ring.copy_records(&mut out), (0x10, 2));
+        assert_eq!(&out[..0x10], &[4, 0, 0, 0, 1, 1, 1, 1, 4, 0, 0, 0, 2, 2, 2, 2]);
+        assert_eq!(ring.copy_records(&mut []), (0, 0));
+        // Copying leaves the records there
+        assert_eq!(ring.get_record_count(), 3);
+
+        ring.clear();
+        assert!(ring.is_empty());
+        assert_eq!(ring.copy_records(&mut out), (0, 0));
+    }
+}
@@ -0,0 +1,29 @@
+//! Protocol of the live log stream served over TCP by the `lm` sysmodule example (when built with its `tcp-stream` feature)
+//!
+//! Right after connecting, clients send a single byte selecting the format of the stream (see `StreamFormat`).
+//! If nothing is received within `HANDSHAKE_TIMEOUT_MS`, JSON lines are sent, so plain tools like `nc` work too.
+
+pub const STREAM_PORT: u16 = 5002;
+
+pub const HANDSHAKE_TIMEOUT_MS: u64 = 1000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum StreamFormat {
+    /// Every packet as a line with a `PacketJson` object (which includes the program ID)
+    JsonLines = b'j',
+    /// Same framing as binlog files: a `LogBinaryHeader` followed by records, each being a `LogRecordHeader` and a raw packet
+    ///
+    /// Like in binlog files, the program ID is not part of the records.
+    BinLog = b'b',
+}
+
+impl StreamFormat {
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            b'j' => Some(Self::JsonLines),
+            b'b' => Some(Self::BinLog),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Conversion of system ticks to readable timestamps
+//!
+//! Log packets may carry the sender's user system clock (POSIX seconds), so pairing one of those with the tick it was received at
+//! is enough to turn any later tick into wall-clock time.
+
+use core::fmt;
+
+pub fn ticks_to_millis(ticks: u64, tick_frequency: u64) -> u64 {
+    if tick_frequency == 0 {
+        return 0;
+    }
+    u64::try_from((ticks as u128) * 1000 / (tick_frequency as u128)).unwrap_or(u64::MAX)
+}
+
+/// Gets the (year, month, day) of the given amount of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Formats as `YYYY-MM-DD hh:mm:ss.mmm` (UTC)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UnixTime {
+    pub millis: u64,
+}
+
+impl fmt::Display for UnixTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.millis / 1000;
+        let (year, month, day) = civil_from_days((secs / 86400) as i64);
+        let day_secs = secs % 86400;
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+            year,
+            month,
+            day,
+            day_secs / 3600,
+            (day_secs / 60) % 60,
+            day_secs % 60,
+            self.millis % 1000
+        )
+    }
+}
+
+/// Formats as `+hh:mm:ss.mmm`, for when there is no wall-clock reference yet
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Uptime {
+    pub millis: u64,
+}
+
+impl fmt::Display for Uptime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.millis / 1000;
+        write!(f, "+{:02}:{:02}:{:02}.{:03}", secs / 3600, (secs / 60) % 60, secs % 60, self.millis % 1000)
+    }
+}
+
+/// Pairs a POSIX time with the tick it was observed at
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ClockBase {
+    pub tick: u64,
+    pub unix_secs: u64,
+}
+
+/// Formats a tick as wall-clock time if a clock base is known, or as uptime otherwise
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TickTime {
+    pub tick: u64,
+    pub tick_frequency: u64,
+    pub base: Option<ClockBase>,
+}
+
+impl TickTime {
+    pub fn get_uptime(&self) -> Uptime {
+        Uptime { millis: ticks_to_millis(self.tick, self.tick_frequency) }
+    }
+}
+
+impl fmt::Display for TickTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.base {
+            Some(base) => {
+                // Ticks before the base was taken are shown as the base time itself, they are off by very little anyway
+                let elapsed_millis = ticks_to_millis(self.tick.saturating_sub(base.tick), self.tick_frequency);
+                match base.unix_secs.checked_mul(1000).and_then(|base_millis| base_millis.checked_add(elapsed_millis)) {
+                    Some(millis) => UnixTime { millis }.fmt(f),
+                    // Only with a bogus clock value from a packet, so just show the uptime
+                    None => self.get_uptime().fmt(f),
+                }
+            }
+            None => self.get_uptime().fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::format;
+
+    const TICK_FREQUENCY: u64 = 19_200_000;
+
+    #[test]
+    fn ticks_to_millis_saturates() {
+        assert_eq!(ticks_to_millis(TICK_FREQUENCY * 3, TICK_FREQUENCY), 3000);
+        assert_eq!(ticks_to_millis(1234, 0), 0);
+        assert_eq!(ticks_to_millis(u64::MAX, 1), u64::MAX);
+    }
+
+    #[test]
+    fn tick_time() {
+        let base = ClockBase { tick: TICK_FREQUENCY, unix_secs: 1_700_000_000 };
+        let time = TickTime { tick: TICK_FREQUENCY * 62 + TICK_FREQUENCY / 2, tick_frequency: TICK_FREQUENCY, base: None };
+        assert_eq!(format!("{}", time), "+00:01:02.500");
+        assert_eq!(format!("{}", TickTime { base: Some(base), ..time }), "2023-11-14 22:14:21.500");
+        // Ticks before the base show the base time
+        assert_eq!(format!("{}", TickTime { tick: 0, base: Some(base), ..time }), "2023-11-14 22:13:20.000");
+    }
+
+    #[test]
+    fn bogus_clock_base_shows_uptime() {
+        let time = TickTime { tick: TICK_FREQUENCY * 62 + TICK_FREQUENCY / 2, tick_frequency: TICK_FREQUENCY, base: None };
+        for unix_secs in [u64::MAX, u64::MAX / 1000] {
+            let base = ClockBase { tick: 0, unix_secs };
+            assert_eq!(format!("{}", TickTime { base: Some(base), ..time }), "+00:01:02.500");
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Per-program filtering rules for the `lm` sysmodule example, loaded from `sdmc:/config/lm/filter.toml`
+//!
+//! The config looks like this, where every key is optional:
+//!
+//! ```toml
+//! # "allow" logs every program unless denied below, "deny" only logs the programs allowed below
+//! default_action = "allow"
+//! # Messages below this severity ("trace", "info", "warn", "error" or "fatal") are dropped
+//! min_severity = "trace"
+//! # Minimum severity of the messages lm logs about itself in its self-log
+//! self_min_severity = "info"
+//!
+//! [program.0x0100000000001000]
+//! action = "deny"
+//!
+//! [program.0x010000000000100D]
+//! action = "allow"
+//! min_severity = "warn"
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use sd_config::{Document, Table};
+
+pub const FILTER_CONFIG_PATH: &str = "sdmc:/config/lm/filter.toml";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FilterAction {
+    Allow,
+    Deny,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterError {
+    Parse(sd_config::ParseError),
+    /// A table other than `[program.<hex program ID>]` was found
+    UnknownTable,
+    InvalidProgramId,
+    /// A key had an unexpected type or value
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "{}", e),
+            Self::UnknownTable => write!(f, "unknown table, expected [program.<program ID>]"),
+            Self::InvalidProgramId => write!(f, "invalid program ID"),
+            Self::InvalidValue(key) => write!(f, "invalid value for \"{}\"", key),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, FilterError>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProgramRule {
+    pub program_id: u64,
+    /// Falls back to the default action when not set
+    pub action: Option<FilterAction>,
+    /// Falls back to the default minimum severity when not set
+    pub min_severity: Option<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterConfig {
+    pub default_action: FilterAction,
+    pub default_min_severity: u8,
+    /// Falls back to the self-logger's built-in minimum severity when not set
+    pub self_min_severity: Option<u8>,
+    pub rules: Vec<ProgramRule>,
+}
+
+// Same values as the severities in log packet headers
+fn parse_severity(name: &str) -> Option<u8> {
+    match name {
+        "trace" => Some(0),
+        "info" => Some(1),
+        "warn" => Some(2),
+        "error" => Some(3),
+        "fatal" => Some(4),
+        _ => None,
+    }
+}
+
+fn parse_action(name: &str) -> Option<FilterAction> {
+    match name {
+        "allow" => Some(FilterAction::Allow),
+        "deny" => Some(FilterAction::Deny),
+        _ => None,
+    }
+}
+
+fn get_action(table: &Table) -> Result<Option<FilterAction>> {
+    table
+        .get("action")
+        .map(|value| value.as_str().and_then(parse_action).ok_or(FilterError::InvalidValue("action")))
+        .transpose()
+}
+
+fn get_severity(table: &Table, key: &'static str) -> Result<Option<u8>> {
+    table
+        .get(key)
+        .map(|value| value.as_str().and_then(parse_severity).ok_or(FilterError::InvalidValue(key)))
+        .transpose()
+}
+
+impl FilterConfig {
+    /// Config letting everything through, used when there's no config file
+    pub const fn new() -> Self {
+        Self {
+            default_action: FilterAction::Allow,
+            default_min_severity: 0,
+            self_min_severity: None,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<Self> {
+        let doc = Document::parse(input).map_err(FilterError::Parse)?;
+
+        let root = doc.get_root();
+        let default_action = root
+            .get("default_action")
+            .map(|value| value.as_str().and_then(parse_action).ok_or(FilterError::InvalidValue("default_action")))
+            .transpose()?
+            .unwrap_or(FilterAction::Allow);
+        let default_min_severity = get_severity(root, "min_severity")?.unwrap_or(0);
+        let self_min_severity = get_severity(root, "self_min_severity")?;
+
+        if doc.tables.iter().skip(1).any(|table| !table.name.starts_with("program.")) {
+            return Err(FilterError::UnknownTable);
+        }
+
+        let mut rules = Vec::new();
+        for (program_id_str, table) in doc.iter_subtables("program") {
+            let program_id = program_id_str
+                .strip_prefix("0x")
+                .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                .ok_or(FilterError::InvalidProgramId)?;
+            rules.push(ProgramRule {
+                program_id,
+                action: get_action(table)?,
+                min_severity: get_severity(table, "min_severity")?,
+            });
+        }
+
+        Ok(Self {
+            default_action,
+            default_min_severity,
+            self_min_severity,
+            rules,
+        })
+    }
+
+    pub fn get_rule(&self, program_id: u64) -> Option<&ProgramRule> {
+        // Later rules win, like later tables overriding earlier ones (prepo-policy rules are patterns which overlap on purpose,
+        // so the first match wins there instead)
+        self.rules.iter().rev().find(|rule| rule.program_id == program_id)
+    }
+
+    /// Whether nothing from the given program gets logged at all
+    pub fn is_program_denied(&self, program_id: u64) -> bool {
+        let action = self
+            .get_rule(program_id)
+            .and_then(|rule| rule.action)
+            .unwrap_or(self.default_action);
+        action == FilterAction::Deny
+    }
+
+    /// Whether a message with the given severity from the given program gets logged
+    pub fn should_log(&self, program_id: u64, severity: u8) -> bool {
+        if self.is_program_denied(program_id) {
+            return false;
+        }
+
+        let min_severity = self
+            .get_rule(program_id)
+            .and_then(|rule| rule.min_severity)
+            .unwrap_or(self.default_min_severity);
+        severity >= min_severity
+    }
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP_PROGRAM_ID: u64 = 0x0100000000001000;
+    const OTHER_PROGRAM_ID: u64 = 0x010000000000100D;
+
+    const TRACE: u8 = 0;
+    const INFO: u8 = 1;
+    const WARN: u8 = 2;
+    const ERROR: u8 = 3;
+    const FATAL: u8 = 4;
+
+    fn parse(input: &str) -> FilterConfig {
+        FilterConfig::parse(input).unwrap()
+    }
+
+    #[test]
+    fn default_config_logs_everything() {
+        for config in [FilterConfig::new(), parse("")] {
+            assert_eq!(config, FilterConfig::default());
+            for severity in [TRACE, INFO, WARN, ERROR, FATAL] {
+                assert!(config.should_log(APP_PROGRAM_ID, severity));
+            }
+            assert!(!config.is_program_denied(APP_PROGRAM_ID));
+            assert_eq!(config.self_min_severity, None);
+        }
+    }
+
+    #[test]
+    fn default_rule_applies_to_programs_without_rules() {
+        let config = parse(
+            r#"
+            default_action = "deny"
+
+            [program.0x0100000000001000]
+            action = "allow"
+            "#,
+        );
+        assert!(config.should_log(APP_PROGRAM_ID, TRACE));
+        assert!(config.is_program_denied(OTHER_PROGRAM_ID));
+        assert!(!config.should_log(OTHER_PROGRAM_ID, FATAL));
+
+        let config = parse(
+            r#"
+            min_severity = "warn"
+
+            [program.0x0100000000001000]
+            action = "allow"
+            "#,
+        );
+        assert!(!config.should_log(OTHER_PROGRAM_ID, INFO));
+        assert!(config.should_log(OTHER_PROGRAM_ID, WARN));
+    }
+
+    #[test]
+    fn program_rules_override_defaults() {
+        let config = parse(
+            r#"
+            default_action = "allow"
+            min_severity = "error"
+
+            [program.0x0100000000001000]
+            action = "deny"
+
+            [program.0x010000000000100D]
+            min_severity = "info"
+            "#,
+        );
+
+        // The program action wins over the default action, whatever the severity
+        assert!(config.is_program_denied(APP_PROGRAM_ID));
+        assert!(!config.should_log(APP_PROGRAM_ID, FATAL));
+
+        // The program minimum severity wins over the default one, in both directions
+        assert!(!config.should_log(OTHER_PROGRAM_ID, TRACE));
+        assert!(config.should_log(OTHER_PROGRAM_ID, INFO));
+        assert!(!config.should_log(0x0100000000002000, WARN));
+        assert!(config.should_log(0x0100000000002000, ERROR));
+    }
+
+    #[test]
+    fn unset_rule_keys_fall_back_to_defaults() {
+        let config = parse(
+            r#"
+            default_action = "deny"
+            min_severity = "warn"
+
+            [program.0x0100000000001000]
+            min_severity = "trace"
+
+            [program.0x010000000000100D]
+            action = "allow"
+            "#,
+        );
+
+        // Only the severity is set, so the default deny still applies
+        assert!(!config.should_log(APP_PROGRAM_ID, FATAL));
+        // Only the action is set, so the default minimum severity still applies
+        assert!(!config.should_log(OTHER_PROGRAM_ID, INFO));
+        assert!(config.should_log(OTHER_PROGRAM_ID, WARN));
+    }
+
+    #[test]
+    fn deny_wins_over_severity() {
+        let config = parse(
+            r#"
+            [program.0x0100000000001000]
+            action = "deny"
+            min_severity = "trace"
+            "#,
+        );
+        for severity in [TRACE, INFO, WARN, ERROR, FATAL] {
+            assert!(!config.should_log(APP_PROGRAM_ID, severity));
+        }
+    }
+
+    #[test]
+    fn later_rules_win() {
+        let mut config = FilterConfig::new();
+        config.rules.push(ProgramRule {
+            program_id: APP_PROGRAM_ID,
+            action: Some(FilterAction::Deny),
+            min_severity: None,
+        });
+        config.rules.push(ProgramRule {
+            program_id: APP_PROGRAM_ID,
+            action: Some(FilterAction::Allow),
+            min_severity: Some(ERROR),
+        });
+        assert!(!config.is_program_denied(APP_PROGRAM_ID));
+        assert!(!config.should_log(APP_PROGRAM_ID, WARN));
+        assert!(config.should_log(APP_PROGRAM_ID, ERROR));
+    }
+
+    #[test]
+    fn self_min_severity() {
+        let config = parse(
+            r#"
+            min_severity = "error"
+            self_min_severity = "warn"
+            "#,
+        );
+        assert_eq!(config.default_min_severity, ERROR);
+        assert_eq!(config.self_min_severity, Some(WARN));
+    }
+
+    #[test]
+    fn invalid_configs() {
+        assert_eq!(FilterConfig::parse("default_action = \"maybe\""), Err(FilterError::InvalidValue("default_action")));
+        assert_eq!(FilterConfig::parse("min_severity = 2"), Err(FilterError::InvalidValue("min_severity")));
+        assert_eq!(FilterConfig::parse("[other]"), Err(FilterError::UnknownTable));
+        assert_eq!(FilterConfig::parse("[program.1000]"), Err(FilterError::InvalidProgramId));
+        assert_eq!(
+            FilterConfig::parse("[program.0x0100000000001000]\naction = \"block\""),
+            Err(FilterError::InvalidValue("action"))
+        );
+        assert_eq!(
+            FilterConfig::parse("[program.0x0100000000001000]\nmin_severity = \"debug\""),
+            Err(FilterError::InvalidValue("min_severity"))
+        );
+    }
+}
@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec;
+use core::fmt::Write;
+use core::panic;
+
+use nx::diag::abort;
+use nx::diag::log::lm::LmLogger;
+use nx::fs;
+use nx::ipc::sf;
+use nx::service;
+use nx::svc;
+use nx::util;
+
+use lm_binlog::fmt::RecordText;
+use lm_binlog::record::LogRecordIter;
+use lm_ipc::{ILogHistoryClient, LogHistory, LOG_HISTORY_SIZE};
+
+nx::rrt0_define_module_name!("lm-history-client");
+
+const HISTORY_LOG_PATH: &str = "sdmc:/lm-history.log";
+
+#[no_mangle]
+pub fn initialize_heap(hbl_heap: util::PointerAndSize) -> util::PointerAndSize {
+    if hbl_heap.is_valid() {
+        hbl_heap
+    } else {
+        let heap_size: usize = 0x10000000;
+        let heap_address = svc::set_heap_size(heap_size).unwrap();
+        util::PointerAndSize::new(heap_address, heap_size)
+    }
+}
+
+#[no_mangle]
+pub fn main() {
+    fs::initialize_fspsrv_session().expect("Error starting filesystem services");
+    fs::mount_sd_card("sdmc").expect("Failed to mount sd card");
+
+    let mut history = service::new_service_object::<LogHistory>().expect("Error opening lm history service (is the lm example running?)");
+
+    // Dump the whole history buffer as text, without going through the (possibly slow or disabled) SD card logging
+    let mut history_buf = vec![0u8; LOG_HISTORY_SIZE];
+    let (history_size, record_count) = history
+        .get_history(sf::OutMapAliasBuffer::from_mut_array(&mut history_buf))
+        .unwrap();
+
+    let _ = fs::remove_file(HISTORY_LOG_PATH);
+    let mut history_log = fs::open_file(
+        HISTORY_LOG_PATH,
+        fs::FileOpenOption::Create() | fs::FileOpenOption::Write() | fs::FileOpenOption::Append(),
+    )
+    .unwrap();
+
+    let _ = writeln!(history_log, "{} records ({} bytes)", record_count, history_size);
+    for record in LogRecordIter::new(&history_buf[..history_size as usize]) {
+        match record {
+            Ok(record) => {
+                let _ = writeln!(history_log, "{}", RecordText(&record));
+            }
+            Err(e) => {
+                let _ = writeln!(history_log, "Invalid record: {}", e);
+                break;
+            }
+        }
+    }
+
+    fs::unmount_all();
+}
+
+#[panic_handler]
+fn panic_handler(info: &panic::PanicInfo) -> ! {
+    util::simple_panic_handler::<LmLogger>(info, abort::AbortLevel::FatalThrow())
+}
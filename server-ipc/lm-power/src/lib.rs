@@ -0,0 +1,253 @@
+//! Sleep/wake handling of the `lm` sysmodule example: log files must be flushed and closed before the system sleeps, and
+//! reopened once it wakes up
+//!
+//! The state machine only talks to psc and to the log files through the traits below, the sysmodule implements them on top
+//! of its `IPmModule` and its writers.
+
+#![no_std]
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PowerState {
+    Awake,
+    Sleeping,
+}
+
+/// Source of power state change requests, like a psc `IPmModule`
+pub trait PmModule {
+    type State;
+    type Error;
+
+    /// Waits for the next request and returns the requested state
+    fn wait_request(&mut self) -> Result<Self::State, Self::Error>;
+
+    fn get_power_state(state: &Self::State) -> PowerState;
+
+    /// Tells psc we are done handling the requested state, the system won't go on with the transition until this happens
+    fn acknowledge(&mut self, state: Self::State) -> Result<(), Self::Error>;
+}
+
+/// Whatever needs to be made safe before sleeping
+pub trait LogStorage {
+    /// Writes out anything not written yet
+    fn flush(&mut self);
+
+    /// Closes every open file, nothing may be written (or reopened) until `reopen` is called
+    fn close(&mut self);
+
+    /// Reopens the files closed by `close` and allows writes again
+    fn reopen(&mut self);
+}
+
+pub struct PowerStateMachine {
+    state: PowerState,
+}
+
+impl PowerStateMachine {
+    pub const fn new() -> Self {
+        Self {
+            state: PowerState::Awake,
+        }
+    }
+
+    pub const fn get_state(&self) -> PowerState {
+        self.state
+    }
+
+    /// Handles a single request: closes or reopens the storage if the power state changed, and then acknowledges it
+    pub fn handle_request<M: PmModule, S: LogStorage>(&mut self, module: &mut M, storage: &mut S) -> Result<(), M::Error> {
+        let state = module.wait_request()?;
+        let new_power_state = M::get_power_state(&state);
+
+        match (self.state, new_power_state) {
+            (PowerState::Awake, PowerState::Sleeping) => {
+                storage.flush();
+                storage.close();
+            }
+            (PowerState::Sleeping, PowerState::Awake) => storage.reopen(),
+            // Transitions between awake (or between sleeping) states don't matter to us
+            _ => {}
+        }
+        self.state = new_power_state;
+
+        module.acknowledge(state)
+    }
+}
+
+impl Default for PowerStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use super::*;
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    // Same states as psc's ones, in the order the system goes through them
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    enum MockState {
+        FullAwake,
+        MinimumAwake,
+        SleepReady,
+        EssentialServicesSleepReady,
+        EssentialServicesAwake,
+        ShutdownReady,
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    enum Op {
+        Flush,
+        Close,
+        Reopen,
+        Acknowledge(MockState),
+    }
+
+    type OpLog = Rc<RefCell<Vec<Op>>>;
+
+    struct MockModule {
+        requests: Vec<MockState>,
+        ops: OpLog,
+    }
+
+    impl PmModule for MockModule {
+        type State = MockState;
+        type Error = ();
+
+        fn wait_request(&mut self) -> Result<MockState, ()> {
+            if self.requests.is_empty() {
+                Err(())
+            } else {
+                Ok(self.requests.remove(0))
+            }
+        }
+
+        fn get_power_state(state: &MockState) -> PowerState {
+            match state {
+                MockState::FullAwake | MockState::MinimumAwake | MockState::EssentialServicesAwake => PowerState::Awake,
+                _ => PowerState::Sleeping,
+            }
+        }
+
+        fn acknowledge(&mut self, state: MockState) -> Result<(), ()> {
+            self.ops.borrow_mut().push(Op::Acknowledge(state));
+            Ok(())
+        }
+    }
+
+    struct MockStorage {
+        ops: OpLog,
+        open: bool,
+    }
+
+    impl LogStorage for MockStorage {
+        fn flush(&mut self) {
+            assert!(self.open, "flushing closed files");
+            self.ops.borrow_mut().push(Op::Flush);
+        }
+
+        fn close(&mut self) {
+            assert!(self.open, "closing twice");
+            self.open = false;
+            self.ops.borrow_mut().push(Op::Close);
+        }
+
+        fn reopen(&mut self) {
+            assert!(!self.open, "reopening open files");
+            self.open = true;
+            self.ops.borrow_mut().push(Op::Reopen);
+        }
+    }
+
+    // Runs every request through a fresh state machine, returning what got done
+    fn run(requests: &[MockState]) -> Vec<Op> {
+        let ops = OpLog::default();
+        let mut module = MockModule {
+            requests: requests.to_vec(),
+            ops: ops.clone(),
+        };
+        let mut storage = MockStorage { ops: ops.clone(), open: true };
+
+        let mut state_machine = PowerStateMachine::new();
+        for _ in requests {
+            state_machine.handle_request(&mut module, &mut storage).unwrap();
+        }
+        assert_eq!(state_machine.handle_request(&mut module, &mut storage), Err(()));
+
+        ops.take()
+    }
+
+    #[test]
+    fn awake_states_touch_nothing() {
+        for state in [MockState::FullAwake, MockState::MinimumAwake, MockState::EssentialServicesAwake] {
+            assert_eq!(run(&[state]), vec![Op::Acknowledge(state)]);
+        }
+    }
+
+    #[test]
+    fn sleeping_states_flush_then_close_before_acknowledging() {
+        for state in [MockState::SleepReady, MockState::EssentialServicesSleepReady, MockState::ShutdownReady] {
+            assert_eq!(run(&[state]), vec![Op::Flush, Op::Close, Op::Acknowledge(state)]);
+        }
+    }
+
+    #[test]
+    fn full_sleep_cycle() {
+        let ops = run(&[
+            MockState::FullAwake,
+            MockState::MinimumAwake,
+            MockState::SleepReady,
+            MockState::EssentialServicesSleepReady,
+            MockState::EssentialServicesAwake,
+            MockState::MinimumAwake,
+            MockState::FullAwake,
+        ]);
+        assert_eq!(ops, vec![
+            Op::Acknowledge(MockState::FullAwake),
+            Op::Acknowledge(MockState::MinimumAwake),
+            Op::Flush,
+            Op::Close,
+            Op::Acknowledge(MockState::SleepReady),
+            // Still sleeping, the files stay closed
+            Op::Acknowledge(MockState::EssentialServicesSleepReady),
+            Op::Reopen,
+            Op::Acknowledge(MockState::EssentialServicesAwake),
+            Op::Acknowledge(MockState::MinimumAwake),
+            Op::Acknowledge(MockState::FullAwake),
+        ]);
+    }
+
+    #[test]
+    fn repeated_sleep_cycles() {
+        let ops = run(&[MockState::SleepReady, MockState::FullAwake, MockState::SleepReady, MockState::FullAwake]);
+        assert_eq!(ops, vec![
+            Op::Flush,
+            Op::Close,
+            Op::Acknowledge(MockState::SleepReady),
+            Op::Reopen,
+            Op::Acknowledge(MockState::FullAwake),
+            Op::Flush,
+            Op::Close,
+            Op::Acknowledge(MockState::SleepReady),
+            Op::Reopen,
+            Op::Acknowledge(MockState::FullAwake),
+        ]);
+    }
+
+    #[test]
+    fn failed_request_is_not_acknowledged() {
+        let ops = OpLog::default();
+        let mut module = MockModule { requests: Vec::new(), ops: ops.clone() };
+        let mut storage = MockStorage { ops: ops.clone(), open: true };
+
+        let mut state_machine = PowerStateMachine::new();
+        assert_eq!(state_machine.handle_request(&mut module, &mut storage), Err(()));
+        assert_eq!(state_machine.get_state(), PowerState::Awake);
+        assert!(ops.borrow().is_empty());
+    }
+}
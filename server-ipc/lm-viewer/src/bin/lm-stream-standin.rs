@@ -0,0 +1,120 @@
+//! Local stand-in for the `lm` log stream, sending made-up logs in the same formats so `lm-viewer` can be tried without a console
+//!
+//! Run it with `cargo run --bin lm-stream-standin -- [port]` and point `lm-viewer` to `127.0.0.1`.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lm_binlog::binlog::{LogBinaryHeader, LogRecordHeader};
+use lm_binlog::fmt::PacketJson;
+use lm_binlog::packet::{LogDataChunkKey, LogPacket, LogPacketFlags, LogPacketHeader, LogSeverity, LOG_PACKET_HEADER_SIZE};
+use lm_binlog::stream::{StreamFormat, HANDSHAKE_TIMEOUT_MS, STREAM_PORT};
+
+const SAMPLE_PROGRAM_ID: u64 = 0x0100000000001000;
+// Same frequency as the console's system tick
+const TICK_FREQUENCY: u64 = 19_200_000;
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, key: LogDataChunkKey, data: &[u8]) {
+    write_uleb128(out, key as usize);
+    write_uleb128(out, data.len());
+    out.extend_from_slice(data);
+}
+
+fn make_sample_packet(index: u32) -> Vec<u8> {
+    let severities = [LogSeverity::Trace, LogSeverity::Info, LogSeverity::Warn, LogSeverity::Error];
+
+    let mut payload = Vec::new();
+    write_chunk(&mut payload, LogDataChunkKey::TextLog, format!("Sample message #{}\n", index).as_bytes());
+    write_chunk(&mut payload, LogDataChunkKey::LineNumber, &(100 + index).to_le_bytes());
+    write_chunk(&mut payload, LogDataChunkKey::FileName, b"standin.cpp");
+    write_chunk(&mut payload, LogDataChunkKey::FunctionName, b"SendSamples");
+    write_chunk(&mut payload, LogDataChunkKey::ModuleName, b"standin");
+
+    let header = LogPacketHeader {
+        process_id: 0x80,
+        thread_id: 0x1234,
+        flags: LogPacketFlags(LogPacketFlags::HEAD | LogPacketFlags::TAIL | LogPacketFlags::LITTLE_ENDIAN),
+        severity: severities[index as usize % severities.len()] as u8,
+        verbosity: 0,
+        payload_size: payload.len() as u32,
+    };
+    let mut header_buf = [0u8; LOG_PACKET_HEADER_SIZE];
+    header.write_to(&mut header_buf);
+
+    let mut packet_buf = header_buf.to_vec();
+    packet_buf.extend_from_slice(&payload);
+    packet_buf
+}
+
+fn read_format(stream: &mut TcpStream) -> io::Result<StreamFormat> {
+    stream.set_read_timeout(Some(Duration::from_millis(HANDSHAKE_TIMEOUT_MS)))?;
+    let mut format_buf = [0u8; 1];
+    let format = match stream.read(&mut format_buf) {
+        Ok(1) => StreamFormat::from_u8(format_buf[0]).unwrap_or(StreamFormat::JsonLines),
+        _ => StreamFormat::JsonLines,
+    };
+    stream.set_read_timeout(None)?;
+    Ok(format)
+}
+
+fn serve_client(mut stream: TcpStream, start: Instant) -> io::Result<()> {
+    let format = read_format(&mut stream)?;
+    eprintln!("Serving {:?} to {}", format, stream.peer_addr()?);
+
+    if format == StreamFormat::BinLog {
+        stream.write_all(&LogBinaryHeader::current().to_bytes())?;
+    }
+
+    for index in 0.. {
+        let packet_buf = make_sample_packet(index);
+        let tick = (start.elapsed().as_nanos() * TICK_FREQUENCY as u128 / 1_000_000_000) as u64;
+        match format {
+            StreamFormat::JsonLines => {
+                // Always valid, we just made it
+                let packet = LogPacket::parse(&packet_buf).unwrap();
+                writeln!(stream, "{}", PacketJson { packet: &packet, program_id: Some(SAMPLE_PROGRAM_ID), tick: Some(tick) })?;
+            }
+            StreamFormat::BinLog => {
+                stream.write_all(&LogRecordHeader::new(packet_buf.len() as u32, tick).to_bytes())?;
+                stream.write_all(&packet_buf)?;
+            }
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let port = match std::env::args().nth(1) {
+        Some(port) => port.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid port"))?,
+        None => STREAM_PORT,
+    };
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+
+    let start = Instant::now();
+    for stream in listener.incoming() {
+        let stream = stream?;
+        thread::spawn(move || {
+            if let Err(e) = serve_client(stream, start) {
+                eprintln!("Client error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
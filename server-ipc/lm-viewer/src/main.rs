@@ -0,0 +1,113 @@
+//! Host tool watching the live log stream of the `lm` sysmodule example (built with its `tcp-stream` feature)
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::process::ExitCode;
+
+use lm_binlog::binlog::{LogBinaryHeader, LogRecordHeader, LOG_BINARY_HEADER_SIZE, LOG_RECORD_HEADER_SIZE};
+use lm_binlog::fmt::PacketText;
+use lm_binlog::packet::LogPacket;
+use lm_binlog::stream::{StreamFormat, STREAM_PORT};
+
+fn usage() -> ExitCode {
+    eprintln!("Usage: lm-viewer [--json] <console address>[:port]");
+    eprintln!("  By default logs are received in binlog framing and printed as text, --json prints the JSON lines as received");
+    ExitCode::FAILURE
+}
+
+// Decodes binlog framing as it arrives, printing every complete record
+struct BinLogPrinter {
+    data: Vec<u8>,
+    got_header: bool,
+}
+
+impl BinLogPrinter {
+    fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            got_header: false,
+        }
+    }
+
+    fn push(&mut self, received: &[u8], out: &mut impl Write) -> io::Result<()> {
+        self.data.extend_from_slice(received);
+
+        if !self.got_header {
+            if self.data.len() < LOG_BINARY_HEADER_SIZE {
+                return Ok(());
+            }
+            LogBinaryHeader::parse(&self.data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            self.data.drain(..LOG_BINARY_HEADER_SIZE);
+            self.got_header = true;
+        }
+
+        let mut offset = 0;
+        while let Ok(record_header) = LogRecordHeader::parse(&self.data[offset..]) {
+            let record_end = offset + LOG_RECORD_HEADER_SIZE + record_header.packet_size as usize;
+            let Some(packet_buf) = self.data.get(offset + LOG_RECORD_HEADER_SIZE..record_end) else {
+                break;
+            };
+
+            match LogPacket::parse(packet_buf) {
+                Ok(packet) => writeln!(out, "{}", PacketText { packet: &packet, program_id: None, tick: Some(record_header.tick) })?,
+                Err(e) => eprintln!("Invalid packet: {}", e),
+            }
+            offset = record_end;
+        }
+        self.data.drain(..offset);
+        out.flush()
+    }
+}
+
+fn run(address: &str, format: StreamFormat) -> io::Result<()> {
+    let mut stream = TcpStream::connect(address)?;
+    stream.write_all(&[format as u8])?;
+    eprintln!("Connected to {}", address);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut printer = BinLogPrinter::new();
+    let mut recv_buf = [0u8; 0x1000];
+    loop {
+        let read_len = stream.read(&mut recv_buf)?;
+        if read_len == 0 {
+            eprintln!("Connection closed");
+            return Ok(());
+        }
+
+        match format {
+            StreamFormat::JsonLines => {
+                out.write_all(&recv_buf[..read_len])?;
+                out.flush()?;
+            }
+            StreamFormat::BinLog => printer.push(&recv_buf[..read_len], &mut out)?,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let mut format = StreamFormat::BinLog;
+    let mut address = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => format = StreamFormat::JsonLines,
+            "-h" | "--help" => return usage(),
+            _ if address.is_none() => address = Some(arg),
+            _ => return usage(),
+        }
+    }
+    let Some(mut address) = address else {
+        return usage();
+    };
+    if !address.contains(':') {
+        address = format!("{}:{}", address, STREAM_PORT);
+    }
+
+    match run(&address, format) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}: {}", address, e);
+            ExitCode::FAILURE
+        }
+    }
+}
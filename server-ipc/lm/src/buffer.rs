@@ -0,0 +1,33 @@
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+
+use nx::sync::Mutex;
+
+use lm_binlog::ring::RecordRing;
+
+// Kept in a static (instead of our tiny heap), holding the most recent packets
+const LOG_BUFFER_SIZE: usize = 0x2000;
+
+static G_LOG_BUFFER: Mutex<RecordRing<LOG_BUFFER_SIZE>> = Mutex::new(RecordRing::new());
+
+// Controlled through "lm:get", like the official log buffer only gets filled while someone is capturing logs
+static G_CAPTURE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_capture_enabled(enabled: bool) {
+    G_CAPTURE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn push_packet(packet_buf: &[u8]) {
+    if G_CAPTURE_ENABLED.load(Ordering::Relaxed) {
+        G_LOG_BUFFER.lock().push(packet_buf);
+    }
+}
+
+/// Pops the oldest packet, see `RecordRing::pop`
+pub fn pop_packet(out: &mut [u8]) -> Option<usize> {
+    G_LOG_BUFFER.lock().pop(out)
+}
+
+pub fn take_drop_count() -> u32 {
+    G_LOG_BUFFER.lock().take_drop_count()
+}
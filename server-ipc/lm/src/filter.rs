@@ -0,0 +1,46 @@
+use nx::diag::log;
+use nx::fs;
+use nx::sync::Mutex;
+
+use lm_filter::{FilterConfig, FILTER_CONFIG_PATH};
+
+use crate::logger;
+
+// Without a config file everything gets logged
+static G_FILTER: Mutex<FilterConfig> = Mutex::new(FilterConfig::new());
+
+/// Loads the filter config from the SD card, the SD card must already be mounted
+pub fn load() {
+    // A missing config file is fine, it just means no filtering
+    let Ok(mut config_file) = fs::open_file(FILTER_CONFIG_PATH, fs::FileOpenOption::Read()) else {
+        return;
+    };
+    let config_str = match sd_config::read_config(|chunk_buf| config_file.read_array(chunk_buf)) {
+        Ok(config_str) => config_str,
+        Err(e) => {
+            diag_log!(logger::SelfLogger { log::LogSeverity::Warn, false } => "Ignoring unreadable filter config {}: {}", FILTER_CONFIG_PATH, e);
+            return;
+        }
+    };
+
+    match FilterConfig::parse(config_str.as_str()) {
+        Ok(config) => {
+            if let Some(self_min_severity) = config.self_min_severity {
+                logger::set_min_severity(self_min_severity);
+            }
+            diag_log!(logger::SelfLogger { log::LogSeverity::Info, false } => "Loaded filter config with {} program rules", config.rules.len());
+            *G_FILTER.lock() = config;
+        }
+        Err(e) => {
+            diag_log!(logger::SelfLogger { log::LogSeverity::Warn, false } => "Ignoring invalid filter config {}: {}", FILTER_CONFIG_PATH, e);
+        }
+    }
+}
+
+pub fn is_program_denied(program_id: u64) -> bool {
+    G_FILTER.lock().is_program_denied(program_id)
+}
+
+pub fn should_log(program_id: u64, severity: u8) -> bool {
+    G_FILTER.lock().should_log(program_id, severity)
+}
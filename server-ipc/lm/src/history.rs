@@ -0,0 +1,33 @@
+use nx::arm;
+use nx::sync::Mutex;
+
+use lm_binlog::packet::LogPacket;
+use lm_binlog::record::{LogRecord, MAX_LOG_RECORD_SIZE};
+use lm_binlog::ring::RecordRing;
+use lm_ipc::LOG_HISTORY_SIZE;
+
+// Decoded records of the most recent logs, regardless of the destination or the sleep state
+static G_LOG_HISTORY: Mutex<RecordRing<LOG_HISTORY_SIZE>> = Mutex::new(RecordRing::new());
+
+pub fn push_packet(packet_buf: &[u8], program_id: u64) {
+    let packet = match LogPacket::parse(packet_buf) {
+        Ok(packet) => packet,
+        Err(_) => return,
+    };
+
+    // Encode in the stack, our heap is too small to waste it on this
+    let record = LogRecord::from_packet(&packet, program_id, arm::get_system_tick());
+    let mut record_buf = [0u8; MAX_LOG_RECORD_SIZE];
+    if let Some(record_size) = record.encode(&mut record_buf) {
+        G_LOG_HISTORY.lock().push(&record_buf[..record_size]);
+    }
+}
+
+/// Copies the newest records which fit into `out`, see `RecordRing::copy_records`
+pub fn copy_records(out: &mut [u8]) -> (usize, usize) {
+    G_LOG_HISTORY.lock().copy_records(out)
+}
+
+pub fn clear() {
+    G_LOG_HISTORY.lock().clear();
+}
@@ -1,4 +1,8 @@
+use crate::buffer;
+use crate::filter;
+use crate::history;
 use crate::logger;
+use crate::sink;
 use nx::diag::log;
 use nx::ipc::server;
 use nx::ipc::server::ISessionObject;
@@ -6,39 +10,70 @@ use nx::ipc::sf;
 use nx::ipc::sf::lm;
 use nx::result::*;
 use nx::service;
-use nx::service::lm::ILoggerServer;
-use nx::service::lm::ILoggingServer;
 use nx::service::pm;
 use nx::service::pm::IInformationInterfaceClient;
 use nx::service::sm;
 
+use lm_binlog::assembler::{PacketAssembler, PendingBudget};
+use lm_binlog::packet::LogPacketHeader;
+use lm_ipc::{ILogGetterServer, ILogHistoryServer, ILoggerServer, ILoggingServer};
+
+// Split messages are small in practice, so a single session doesn't need to buffer more than this...
+const MAX_PENDING_PACKET_SIZE: usize = 0x1000;
+// ...and all of them together (there can be dozens) get this much, which has to fit in our tiny heap along with everything else
+static G_PENDING_BUDGET: PendingBudget = PendingBudget::new(0x2000);
+
 pub struct BinaryFileLogger {
-    log_destination: lm::LogDestination,
     program_id: u64,
+    // The filter config is only loaded on startup, so this can't change during the session
+    denied: bool,
+    // Every logger session belongs to a single process, so this is per process
+    assembler: PacketAssembler<'static>,
 }
 
 impl BinaryFileLogger {
     pub fn new(program_id: u64) -> Self {
         Self {
-            log_destination: lm::LogDestination::Tma(),
             program_id,
+            denied: filter::is_program_denied(program_id),
+            assembler: PacketAssembler::new(MAX_PENDING_PACKET_SIZE, &G_PENDING_BUDGET),
         }
     }
 }
 
+impl Drop for BinaryFileLogger {
+    fn drop(&mut self) {
+        // The session is being closed, so any message still pending won't ever be completed
+        let program_id = self.program_id;
+        self.assembler.flush_all(|packet_buf| sink::log_packet(packet_buf, program_id));
+    }
+}
+
 impl ILoggerServer for BinaryFileLogger {
     fn log(&mut self, log_buf: sf::InAutoSelectBuffer<'_, u8>) -> Result<()> {
         diag_log!(logger::SelfLogger { log::LogSeverity::Trace, false } => "Logging with buffer ({:p}, 0x{:X})", log_buf.get_address(), log_buf.get_size());
 
-        logger::log_packet_buf(log_buf.get_address(), log_buf.get_size(), self.program_id);
+        if self.denied {
+            return Ok(());
+        }
+
+        let packet_buf = unsafe { core::slice::from_raw_parts(log_buf.get_address(), log_buf.get_size()) };
+        let program_id = self.program_id;
+
+        // Every packet of a split message carries the same severity, so they can be dropped right away without reassembling anything
+        if let Ok(header) = LogPacketHeader::parse(packet_buf) {
+            if !filter::should_log(program_id, header.severity) {
+                return Ok(());
+            }
+        }
+        self.assembler.push(packet_buf, |packet_buf| sink::log_packet(packet_buf, program_id));
         Ok(())
     }
 
     fn set_destination(&mut self, log_destination: lm::LogDestination) -> Result<()> {
-        // Note: in official code, log destination is a global variable (not stored in the logger interface like here)
-        // TODO: make use of log destination?
+        // Like in official code, this affects all loggers
         diag_log!(logger::SelfLogger { log::LogSeverity::Trace, false } => "Setting destination 0x{:X}", log_destination.get());
-        self.log_destination = log_destination;
+        sink::set_destination(log_destination);
 
         Ok(())
     }
@@ -96,3 +131,96 @@ impl server::IService for LogService {
         42
     }
 }
+
+pub struct LogGetterService;
+
+impl ILogGetterServer for LogGetterService {
+    fn start_logging(&mut self) -> Result<()> {
+        diag_log!(logger::SelfLogger { log::LogSeverity::Trace, false } => "Starting log capture");
+        buffer::set_capture_enabled(true);
+        Ok(())
+    }
+
+    fn stop_logging(&mut self) -> Result<()> {
+        diag_log!(logger::SelfLogger { log::LogSeverity::Trace, false } => "Stopping log capture");
+        buffer::set_capture_enabled(false);
+        Ok(())
+    }
+
+    fn get_log(&mut self, log_buf: sf::OutAutoSelectBuffer<'_, u8>) -> Result<(u64, u32)> {
+        let out_buf = unsafe { core::slice::from_raw_parts_mut(log_buf.get_address() as *mut u8, log_buf.get_size()) };
+
+        // Like official code, an empty log (size 0) is returned when there's nothing buffered
+        let log_size = buffer::pop_packet(out_buf).unwrap_or(0).min(out_buf.len());
+        Ok((log_size as u64, buffer::take_drop_count()))
+    }
+}
+
+impl server::ISessionObject for LogGetterService {
+    fn try_handle_request_by_id(
+        &mut self,
+        req_id: u32,
+        protocol: nx::ipc::CommandProtocol,
+        server_ctx: &mut server::ServerContext,
+    ) -> Option<Result<()>> {
+        <Self as ILogGetterServer>::try_handle_request_by_id(self, req_id, protocol, server_ctx)
+    }
+}
+
+impl server::IServerObject for LogGetterService {
+    fn new() -> Self {
+        Self
+    }
+}
+
+impl server::IService for LogGetterService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("lm:get")
+    }
+
+    fn get_max_sesssions() -> i32 {
+        1
+    }
+}
+
+pub struct LogHistoryService;
+
+impl ILogHistoryServer for LogHistoryService {
+    fn get_history(&mut self, out_buf: sf::OutMapAliasBuffer<'_, u8>) -> Result<(u64, u32)> {
+        let out_buf = unsafe { core::slice::from_raw_parts_mut(out_buf.get_address() as *mut u8, out_buf.get_size()) };
+        let (size, record_count) = history::copy_records(out_buf);
+        Ok((size as u64, record_count as u32))
+    }
+
+    fn clear_history(&mut self) -> Result<()> {
+        history::clear();
+        Ok(())
+    }
+}
+
+impl server::ISessionObject for LogHistoryService {
+    fn try_handle_request_by_id(
+        &mut self,
+        req_id: u32,
+        protocol: nx::ipc::CommandProtocol,
+        server_ctx: &mut server::ServerContext,
+    ) -> Option<Result<()>> {
+        <Self as ILogHistoryServer>::try_handle_request_by_id(self, req_id, protocol, server_ctx)
+    }
+}
+
+impl server::IServerObject for LogHistoryService {
+    fn new() -> Self {
+        Self
+    }
+}
+
+impl server::IService for LogHistoryService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new(lm_ipc::LOG_HISTORY_SERVICE_NAME)
+    }
+
+    fn get_max_sesssions() -> i32 {
+        4
+    }
+}
@@ -0,0 +1,65 @@
+//! IPC interfaces implemented by this LogManager replacement, also usable by clients
+
+#![no_std]
+
+use nx::ipc::sf;
+use nx::ipc::sf::lm;
+use nx::result::Result;
+use nx::service::{self, sm};
+use nx::version;
+use nx::{ipc_sf_define_default_client_for_interface, ipc_sf_define_interface_trait};
+
+// The interfaces in nx only cover the original commands, so the full ones are defined here
+
+ipc_sf_define_default_client_for_interface!(Logger);
+ipc_sf_define_interface_trait! {
+    trait Logger {
+        log [0, version::VersionInterval::all(), mut ]: (log_buf: sf::InAutoSelectBuffer<'_, u8>) => () ();
+        set_destination [1, version::VersionInterval::from(version::Version::new(3, 0, 0)), mut ]: (log_destination: lm::LogDestination) => () ();
+    }
+}
+
+ipc_sf_define_default_client_for_interface!(Logging);
+ipc_sf_define_interface_trait! {
+    trait Logging {
+        open_logger [0, version::VersionInterval::all(), mut ]: (process_id: sf::ProcessId) => (logger: Logger) (logger: impl ILoggerServer + 'static);
+    }
+}
+
+ipc_sf_define_default_client_for_interface!(LogGetter);
+ipc_sf_define_interface_trait! {
+    trait LogGetter {
+        start_logging [0, version::VersionInterval::from(version::Version::new(11, 0, 0)), mut ]: () => () ();
+        stop_logging [1, version::VersionInterval::from(version::Version::new(11, 0, 0)), mut ]: () => () ();
+        get_log [2, version::VersionInterval::from(version::Version::new(11, 0, 0)), mut ]: (log_buf: sf::OutAutoSelectBuffer<'_, u8>) => (log_size: u64, drop_count: u32) (log_size: u64, drop_count: u32);
+    }
+}
+
+// Custom interface (not present in official LogManager) to read the most recent logs, even the ones which didn't reach the SD card
+
+pub const LOG_HISTORY_SERVICE_NAME: &str = "lm:hist";
+
+/// Size of the history buffer, a buffer of this size is enough to get the whole history
+pub const LOG_HISTORY_SIZE: usize = 0x4000;
+
+ipc_sf_define_default_client_for_interface!(LogHistory);
+ipc_sf_define_interface_trait! {
+    trait LogHistory {
+        get_history [0, version::VersionInterval::all(), mut ]: (out_buf: sf::OutMapAliasBuffer<'_, u8>) => (size: u64, record_count: u32) (size: u64, record_count: u32);
+        clear_history [1, version::VersionInterval::all(), mut ]: () => () ();
+    }
+}
+
+impl service::IService for LogHistory {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new(LOG_HISTORY_SERVICE_NAME)
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -1,106 +1,185 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
 use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
 
-use alloc::string::String;
 use nx::arm;
 use nx::diag::log;
 use nx::fs;
-use nx::fs::Write;
+use nx::fs::Write as _;
 use nx::result::*;
+use nx::sync::Mutex;
 use nx::thread;
 
-const BASE_LOG_DIR: &'static str = "sdmc:/lm-binlogs";
+use lm_binlog::fmt::FixedBuffer;
+use lm_binlog::time::{ClockBase, TickTime};
+
+use crate::writer;
+
+pub const BASE_LOG_DIR: &'static str = "sdmc:/lm-binlogs";
 
 pub static G_ENABLED: AtomicBool = AtomicBool::new(true);
 
-const LOG_BINARY_HEADER_MAGIC: u32 = 0x70687068;
-const CURRENT_VERSION: u32 = 1;
+pub struct SelfLogConfig {
+    /// Messages below this severity are dropped (can be overriden with `self_min_severity` in the filter config)
+    pub min_severity: log::LogSeverity,
+    /// Size after which a new self-log file is started
+    pub max_file_size: usize,
+    /// Maximum amount of self-log files kept, older ones get removed
+    pub max_file_count: u32,
+    /// Whether to prefix every line with its time (wall-clock time once some logger sent its user system clock, uptime until then)
+    pub timestamps: bool,
+}
 
-#[derive(Copy, Clone)]
-#[repr(C)]
-pub struct LogBinaryHeader {
-    magic: u32,
-    version: u32,
+pub const SELF_LOG_CONFIG: SelfLogConfig = SelfLogConfig {
+    min_severity: log::LogSeverity::Info,
+    max_file_size: 0x10000,
+    max_file_count: 4,
+    timestamps: true,
+};
+
+// Lines are formatted in the stack (and truncated to this size), our heap is too small to waste it on them
+const MAX_SELF_LOG_LINE_SIZE: usize = 0x400;
+
+// Same values as the severities in log packet headers
+pub const fn get_severity_value(severity: log::LogSeverity) -> u8 {
+    match severity {
+        log::LogSeverity::Trace => 0,
+        log::LogSeverity::Info => 1,
+        log::LogSeverity::Warn => 2,
+        log::LogSeverity::Error => 3,
+        log::LogSeverity::Fatal => 4,
+    }
 }
 
-impl LogBinaryHeader {
-    pub const fn new(magic: u32, version: u32) -> Self {
-        Self {
-            magic: magic,
-            version: version,
-        }
+static G_MIN_SEVERITY: AtomicU8 = AtomicU8::new(get_severity_value(SELF_LOG_CONFIG.min_severity));
+
+static G_CLOCK_BASE: Mutex<Option<ClockBase>> = Mutex::new(None);
+
+struct SelfLogState {
+    segment: u32,
+    file_size: usize,
+}
+
+static G_SELF_LOG: Mutex<SelfLogState> = Mutex::new(SelfLogState { segment: 0, file_size: 0 });
+
+/// Gets the names of the entries of a directory (only files or only directories, depending on the mode)
+pub fn list_dir(path: &str, mode: fs::DirectoryOpenMode) -> Result<Vec<String>> {
+    let mut dir = fs::open_directory(path, mode)?;
+    let mut names = Vec::new();
+    while let Some(entry) = dir.read_next()? {
+        names.push(entry.name);
+    }
+    Ok(names)
+}
+
+fn parse_self_log_segment(file_name: &str) -> Option<u32> {
+    file_name.strip_prefix("self-")?.strip_suffix(".log")?.parse().ok()
+}
+
+// Segments go on from the ones of previous boots (instead of overwriting them from the first one), removing the ones which
+// wouldn't have been kept by rotating, so that the newest self-logs are always the highest segments
+fn continue_self_log() {
+    let mut log_dir = FixedBuffer::<0x40>::new();
+    let _ = write!(log_dir, "{}/self-logs", BASE_LOG_DIR);
+    let Ok(file_names) = list_dir(log_dir.as_str(), fs::DirectoryOpenMode::ReadFiles()) else {
+        return;
+    };
+    let segments: Vec<u32> = file_names.iter().filter_map(|file_name| parse_self_log_segment(file_name)).collect();
+    let Some(last_segment) = segments.iter().max() else {
+        return;
+    };
+
+    let segment = last_segment.saturating_add(1);
+    for &old_segment in segments.iter().filter(|&&old_segment| segment - old_segment >= SELF_LOG_CONFIG.max_file_count) {
+        let _ = fs::remove_file(get_self_log_path(old_segment).as_str());
     }
+    G_SELF_LOG.lock().segment = segment;
 }
 
 pub fn initialize() -> Result<()> {
-    let _ = fs::remove_dir(BASE_LOG_DIR);
-    fs::create_directory(BASE_LOG_DIR)
+    let _ = fs::create_directory(BASE_LOG_DIR);
+    continue_self_log();
+    writer::initialize()
 }
 
-fn log_packet_buf_impl(
-    packet_buf: *const u8,
-    packet_buf_size: usize,
-    bin_header: LogBinaryHeader,
-    log_dir: String,
-    log_buf_file: String,
-) -> Result<()> {
-    if G_ENABLED.load(core::sync::atomic::Ordering::Relaxed) {
-        let _ = fs::create_directory(BASE_LOG_DIR);
-        let _ = fs::create_directory(log_dir.as_str());
+pub fn set_min_severity(min_severity: u8) {
+    G_MIN_SEVERITY.store(min_severity, Ordering::Relaxed);
+}
 
-        let _ = fs::remove_file(log_buf_file.as_str());
-
-        let mut log_file = fs::open_file(
-            log_buf_file.as_str(),
-            fs::FileOpenOption::Create()
-                | fs::FileOpenOption::Write()
-                | fs::FileOpenOption::Append(),
-        )?;
-        log_file.write_all(unsafe {
-            core::mem::transmute::<&LogBinaryHeader, &[u8; size_of::<LogBinaryHeader>()]>(
-                &bin_header,
-            )
-        })?;
-        log_file.write_all(unsafe { core::slice::from_raw_parts(packet_buf, packet_buf_size) })?;
+/// Pairs a user system clock value (POSIX seconds) sent by some logger with the tick it was received at, for timestamps to show wall-clock time
+pub fn update_clock_base(unix_secs: u64, tick: u64) {
+    *G_CLOCK_BASE.lock() = Some(ClockBase { tick, unix_secs });
+}
+
+fn write_timestamp<const N: usize>(line: &mut FixedBuffer<N>) {
+    if SELF_LOG_CONFIG.timestamps {
+        let tick_time = TickTime {
+            tick: arm::get_system_tick(),
+            tick_frequency: arm::get_system_tick_frequency(),
+            base: *G_CLOCK_BASE.lock(),
+        };
+        let _ = write!(line, "{} ", tick_time);
     }
+}
 
-    Ok(())
+fn get_self_log_path(segment: u32) -> FixedBuffer<0x40> {
+    let mut path = FixedBuffer::new();
+    let _ = write!(path, "{}/self-logs/self-{:04}.log", BASE_LOG_DIR, segment);
+    path
 }
 
-fn log_self_impl(self_msg: String, log_dir: String, log_buf_file: String) -> Result<()> {
-    if G_ENABLED.load(core::sync::atomic::Ordering::Relaxed) {
+fn write_self_log(line: &[u8]) -> Result<()> {
+    if !G_ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let needs_newline = !line.ends_with(b"\n");
+    let line_size = line.len() + needs_newline as usize;
+
+    let mut state = G_SELF_LOG.lock();
+    if (state.file_size > 0) && (state.file_size + line_size > SELF_LOG_CONFIG.max_file_size) {
+        state.segment += 1;
+        state.file_size = 0;
+        if let Some(old_segment) = state.segment.checked_sub(SELF_LOG_CONFIG.max_file_count) {
+            let _ = fs::remove_file(get_self_log_path(old_segment).as_str());
+        }
+    }
+
+    let log_path = get_self_log_path(state.segment);
+    if state.file_size == 0 {
+        // Leftovers from a previous boot, this segment must start fresh
+        let mut log_dir = FixedBuffer::<0x40>::new();
+        let _ = write!(log_dir, "{}/self-logs", BASE_LOG_DIR);
         let _ = fs::create_directory(BASE_LOG_DIR);
         let _ = fs::create_directory(log_dir.as_str());
+        let _ = fs::remove_file(log_path.as_str());
+    }
 
-        let mut log_file = fs::open_file(
-            log_buf_file.as_str(),
-            fs::FileOpenOption::Create()
-                | fs::FileOpenOption::Write()
-                | fs::FileOpenOption::Append(),
-        )?;
-        log_file.write_all(self_msg.as_bytes())?;
+    let mut log_file = fs::open_file(
+        log_path.as_str(),
+        fs::FileOpenOption::Create()
+            | fs::FileOpenOption::Write()
+            | fs::FileOpenOption::Append(),
+    )?;
+    log_file.write_all(line)?;
+    if needs_newline {
+        log_file.write_all(b"\n")?;
     }
-    Ok(())
-}
 
-pub fn log_packet_buf(packet_buf: *const u8, packet_buf_size: usize, program_id: u64) {
-    let log_timestamp = arm::get_system_tick();
-    let process_log_dir = format!("{}/0x{:016X}", BASE_LOG_DIR, program_id);
-    let log_buf_path = format!("{}/0x{:016X}.nxbinlog", process_log_dir, log_timestamp);
-
-    let _ = log_packet_buf_impl(
-        packet_buf,
-        packet_buf_size,
-        LogBinaryHeader::new(LOG_BINARY_HEADER_MAGIC, CURRENT_VERSION),
-        process_log_dir,
-        log_buf_path,
-    );
+    state.file_size += line_size;
+    Ok(())
 }
 
-pub fn log_self(self_msg: String) {
-    let process_log_dir = format!("{}/self-logs", BASE_LOG_DIR);
-    let log_buf_path = format!("{}/self.log", process_log_dir);
+/// Appends a line of text to the self-log, regardless of the minimum severity
+pub fn log_self(text: &str) {
+    let mut line = FixedBuffer::<MAX_SELF_LOG_LINE_SIZE>::new();
+    write_timestamp(&mut line);
+    let _ = line.write_str(text);
 
-    let _ = log_self_impl(self_msg, process_log_dir, log_buf_path);
+    let _ = write_self_log(line.as_bytes());
 }
 
 // System for LogManager to be able to log stuff itself (even if it gets saved in a different way)
@@ -113,6 +192,10 @@ impl log::Logger for SelfLogger {
     }
 
     fn log(&mut self, metadata: &log::LogMetadata) {
+        if get_severity_value(metadata.severity) < G_MIN_SEVERITY.load(Ordering::Relaxed) {
+            return;
+        }
+
         let severity_str = match metadata.severity {
             log::LogSeverity::Trace => "Trace",
             log::LogSeverity::Info => "Info",
@@ -120,8 +203,12 @@ impl log::Logger for SelfLogger {
             log::LogSeverity::Error => "Error",
             log::LogSeverity::Fatal => "Fatal",
         };
-        let msg = format!(
-            "[ SelfLog (severity: {}, verbosity: {}) from {} in thread {}, at {}:{} ] {}\n",
+
+        let mut line = FixedBuffer::<MAX_SELF_LOG_LINE_SIZE>::new();
+        write_timestamp(&mut line);
+        let _ = write!(
+            line,
+            "[ SelfLog (severity: {}, verbosity: {}) from {} in thread {}, at {}:{} ] {}",
             severity_str,
             metadata.verbosity,
             metadata.fn_name,
@@ -130,6 +217,7 @@ impl log::Logger for SelfLogger {
             metadata.line_number,
             metadata.msg
         );
-        log_self(msg);
+
+        let _ = write_self_log(line.as_bytes());
     }
 }
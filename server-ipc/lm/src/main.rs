@@ -19,16 +19,36 @@ use nx::service;
 use nx::service::psc;
 use nx::service::psc::IPmModuleClient;
 use nx::service::psc::IPmClient;
+use nx::svc;
 use nx::thread;
 use nx::util;
+use nx::version;
 use nx::wait;
 
+use lm_power::{PmModule, PowerState, PowerStateMachine};
+
 rrt0_define_default_module_name!();
 
+mod buffer;
+mod filter;
+mod history;
 mod ipc;
 mod logger;
-
+#[cfg(any(feature = "udp-sink", feature = "tcp-stream"))]
+mod net;
+mod power;
+mod sink;
+#[cfg(feature = "tcp-stream")]
+mod stream;
+#[cfg(feature = "udp-sink")]
+mod udp;
+mod writer;
+
+// Sockets need quite some transfer memory, which gets allocated from our heap
+#[cfg(not(any(feature = "udp-sink", feature = "tcp-stream")))]
 const CUSTOM_HEAP_SIZE: usize = 0x8000;
+#[cfg(any(feature = "udp-sink", feature = "tcp-stream"))]
+const CUSTOM_HEAP_SIZE: usize = 0x40000;
 static mut CUSTOM_HEAP: [u8; CUSTOM_HEAP_SIZE] = [0; CUSTOM_HEAP_SIZE];
 
 #[no_mangle]
@@ -37,23 +57,49 @@ pub fn initialize_heap(_hbl_heap: util::PointerAndSize) -> util::PointerAndSize
     util::PointerAndSize::new(&raw mut CUSTOM_HEAP as _, CUSTOM_HEAP_SIZE)
 }
 
-pub fn pm_module_main() -> Result<()> {
-    let psc = service::new_service_object::<psc::PmService>().unwrap();
-    let module = psc.get_pm_module().unwrap();
+struct PscModule<T: IPmModuleClient> {
+    module: T,
+    event_handle: svc::Handle,
+}
 
-    let event_handle = module.initialize(psc::ModuleId::Lm, sf::Buffer::from_array(&[])).unwrap();
-    loop {
-        wait::wait_handles(&[event_handle.handle], -1).unwrap();
+impl<T: IPmModuleClient> PmModule for PscModule<T> {
+    type State = psc::State;
+    type Error = ResultCode;
+
+    fn wait_request(&mut self) -> Result<psc::State> {
+        wait::wait_handles(&[self.event_handle], -1)?;
 
-        let (state, _flags) = module.get_request().unwrap();
+        let (state, _flags) = self.module.get_request()?;
+        Ok(state)
+    }
+
+    fn get_power_state(state: &psc::State) -> PowerState {
         match state {
             psc::State::FullAwake
             | psc::State::MinimumAwake
-            | psc::State::EssentialServicesAwake => logger::G_ENABLED.store(true, core::sync::atomic::Ordering::Relaxed),
-            _ => logger::G_ENABLED.store(false, core::sync::atomic::Ordering::Relaxed),
-        };
+            | psc::State::EssentialServicesAwake => PowerState::Awake,
+            _ => PowerState::Sleeping,
+        }
+    }
 
-        module.acknowledge_ex(state).unwrap();
+    fn acknowledge(&mut self, state: psc::State) -> Result<()> {
+        self.module.acknowledge_ex(state)
+    }
+}
+
+pub fn pm_module_main() -> Result<()> {
+    let psc = service::new_service_object::<psc::PmService>().unwrap();
+    let module = psc.get_pm_module().unwrap();
+
+    let event_handle = module.initialize(psc::ModuleId::Lm, sf::Buffer::from_array(&[])).unwrap();
+    let mut psc_module = PscModule {
+        module,
+        event_handle: event_handle.handle,
+    };
+
+    let mut state_machine = PowerStateMachine::new();
+    loop {
+        state_machine.handle_request(&mut psc_module, &mut power::LogFiles).unwrap();
     }
 }
 
@@ -70,14 +116,24 @@ pub fn main() {
     fs::initialize_fspsrv_session().expect("Error starting filesystem services");
     fs::mount_sd_card("sdmc").expect("Failed to mount sd card");
     logger::initialize().unwrap();
+    filter::load();
+    sink::initialize();
 
     let pm_module_thread = thread::Builder::new()
         .name("lm.PmModule")
         .stack_size(0x2000)
         .spawn(|| pm_module_main()).unwrap();
 
+    #[cfg(feature = "tcp-stream")]
+    let _stream_threads = stream::start().unwrap();
+
     let mut manager = Manager::new().unwrap();
     manager.register_service_server::<ipc::LogService>().unwrap();
+    if version::get_version() >= version::Version::new(11, 0, 0) {
+        // 11.0.0 -> (...) has "lm:get"
+        manager.register_service_server::<ipc::LogGetterService>().unwrap();
+    }
+    manager.register_service_server::<ipc::LogHistoryService>().unwrap();
     manager.loop_process().unwrap();
 
     pm_module_thread
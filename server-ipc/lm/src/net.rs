@@ -0,0 +1,19 @@
+use nx::result::*;
+use nx::sync::Mutex;
+
+// Both the UDP sink and the TCP stream need the socket service, but it must only be initialized once
+static G_SOCKETS_INITIALIZED: Mutex<bool> = Mutex::new(false);
+
+pub fn initialize_sockets() -> Result<()> {
+    let mut initialized = G_SOCKETS_INITIALIZED.lock();
+    if !*initialized {
+        nx::socket::initialize(
+            nx::socket::BsdSrvkind::System,
+            Default::default(),
+            None,
+            nx::socket::Paralellism::One,
+        )?;
+        *initialized = true;
+    }
+    Ok(())
+}
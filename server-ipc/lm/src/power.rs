@@ -0,0 +1,30 @@
+use core::sync::atomic::Ordering;
+
+use nx::diag::log;
+
+use lm_power::LogStorage;
+
+use crate::logger;
+use crate::writer;
+
+/// Our log files on the SD card
+pub struct LogFiles;
+
+impl LogStorage for LogFiles {
+    fn flush(&mut self) {
+        diag_log!(logger::SelfLogger { log::LogSeverity::Info, false } => "Suspending logging before sleep");
+        writer::flush();
+    }
+
+    fn close(&mut self) {
+        // Disable logging first, so nothing gets written between closing the files and sleeping
+        logger::G_ENABLED.store(false, Ordering::Relaxed);
+        writer::suspend();
+    }
+
+    fn reopen(&mut self) {
+        writer::resume();
+        logger::G_ENABLED.store(true, Ordering::Relaxed);
+        diag_log!(logger::SelfLogger { log::LogSeverity::Info, false } => "Resumed logging after wake");
+    }
+}
@@ -0,0 +1,102 @@
+use core::fmt::Write;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+
+use nx::arm;
+use nx::ipc::sf::lm;
+
+use lm_binlog::fmt::{FixedBuffer, PacketText};
+use lm_binlog::packet::LogPacket;
+
+use crate::buffer;
+use crate::history;
+use crate::logger;
+use crate::writer;
+
+// How each destination bit is handled here:
+// - Tma: binary log files on the SD card
+// - Uart and UartSleeping: decoded text lines in the self-log (which isn't written while sleeping either way)
+// - DESTINATION_LOG_BUFFER: in-memory buffer, which can be read back through "lm:get"
+// - DESTINATION_UDP: decoded text lines sent over UDP (only with the "udp-sink" feature)
+// Besides these, every packet is kept in the history and streamed over TCP (only with the "tcp-stream" feature)
+
+/// Not an official destination, but covered by `LogDestination::All`
+pub const DESTINATION_LOG_BUFFER: u32 = 1 << 3;
+/// Not an official destination either, also covered by `LogDestination::All`
+pub const DESTINATION_UDP: u32 = 1 << 4;
+
+// Like in official code, the destination is a global setting shared by all loggers
+static G_DESTINATION: AtomicU32 = AtomicU32::new(0);
+
+pub fn initialize() {
+    let mut destination = lm::LogDestination::Tma().get() | DESTINATION_LOG_BUFFER;
+    // Building with it means wanting it
+    if cfg!(feature = "udp-sink") {
+        destination |= DESTINATION_UDP;
+    }
+    G_DESTINATION.store(destination, Ordering::Relaxed);
+}
+
+pub fn set_destination(log_destination: lm::LogDestination) {
+    G_DESTINATION.store(log_destination.get(), Ordering::Relaxed);
+}
+
+pub fn get_destination() -> u32 {
+    G_DESTINATION.load(Ordering::Relaxed)
+}
+
+fn get_self_log_destinations() -> u32 {
+    lm::LogDestination::Uart().get() | lm::LogDestination::UartSleeping().get()
+}
+
+// Decoded text lines are formatted in the stack (and truncated to this size), our heap is too small to waste it on them
+const MAX_TEXT_LINE_SIZE: usize = 0x400;
+
+fn log_text(packet: &LogPacket, program_id: u64, destination: u32) {
+    let mut line = FixedBuffer::<MAX_TEXT_LINE_SIZE>::new();
+    let _ = writeln!(line, "{}", PacketText { packet, program_id: Some(program_id), tick: None });
+
+    #[cfg(feature = "udp-sink")]
+    if (destination & DESTINATION_UDP) != 0 {
+        crate::udp::send_line(line.as_str());
+    }
+
+    if (destination & get_self_log_destinations()) != 0 {
+        logger::log_self(line.as_str());
+    }
+}
+
+/// Sends a (reassembled) packet to every sink enabled by the current destination
+pub fn log_packet(packet_buf: &[u8], program_id: u64) {
+    history::push_packet(packet_buf, program_id);
+
+    // Streamed regardless of the destination, this is meant for watching everything live
+    #[cfg(feature = "tcp-stream")]
+    crate::stream::push_packet(packet_buf, program_id, arm::get_system_tick());
+
+    let packet = LogPacket::parse(packet_buf).ok();
+    if let Some(unix_secs) = packet.as_ref().and_then(|packet| packet.user_system_clock) {
+        logger::update_clock_base(unix_secs, arm::get_system_tick());
+    }
+
+    let destination = get_destination();
+
+    // The buffer lives in memory, so it's fine to keep on using it while sleeping
+    if (destination & DESTINATION_LOG_BUFFER) != 0 {
+        buffer::push_packet(packet_buf);
+    }
+
+    if !logger::G_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    if (destination & lm::LogDestination::Tma().get()) != 0 {
+        let _ = writer::write_packet(program_id, packet_buf);
+    }
+
+    if (destination & (get_self_log_destinations() | DESTINATION_UDP)) != 0 {
+        if let Some(packet) = packet.as_ref() {
+            log_text(packet, program_id, destination);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::net::Ipv4Addr;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+use nx::diag::log;
+use nx::result::*;
+use nx::socket::net::traits::SocketCommon;
+use nx::socket::net::{TcpListener, TcpStream};
+use nx::sync::Mutex;
+use nx::thread;
+
+use lm_binlog::binlog::{LogBinaryHeader, LogRecordHeader};
+use lm_binlog::fmt::{FixedBuffer, PacketJson};
+use lm_binlog::packet::LogPacket;
+use lm_binlog::ring::RecordRing;
+use lm_binlog::stream::{StreamFormat, HANDSHAKE_TIMEOUT_MS, STREAM_PORT};
+
+use crate::logger;
+use crate::net;
+
+// Every client costs a socket and some sending time, and this is meant for a single developer watching at a desk
+const MAX_CLIENTS: usize = 2;
+
+// Packets are queued here by the IPC thread and sent by the streaming thread, so slow clients never block loggers
+const STREAM_QUEUE_SIZE: usize = 0x4000;
+// Queued entries are the program ID and tick (as u64s) followed by the raw packet
+const QUEUE_ENTRY_HEADER_SIZE: usize = 0x10;
+// Bigger packets get dropped, reassembled messages are capped way below this anyway
+const MAX_STREAM_PACKET_SIZE: usize = 0x1000;
+const MAX_JSON_LINE_SIZE: usize = 0x800;
+
+// We start way before the network services are up, so keep on retrying to listen every ~5 seconds
+const LISTEN_RETRY_INTERVAL_NS: i64 = 5_000_000_000;
+const POLL_INTERVAL_NS: i64 = 10_000_000;
+// Clients which can't keep up for this long (in poll intervals) get disconnected
+const MAX_SEND_RETRIES: usize = 100;
+
+struct StreamClient {
+    stream: TcpStream,
+    format: StreamFormat,
+}
+
+static G_QUEUE: Mutex<RecordRing<STREAM_QUEUE_SIZE>> = Mutex::new(RecordRing::new());
+// Accepted clients waiting to be picked up by the streaming thread, which keeps the connected ones to itself, so that the
+// accept thread never waits on slow sends
+static G_NEW_CLIENTS: Mutex<Vec<StreamClient>> = Mutex::new(Vec::new());
+// Both new and connected clients, which also lets loggers skip queueing without locking anything while nobody is connected
+static G_CLIENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Queues a (reassembled) packet to be sent to every connected client
+pub fn push_packet(packet_buf: &[u8], program_id: u64, tick: u64) {
+    if (G_CLIENT_COUNT.load(Ordering::Relaxed) == 0) || (packet_buf.len() > MAX_STREAM_PACKET_SIZE) {
+        return;
+    }
+
+    G_QUEUE.lock().push_parts(&[&program_id.to_le_bytes(), &tick.to_le_bytes(), packet_buf]);
+}
+
+fn send_all(stream: &TcpStream, mut data: &[u8]) -> bool {
+    let mut retries = 0;
+    while !data.is_empty() {
+        match stream.send_non_blocking(data) {
+            Ok(Some(sent_len)) if sent_len > 0 => {
+                data = &data[sent_len..];
+                retries = 0;
+            }
+            Ok(_) if retries < MAX_SEND_RETRIES => {
+                retries += 1;
+                let _ = thread::sleep(POLL_INTERVAL_NS);
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn send_entry(clients: &mut Vec<StreamClient>, entry: &[u8]) {
+    // The entry is at least QUEUE_ENTRY_HEADER_SIZE bytes long
+    let program_id = u64::from_le_bytes(entry[0x0..0x8].try_into().unwrap());
+    let tick = u64::from_le_bytes(entry[0x8..0x10].try_into().unwrap());
+    let packet_buf = &entry[QUEUE_ENTRY_HEADER_SIZE..];
+
+    // Only formatted if some client wants it
+    let mut json_line: Option<FixedBuffer<MAX_JSON_LINE_SIZE>> = None;
+
+    let client_count = clients.len();
+    clients.retain(|client| match client.format {
+        StreamFormat::JsonLines => {
+            let json_line = json_line.get_or_insert_with(|| {
+                let mut line = FixedBuffer::new();
+                if let Ok(packet) = LogPacket::parse(packet_buf) {
+                    let _ = writeln!(line, "{}", PacketJson { packet: &packet, program_id: Some(program_id), tick: Some(tick) });
+                }
+                line
+            });
+            json_line.is_empty() || send_all(&client.stream, json_line.as_bytes())
+        }
+        StreamFormat::BinLog => {
+            let record_header = LogRecordHeader::new(packet_buf.len() as u32, tick);
+            send_all(&client.stream, &record_header.to_bytes()) && send_all(&client.stream, packet_buf)
+        }
+    });
+    G_CLIENT_COUNT.fetch_sub(client_count - clients.len(), Ordering::Relaxed);
+}
+
+fn send_thread() {
+    let mut entry_buf = [0u8; QUEUE_ENTRY_HEADER_SIZE + MAX_STREAM_PACKET_SIZE];
+    let mut clients = Vec::new();
+    loop {
+        clients.append(&mut G_NEW_CLIENTS.lock());
+
+        // Don't keep the queue locked while sending
+        let entry_size = G_QUEUE.lock().pop(&mut entry_buf);
+        match entry_size {
+            Some(entry_size) if (entry_size >= QUEUE_ENTRY_HEADER_SIZE) && (entry_size <= entry_buf.len()) => {
+                send_entry(&mut clients, &entry_buf[..entry_size]);
+            }
+            Some(_) => {}
+            None => {
+                let _ = thread::sleep(POLL_INTERVAL_NS);
+            }
+        }
+    }
+}
+
+fn listen() -> Result<TcpListener> {
+    net::initialize_sockets()?;
+    TcpListener::bind(Ipv4Addr::UNSPECIFIED, STREAM_PORT)
+}
+
+fn read_format(stream: &TcpStream) -> StreamFormat {
+    let mut format_buf = [0u8; 1];
+    let poll_interval_ms = POLL_INTERVAL_NS as u64 / 1_000_000;
+    for _ in 0..(HANDSHAKE_TIMEOUT_MS / poll_interval_ms) {
+        match stream.recv_non_blocking(&mut format_buf) {
+            Ok(Some(read_len)) if read_len > 0 => {
+                return StreamFormat::from_u8(format_buf[0]).unwrap_or(StreamFormat::JsonLines);
+            }
+            Ok(None) => {
+                let _ = thread::sleep(POLL_INTERVAL_NS);
+            }
+            // Closed or failed, it will be dropped on the first send anyway
+            _ => break,
+        }
+    }
+    StreamFormat::JsonLines
+}
+
+fn accept_thread() {
+    let listener = loop {
+        match listen() {
+            Ok(listener) => break listener,
+            Err(_) => {
+                let _ = thread::sleep(LISTEN_RETRY_INTERVAL_NS);
+            }
+        }
+    };
+    diag_log!(logger::SelfLogger { log::LogSeverity::Info, false } => "Streaming logs on TCP port {}", STREAM_PORT);
+
+    loop {
+        let stream = match listener.accept() {
+            Ok((stream, _remote_addr)) => stream,
+            Err(_) => {
+                let _ = thread::sleep(POLL_INTERVAL_NS);
+                continue;
+            }
+        };
+
+        if G_CLIENT_COUNT.load(Ordering::Relaxed) >= MAX_CLIENTS {
+            // Dropping it closes the connection
+            continue;
+        }
+
+        let format = read_format(&stream);
+        if (format == StreamFormat::BinLog) && !send_all(&stream, &LogBinaryHeader::current().to_bytes()) {
+            continue;
+        }
+
+        G_NEW_CLIENTS.lock().push(StreamClient { stream, format });
+        G_CLIENT_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Starts the threads accepting clients and sending them the queued packets
+pub fn start() -> Result<(thread::JoinHandle<()>, thread::JoinHandle<()>)> {
+    let accept_thread = thread::Builder::new()
+        .name("lm.StreamAccept")
+        .stack_size(0x2000)
+        .spawn(accept_thread)?;
+    let send_thread = thread::Builder::new()
+        .name("lm.StreamSend")
+        .stack_size(0x4000)
+        .spawn(send_thread)?;
+    Ok((accept_thread, send_thread))
+}
@@ -0,0 +1,54 @@
+use core::fmt::Write;
+use core::net::Ipv4Addr;
+
+use nx::arm;
+use nx::result::*;
+use nx::socket::net::UdpSocket;
+use nx::sync::Mutex;
+
+use crate::net;
+
+// Same host as the one used in the net-log example, listen with something like "nc -ul 5001"
+const LOG_HOST: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 65);
+const LOG_PORT: u16 = 5001;
+
+// We start way before the network services are up, so retry (at most) every ~5 seconds
+const INIT_RETRY_INTERVAL_SECS: u64 = 5;
+
+struct UdpSink {
+    socket: Option<UdpSocket>,
+    last_init_tick: Option<u64>,
+}
+
+static G_UDP_SINK: Mutex<UdpSink> = Mutex::new(UdpSink {
+    socket: None,
+    last_init_tick: None,
+});
+
+fn connect() -> Result<UdpSocket> {
+    net::initialize_sockets()?;
+    UdpSocket::connect(LOG_HOST, LOG_PORT)
+}
+
+/// Sends a line of text as a single datagram, silently dropping it if the network isn't available (yet)
+pub fn send_line(line: &str) {
+    let mut sink = G_UDP_SINK.lock();
+
+    if sink.socket.is_none() {
+        let tick = arm::get_system_tick();
+        let retry_ticks = INIT_RETRY_INTERVAL_SECS * arm::get_system_tick_frequency();
+        if sink.last_init_tick.is_some_and(|last_tick| tick.saturating_sub(last_tick) < retry_ticks) {
+            return;
+        }
+
+        sink.last_init_tick = Some(tick);
+        sink.socket = connect().ok();
+    }
+
+    if let Some(socket) = sink.socket.as_mut() {
+        if socket.write_str(line).is_err() {
+            // Reconnect next time
+            sink.socket = None;
+        }
+    }
+}
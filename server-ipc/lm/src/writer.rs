@@ -0,0 +1,261 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+
+use nx::arm;
+use nx::fs;
+use nx::fs::Write;
+use nx::result::*;
+use nx::sync::Mutex;
+
+use lm_binlog::binlog::{LogBinaryHeader, LogRecordHeader, BINLOG_EXTENSION, LOG_BINARY_HEADER_SIZE, LOG_RECORD_HEADER_SIZE};
+
+use crate::logger::{list_dir, BASE_LOG_DIR};
+
+// Log files are named "<boot>-<segment>.nxbinlog" inside each program's directory, where the segment increases on every rotation
+
+pub struct RotationConfig {
+    /// Size after which a new file (segment) is started
+    pub max_file_size: usize,
+    /// Maximum amount of files kept per program (and boot), older ones get removed
+    pub max_file_count: u32,
+    /// Amount of boots (this one included) whose files are kept, the ones from older boots get removed on startup
+    pub max_boot_count: u32,
+}
+
+pub const ROTATION_CONFIG: RotationConfig = RotationConfig {
+    max_file_size: 0x40000,
+    max_file_count: 8,
+    max_boot_count: 4,
+};
+
+// Every open file takes a handle and our handle table is quite small, so only keep a few of them open
+const MAX_OPEN_FILES: usize = 4;
+
+static G_BOOT_INDEX: AtomicU32 = AtomicU32::new(0);
+
+struct ProgramLogWriter {
+    program_id: u64,
+    segment: u32,
+    file_size: usize,
+    file: Option<fs::File>,
+    last_write_tick: u64,
+    // Whether the file was open when writers got suspended
+    reopen_on_resume: bool,
+}
+
+impl ProgramLogWriter {
+    const fn new(program_id: u64) -> Self {
+        Self {
+            program_id,
+            segment: 0,
+            file_size: 0,
+            file: None,
+            last_write_tick: 0,
+            reopen_on_resume: false,
+        }
+    }
+
+    fn get_log_dir(&self) -> String {
+        format!("{}/0x{:016X}", BASE_LOG_DIR, self.program_id)
+    }
+
+    fn get_file_path(&self, segment: u32) -> String {
+        format!("{}/{:04}-{:04}.{}", self.get_log_dir(), G_BOOT_INDEX.load(Ordering::Relaxed), segment, BINLOG_EXTENSION)
+    }
+
+    fn rotate(&mut self) {
+        self.file = None;
+        self.segment += 1;
+        self.file_size = 0;
+
+        if let Some(old_segment) = self.segment.checked_sub(ROTATION_CONFIG.max_file_count) {
+            let _ = fs::remove_file(self.get_file_path(old_segment).as_str());
+        }
+    }
+
+    fn ensure_open(&mut self) -> Result<&mut fs::File> {
+        if self.file.is_none() {
+            let file_path = self.get_file_path(self.segment);
+            if self.file_size == 0 {
+                // Leftovers from a boot whose index couldn't be saved, this segment must start fresh
+                let _ = fs::create_directory(self.get_log_dir().as_str());
+                let _ = fs::remove_file(file_path.as_str());
+            }
+
+            let mut file = fs::open_file(
+                file_path.as_str(),
+                fs::FileOpenOption::Create()
+                    | fs::FileOpenOption::Write()
+                    | fs::FileOpenOption::Append(),
+            )?;
+            if self.file_size == 0 {
+                file.write_all(&LogBinaryHeader::current().to_bytes())?;
+                self.file_size = LOG_BINARY_HEADER_SIZE;
+            }
+            self.file = Some(file);
+        }
+
+        // We just made sure it's there
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    fn write_record(&mut self, packet_buf: &[u8], tick: u64) -> Result<()> {
+        let record_size = LOG_RECORD_HEADER_SIZE + packet_buf.len();
+        if (self.file_size > LOG_BINARY_HEADER_SIZE) && (self.file_size + record_size > ROTATION_CONFIG.max_file_size) {
+            self.rotate();
+        }
+
+        let record_header = LogRecordHeader::new(packet_buf.len() as u32, tick);
+        let file = self.ensure_open()?;
+        file.write_all(&record_header.to_bytes())?;
+        file.write_all(packet_buf)?;
+
+        self.file_size += record_size;
+        self.last_write_tick = tick;
+        Ok(())
+    }
+}
+
+struct Writers {
+    list: Vec<ProgramLogWriter>,
+    // Set while the system sleeps, when no file must be touched
+    suspended: bool,
+}
+
+static G_WRITERS: Mutex<Writers> = Mutex::new(Writers {
+    list: Vec::new(),
+    suspended: false,
+});
+
+fn close_least_recently_used(writers: &mut [ProgramLogWriter]) {
+    if let Some(writer) = writers
+        .iter_mut()
+        .filter(|writer| writer.file.is_some())
+        .min_by_key(|writer| writer.last_write_tick)
+    {
+        writer.file = None;
+    }
+}
+
+fn get_boot_index_path() -> String {
+    format!("{}/boot_index", BASE_LOG_DIR)
+}
+
+fn load_boot_index() -> Result<u32> {
+    let mut boot_index_file = fs::open_file(get_boot_index_path().as_str(), fs::FileOpenOption::Read())?;
+    let mut boot_index_buf = [0u8; 4];
+    boot_index_file.read_array(&mut boot_index_buf)?;
+    Ok(u32::from_le_bytes(boot_index_buf))
+}
+
+fn save_boot_index(boot_index: u32) -> Result<()> {
+    let boot_index_path = get_boot_index_path();
+    let _ = fs::remove_file(boot_index_path.as_str());
+
+    let mut boot_index_file = fs::open_file(
+        boot_index_path.as_str(),
+        fs::FileOpenOption::Create() | fs::FileOpenOption::Write(),
+    )?;
+    boot_index_file.write_all(&boot_index.to_le_bytes())
+}
+
+// Gets the boot index a log file name starts with
+fn parse_file_boot_index(file_name: &str) -> Option<u32> {
+    let (boot_index, _) = file_name.strip_suffix(BINLOG_EXTENSION)?.strip_suffix('.')?.split_once('-')?;
+    boot_index.parse().ok()
+}
+
+// Removes the files of every program left by boots older than the ones to keep, see RotationConfig::max_boot_count
+fn remove_old_boot_files(boot_index: u32) -> Result<()> {
+    for program_dir_name in list_dir(BASE_LOG_DIR, fs::DirectoryOpenMode::ReadDirectories())? {
+        // Skip the self-log directory
+        if !program_dir_name.starts_with("0x") {
+            continue;
+        }
+
+        let program_dir = format!("{}/{}", BASE_LOG_DIR, program_dir_name);
+        let Ok(file_names) = list_dir(program_dir.as_str(), fs::DirectoryOpenMode::ReadFiles()) else {
+            continue;
+        };
+        for file_name in file_names {
+            let Some(file_boot_index) = parse_file_boot_index(&file_name) else {
+                continue;
+            };
+            if boot_index.wrapping_sub(file_boot_index) >= ROTATION_CONFIG.max_boot_count {
+                let _ = fs::remove_file(format!("{}/{}", program_dir, file_name).as_str());
+            }
+        }
+
+        // Only goes away if no files were left (directories with files can't be removed)
+        let _ = fs::remove_directory(program_dir.as_str());
+    }
+    Ok(())
+}
+
+/// Picks the boot index used for this boot's log files and removes the ones from older boots, the base log directory must already exist
+pub fn initialize() -> Result<()> {
+    let boot_index = load_boot_index().map(|idx| idx.wrapping_add(1)).unwrap_or(0);
+    G_BOOT_INDEX.store(boot_index, Ordering::Relaxed);
+    save_boot_index(boot_index)?;
+    remove_old_boot_files(boot_index)
+}
+
+/// Appends a packet as a new record to the current log file of the given program
+pub fn write_packet(program_id: u64, packet_buf: &[u8]) -> Result<()> {
+    let tick = arm::get_system_tick();
+    let mut writers_guard = G_WRITERS.lock();
+    if writers_guard.suspended {
+        return Ok(());
+    }
+    let writers = &mut writers_guard.list;
+
+    let writer_idx = match writers.iter().position(|writer| writer.program_id == program_id) {
+        Some(idx) => idx,
+        None => {
+            writers.push(ProgramLogWriter::new(program_id));
+            writers.len() - 1
+        }
+    };
+
+    if writers[writer_idx].file.is_none() && (writers.iter().filter(|writer| writer.file.is_some()).count() >= MAX_OPEN_FILES) {
+        close_least_recently_used(writers);
+    }
+
+    let res = writers[writer_idx].write_record(packet_buf, tick);
+    if res.is_err() {
+        // Start over with a fresh file next time, since this one might have been left in a weird state
+        writers[writer_idx].rotate();
+    }
+    res
+}
+
+/// Writes out whatever the open files still hold
+pub fn flush() {
+    let mut writers = G_WRITERS.lock();
+    for file in writers.list.iter_mut().filter_map(|writer| writer.file.as_mut()) {
+        // Closing them flushes them too, this just makes sure nothing is left for then
+        let _ = file.flush();
+    }
+}
+
+/// Closes every open file (which flushes them) and blocks any further writes until `resume` is called
+pub fn suspend() {
+    let mut writers = G_WRITERS.lock();
+    writers.suspended = true;
+    for writer in writers.list.iter_mut() {
+        writer.reopen_on_resume = writer.file.take().is_some();
+    }
+}
+
+/// Reopens the files closed by `suspend` and allows writes again
+pub fn resume() {
+    let mut writers = G_WRITERS.lock();
+    writers.suspended = false;
+    for writer in writers.list.iter_mut().filter(|writer| writer.reopen_on_resume) {
+        writer.reopen_on_resume = false;
+        // If this fails it will just be retried on the next write
+        let _ = writer.ensure_open();
+    }
+}
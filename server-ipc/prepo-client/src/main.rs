@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use core::fmt::Write;
+use core::panic;
+
+use nx::diag::abort;
+use nx::diag::log::lm::LmLogger;
+use nx::fs;
+use nx::svc;
+use nx::util;
+use nx::version;
+
+use prepo_ipc::IPrepoServiceClient;
+
+nx::rrt0_define_module_name!("prepo-client");
+
+const STATUS_LOG_PATH: &str = "sdmc:/prepo-client.log";
+
+#[no_mangle]
+pub fn initialize_heap(hbl_heap: util::PointerAndSize) -> util::PointerAndSize {
+    if hbl_heap.is_valid() {
+        hbl_heap
+    } else {
+        let heap_size: usize = 0x10000000;
+        let heap_address = svc::set_heap_size(heap_size).unwrap();
+        util::PointerAndSize::new(heap_address, heap_size)
+    }
+}
+
+#[no_mangle]
+pub fn main() {
+    fs::initialize_fspsrv_session().expect("Error starting filesystem services");
+    fs::mount_sd_card("sdmc").expect("Failed to mount sd card");
+
+    // Admin commands are the ones telling about the whole report storage and transmission, not just our own reports
+    let admin_service_name = prepo_ipc::get_admin_service_name();
+    let mut admin = prepo_ipc::open_service(admin_service_name).expect("Error opening prepo admin service");
+
+    let _ = fs::remove_file(STATUS_LOG_PATH);
+    let mut status_log = fs::open_file(
+        STATUS_LOG_PATH,
+        fs::FileOpenOption::Create() | fs::FileOpenOption::Write() | fs::FileOpenOption::Append(),
+    )
+    .unwrap();
+
+    let _ = writeln!(status_log, "Service: {}", admin_service_name);
+    let _ = writeln!(status_log, "Transmission status: {:?}", admin.get_transmission_status());
+    let _ = writeln!(status_log, "User agreement check enabled: {:?}", admin.is_user_agreement_check_enabled());
+
+    // Not every command is there on every firmware, calling the missing ones would just fail
+    let fw_version = version::get_version();
+    if fw_version >= version::Version::new(3, 0, 0) {
+        match admin.get_storage_usage() {
+            Ok((used_size, capacity)) => {
+                let _ = writeln!(status_log, "Storage usage: {} / {} bytes", used_size, capacity);
+            }
+            Err(e) => {
+                let _ = writeln!(status_log, "Storage usage: error {:#X}", e.get_value());
+            }
+        }
+    }
+    if fw_version >= version::Version::new(5, 0, 0) {
+        let _ = writeln!(status_log, "System session ID: {:?}", admin.get_system_session_id());
+    }
+    if fw_version >= version::Version::new(8, 0, 0) {
+        match admin.get_last_upload_error() {
+            Ok(upload_rc) => {
+                let _ = writeln!(status_log, "Last upload error: {:#X}", upload_rc);
+            }
+            Err(e) => {
+                let _ = writeln!(status_log, "Last upload error: error {:#X}", e.get_value());
+            }
+        }
+    }
+
+    fs::unmount_all();
+}
+
+#[panic_handler]
+fn panic_handler(info: &panic::PanicInfo) -> ! {
+    util::simple_panic_handler::<LmLogger>(info, abort::AbortLevel::FatalThrow())
+}
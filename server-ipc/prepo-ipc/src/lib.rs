@@ -0,0 +1,126 @@
+//! IPC interface of the `prepo` (Play Report) services, used both by the `prepo-mitm` example and by clients like `prepo-client`
+//!
+//! All `prepo` services expose this same interface, but each one only accepts the commands it's meant for: `prepo:u` and
+//! `prepo:m` the regular report ones, `prepo:s` the system report ones and `prepo:a`/`prepo:a2` the admin ones.
+//!
+//! `prepo-mitm` also hosts a custom service of its own, `prepo:st`, to manage the reports it saved.
+
+#![no_std]
+
+use nx::ipc::client::IClientObject;
+use nx::ipc::sf;
+use nx::result::Result;
+use nx::service::{self, sm};
+use nx::service::sm::IUserInterfaceClient;
+use nx::version;
+use nx::{ipc_sf_define_default_client_for_interface, ipc_sf_define_interface_trait};
+
+/// Admin service, only present in 1.0.0-5.1.0
+pub const ADMIN_SERVICE_NAME: &str = "prepo:a";
+/// Admin service replacing `prepo:a` since 6.0.0
+pub const ADMIN2_SERVICE_NAME: &str = "prepo:a2";
+pub const MANAGER_SERVICE_NAME: &str = "prepo:m";
+pub const USER_SERVICE_NAME: &str = "prepo:u";
+pub const SYSTEM_SERVICE_NAME: &str = "prepo:s";
+
+/// Gets the admin service name for the current firmware
+pub fn get_admin_service_name() -> &'static str {
+    if version::get_version() >= version::Version::new(6, 0, 0) {
+        ADMIN2_SERVICE_NAME
+    } else {
+        ADMIN_SERVICE_NAME
+    }
+}
+
+/// Values returned by `get_transmission_status`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TransmissionStatus {
+    /// Nothing waiting to be sent
+    Idle = 0,
+    /// Reports are waiting for the next transmission
+    Pending = 1,
+    /// Reports are being sent
+    Processing = 2,
+}
+
+/// Marks a process ID to be passed as it is by the kernel instead of being replaced by the sender's one
+///
+/// This is an Atmosphère extension meant for MitMs, so that the real service gets the original sender's process ID.
+pub const PASSTHROUGH_PROCESS_ID_MASK: u64 = 0xFFFE000000000000;
+
+/// Gets a process ID argument passing through the original sender's process ID, see `PASSTHROUGH_PROCESS_ID_MASK`
+pub const fn get_passthrough_process_id(process_id: u64) -> sf::ProcessId {
+    sf::ProcessId {
+        process_id: PASSTHROUGH_PROCESS_ID_MASK | process_id,
+    }
+}
+
+// Every save command takes the room (event ID) string in a pointer buffer and the MessagePack data in a map-alias buffer,
+// since those are the buffer kinds the real services expect. What changes across versions is the command IDs, and the
+// variants take either the sender's process ID or (for system reports) an application ID, optionally with a user ID
+// The admin commands filling buffers (statistics, throughput history, upload summary and report files) use layouts which
+// aren't covered here, so their data is just handed over as raw bytes
+
+ipc_sf_define_default_client_for_interface!(PrepoService);
+ipc_sf_define_interface_trait! {
+    trait PrepoService {
+        save_report_old [10100, version::VersionInterval::to(version::Version::new(5, 1, 0)), mut ]: (process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
+        save_report_with_user_old [10101, version::VersionInterval::to(version::Version::new(5, 1, 0)), mut ]: (user_id: u128, process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
+        save_report_old_2 [10102, version::VersionInterval::from_to(version::Version::new(6, 0, 0), version::Version::new(9, 2, 0)), mut ]: (process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
+        save_report_with_user_old_2 [10103, version::VersionInterval::from_to(version::Version::new(6, 0, 0), version::Version::new(9, 2, 0)), mut ]: (user_id: u128, process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
+        save_report [10104, version::VersionInterval::from(version::Version::new(10, 0, 0)), mut ]: (process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
+        save_report_with_user [10105, version::VersionInterval::from(version::Version::new(10, 0, 0)), mut ]: (user_id: u128, process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
+        request_immediate_transmission [10200, version::VersionInterval::all(), mut ]: () => () ();
+        get_transmission_status [10300, version::VersionInterval::all(), mut ]: () => (status: u32) (status: u32);
+        get_system_session_id [10400, version::VersionInterval::from(version::Version::new(5, 0, 0)), mut ]: () => (id: u64) (id: u64);
+        save_system_report [20100, version::VersionInterval::all(), mut ]: (application_id: u64, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
+        save_system_report_with_user [20101, version::VersionInterval::all(), mut ]: (user_id: u128, application_id: u64, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
+        set_operation_mode [20200, version::VersionInterval::all(), mut ]: (mode: i64) => () ();
+        clear_storage [30100, version::VersionInterval::all(), mut ]: () => () ();
+        clear_statistics [30200, version::VersionInterval::from(version::Version::new(3, 0, 0)), mut ]: () => () ();
+        get_storage_usage [30300, version::VersionInterval::from(version::Version::new(3, 0, 0)), mut ]: () => (used_size: i64, capacity: i64) (used_size: i64, capacity: i64);
+        get_statistics [30400, version::VersionInterval::from(version::Version::new(3, 0, 0)), mut ]: (statistics_buf: sf::OutMapAliasBuffer<'_, u8>) => () ();
+        get_throughput_history [30401, version::VersionInterval::from(version::Version::new(4, 0, 0)), mut ]: (history_buf: sf::OutMapAliasBuffer<'_, u8>) => (count: u32) (count: u32);
+        get_last_upload_error [30500, version::VersionInterval::from(version::Version::new(8, 0, 0)), mut ]: () => (upload_rc: u32) (upload_rc: u32);
+        get_application_upload_summary [30600, version::VersionInterval::from(version::Version::new(14, 0, 0)), mut ]: (summary_buf: sf::OutMapAliasBuffer<'_, u8>) => (count: u32) (count: u32);
+        is_user_agreement_check_enabled [40100, version::VersionInterval::all(), mut ]: () => (enabled: bool) (enabled: bool);
+        set_user_agreement_check_enabled [40101, version::VersionInterval::all(), mut ]: (enabled: bool) => () ();
+        read_all_report_files [90100, version::VersionInterval::from(version::Version::new(4, 0, 0)), mut ]: (report_files_buf: sf::OutMapAliasBuffer<'_, u8>) => (count: u32) (count: u32);
+    }
+}
+
+/// Opens a session to a `prepo` service by name
+///
+/// When called from the process mitm-ing that service, sm hands out the real service instead of looping back to it.
+pub fn open_service(name: &str) -> Result<PrepoService> {
+    let sm = service::new_named_port_object::<sm::UserInterface>()?;
+    let session_handle = sm.get_service_handle(sm::ServiceName::new(name))?;
+    Ok(PrepoService::new(sf::Session::from_handle(session_handle.handle)))
+}
+
+// Custom interface (not present in official prepo) hosted by prepo-mitm, so that it stays the only one writing its report store.
+// Reports are identified by their index entry fields, see prepo_report::store
+
+pub const STORE_SERVICE_NAME: &str = "prepo:st";
+
+ipc_sf_define_default_client_for_interface!(ReportStore);
+ipc_sf_define_interface_trait! {
+    trait ReportStore {
+        delete_report [0, version::VersionInterval::all(), mut ]: (program_id: u64, day: u32, counter: u32, is_system: bool) => () ();
+    }
+}
+
+impl service::IService for ReportStore {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new(STORE_SERVICE_NAME)
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
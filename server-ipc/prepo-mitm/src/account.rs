@@ -0,0 +1,91 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use nx::ipc::sf;
+use nx::result::*;
+use nx::service;
+use nx::service::sm;
+use nx::version;
+
+// Just the bits of the account services needed to get user nicknames
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct ProfileBase {
+    pub user_id: u128,
+    pub last_edit_timestamp: u64,
+    pub nickname: [u8; 0x20],
+}
+static_assertions::const_assert_eq!(core::mem::size_of::<ProfileBase>(), 0x38);
+
+impl ProfileBase {
+    pub fn get_nickname(&self) -> String {
+        let nickname_len = self.nickname.iter().position(|&b| b == 0).unwrap_or(self.nickname.len());
+        String::from_utf8_lossy(&self.nickname[..nickname_len]).into_owned()
+    }
+}
+
+ipc_sf_define_default_client_for_interface!(Profile);
+ipc_sf_define_interface_trait! {
+    trait Profile {
+        get_base [1, version::VersionInterval::all(), mut ]: () => (profile_base: ProfileBase) (profile_base: ProfileBase);
+    }
+}
+
+ipc_sf_define_default_client_for_interface!(AccountServiceForSystemService);
+ipc_sf_define_interface_trait! {
+    trait AccountServiceForSystemService {
+        get_profile [5, version::VersionInterval::all(), mut ]: (user_id: u128) => (profile: Profile) (profile: impl IProfileServer + 'static);
+    }
+}
+
+impl service::IService for AccountServiceForSystemService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("acc:u1")
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct UserInfo {
+    pub user_id: u128,
+    /// Not available if the user doesn't exist anymore (or the account service failed)
+    pub nickname: Option<String>,
+}
+
+/// Users already looked up by a session, so that every report from the same user doesn't hit the account service again
+pub struct UserCache {
+    users: Vec<UserInfo>,
+}
+
+impl UserCache {
+    pub const fn new() -> Self {
+        Self { users: Vec::new() }
+    }
+
+    pub fn get(&mut self, user_id: u128) -> &UserInfo {
+        let user_idx = match self.users.iter().position(|user| user.user_id == user_id) {
+            Some(user_idx) => user_idx,
+            None => {
+                // Failed lookups are cached too, they would just fail again
+                let nickname = get_nickname(user_id).ok();
+                self.users.push(UserInfo { user_id, nickname });
+                self.users.len() - 1
+            }
+        };
+        &self.users[user_idx]
+    }
+}
+
+fn get_nickname(user_id: u128) -> Result<String> {
+    let mut acc = service::new_service_object::<AccountServiceForSystemService>()?;
+    let mut profile = acc.get_profile(user_id)?;
+    Ok(profile.get_base()?.get_nickname())
+}
@@ -18,9 +18,15 @@ use nx::ipc::server;
 use nx::util;
 use nx::version;
 
+mod account;
+mod policy;
 mod prepo;
+mod store;
+mod time;
+mod transmission;
 
-const CUSTOM_HEAP_SIZE: usize = 0x4000;
+// Policy rules and sessions to the real services need a bit more room than just logging reports
+const CUSTOM_HEAP_SIZE: usize = 0x8000;
 static mut CUSTOM_HEAP: [u8; CUSTOM_HEAP_SIZE] = [0; CUSTOM_HEAP_SIZE];
 
 #[no_mangle]
@@ -39,15 +45,15 @@ pub fn main() {
     fs::initialize_fspsrv_session().expect("Error starting filesystem services");
     fs::mount_sd_card("sdmc").expect("Failed to mount sd card");
 
+    policy::load();
+    store::load();
+
     let mut manager = Manager::new().unwrap();
 
     // Services present in all versions so far
     manager.register_mitm_service_server::<prepo::PrepoServiceMitmServer<{ prepo::SERVICE_TYPE_MANAGER }>>().unwrap();
     manager.register_mitm_service_server::<prepo::PrepoServiceMitmServer<{ prepo::SERVICE_TYPE_USER }>>().unwrap();
 
-    // TODO: fix this mitm, keeps getting stuck on boot when am accesses it
-    // manager.register_mitm_service_server::<prepo::PrepoService<{ prepo::SERVICE_TYPE_SYSTEM }>>().unwrap();
-
     if version::get_version() > version::Version::new(5, 1, 0) {
         // 6.0.0 -> (...) has "prepo:a2"
         manager.register_mitm_service_server::<prepo::PrepoServiceMitmServer<{ prepo::SERVICE_TYPE_ADMIN2 }>>().unwrap();
@@ -56,7 +62,13 @@ pub fn main() {
         manager.register_mitm_service_server::<prepo::PrepoServiceMitmServer<{ prepo::SERVICE_TYPE_ADMIN }>>().unwrap();
     }
 
+    manager.register_service_server::<prepo::ReportStoreServer>().unwrap();
+
     diag_log!(LmLogger { LogSeverity::Info, true } => "Looping...\n");
+
+    // Sessions opened while booting (like am's one) are left to the real service, see prepo_policy::session
+    // Registered last since nothing may get logged from here until boot is done
+    manager.register_mitm_service_server::<prepo::PrepoServiceMitmServer<{ prepo::SERVICE_TYPE_SYSTEM }>>().unwrap();
     manager.loop_process().unwrap();
 
     fs::unmount_all();
@@ -0,0 +1,45 @@
+use nx::diag::log::{lm::LmLogger, LogSeverity};
+use nx::fs;
+use nx::sync::Mutex;
+
+use prepo_policy::{PolicyAction, PolicyConfig, RedactionConfig, TransmissionMode, POLICY_CONFIG_PATH};
+
+// Without a config file every report gets saved, without forwarding it
+static G_POLICY: Mutex<PolicyConfig> = Mutex::new(PolicyConfig::new());
+
+/// Loads the policy config from the SD card, the SD card must already be mounted
+pub fn load() {
+    // A missing config file is fine, it just means using the default policy
+    let Ok(mut config_file) = fs::open_file(POLICY_CONFIG_PATH, fs::FileOpenOption::Read()) else {
+        return;
+    };
+    let config_str = match sd_config::read_config(|chunk_buf| config_file.read_array(chunk_buf)) {
+        Ok(config_str) => config_str,
+        Err(e) => {
+            diag_log!(LmLogger { LogSeverity::Warn, true } => "Ignoring unreadable policy config {}: {}\n", POLICY_CONFIG_PATH, e);
+            return;
+        }
+    };
+
+    match PolicyConfig::parse(config_str.as_str()) {
+        Ok(config) => {
+            diag_log!(LmLogger { LogSeverity::Info, true } => "Loaded policy config with {} rules (default action: {}, transmission: {}, redaction: {})\n", config.rules.len(), config.default_action.name(), config.transmission_mode.name(), config.redaction.is_some());
+            *G_POLICY.lock() = config;
+        }
+        Err(e) => {
+            diag_log!(LmLogger { LogSeverity::Warn, true } => "Ignoring invalid policy config {}: {}\n", POLICY_CONFIG_PATH, e);
+        }
+    }
+}
+
+pub fn get_action(room: &[u8], program_id: u64) -> PolicyAction {
+    G_POLICY.lock().get_action(room, program_id)
+}
+
+pub fn get_transmission_mode() -> TransmissionMode {
+    G_POLICY.lock().transmission_mode
+}
+
+pub fn get_redaction() -> Option<RedactionConfig> {
+    G_POLICY.lock().redaction.clone()
+}
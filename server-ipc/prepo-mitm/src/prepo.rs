@@ -1,31 +1,25 @@
+use alloc::string::String;
+
 use nx::diag::log::{lm::LmLogger, LogSeverity};
-use nx::fs::{self, Write};
 use nx::ipc::server;
 use nx::ipc::sf;
 use nx::ipc::sf::sm;
 use nx::result::*;
-use nx::version;
+use nx::sync::Mutex;
 
+use prepo_ipc::{IPrepoServiceClient, IPrepoServiceServer, IReportStoreServer, PrepoService};
 
+use prepo_policy::session::{self, BootState, ServiceKind, SessionDecision};
+use prepo_policy::{PolicyAction, TransmissionMode};
 
-// TODO: move this interface to nx libs (and finish it)...
+use prepo_report::msgpack;
+use prepo_report::report::{ReportKind, ReportMetadata};
+use prepo_report::store::IndexEntry;
 
-ipc_sf_define_default_client_for_interface!(PrepoService);
-ipc_sf_define_interface_trait! {
-    trait PrepoService {
-        save_report_old [10100, version::VersionInterval::all(), mut ]: (process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
-        save_report_with_user_old [10101, version::VersionInterval::all(), mut ]: (user_id: u128, process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
-        save_report_old_2 [10102, version::VersionInterval::all(), mut ]: (process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
-        save_report_with_user_old_2 [10103, version::VersionInterval::all(), mut ]: (user_id: u128, process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
-        save_report [10104, version::VersionInterval::all(), mut ]: (process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
-        save_report_with_user [10105, version::VersionInterval::all(), mut ]: (user_id: u128, process_id: sf::ProcessId, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
-        request_immediate_transmission [10200, version::VersionInterval::all(), mut ]: () => () ();
-        get_transmission_status [10300, version::VersionInterval::all(), mut ]: () => (status: u32) (status: u32);
-        get_system_session_id [10400, version::VersionInterval::all(), mut ]: () => (id: u64) (id: u64);
-        save_system_report [20100, version::VersionInterval::all(), mut ]: (application_id: u64, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
-        save_system_report_with_user [20101, version::VersionInterval::all(), mut ]: (user_id: u128, application_id: u64, room_str_buf: sf::InPointerBuffer<'_, u8>, report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>) => () ();
-    }
-}
+use crate::account::UserCache;
+use crate::policy;
+use crate::store;
+use crate::transmission;
 
 pub const SERVICE_TYPE_ADMIN: u32 = 1;
 pub const SERVICE_TYPE_ADMIN2: u32 = 2;
@@ -34,70 +28,100 @@ pub const SERVICE_TYPE_USER: u32 = 4;
 pub const SERVICE_TYPE_SYSTEM: u32 = 5;
 
 #[inline]
-fn get_service_name<const S: u32>() -> &'static str {
+fn get_service_kind<const S: u32>() -> ServiceKind {
     match S {
-        SERVICE_TYPE_ADMIN => "prepo:a",
-        SERVICE_TYPE_ADMIN2 => "prepo:a2",
-        SERVICE_TYPE_MANAGER => "prepo:m",
-        SERVICE_TYPE_USER => "prepo:u",
-        SERVICE_TYPE_SYSTEM => "prepo:s",
+        SERVICE_TYPE_ADMIN => ServiceKind::Admin,
+        SERVICE_TYPE_ADMIN2 => ServiceKind::Admin2,
+        SERVICE_TYPE_MANAGER => ServiceKind::Manager,
+        SERVICE_TYPE_USER => ServiceKind::User,
+        SERVICE_TYPE_SYSTEM => ServiceKind::System,
         _ => panic!("Invalid service mode."),
     }
 }
 
-#[derive(Debug)]
-pub enum ReportKind {
-    Normal,
-    System,
+#[inline]
+fn get_service_name<const S: u32>() -> &'static str {
+    match S {
+        SERVICE_TYPE_ADMIN => prepo_ipc::ADMIN_SERVICE_NAME,
+        SERVICE_TYPE_ADMIN2 => prepo_ipc::ADMIN2_SERVICE_NAME,
+        SERVICE_TYPE_MANAGER => prepo_ipc::MANAGER_SERVICE_NAME,
+        SERVICE_TYPE_USER => prepo_ipc::USER_SERVICE_NAME,
+        SERVICE_TYPE_SYSTEM => prepo_ipc::SYSTEM_SERVICE_NAME,
+        _ => panic!("Invalid service mode."),
+    }
 }
 
 pub struct ReportContext<'a> {
     pub kind: ReportKind,
     pub process_id: Option<u64>,
     pub application_id: Option<u64>,
-    pub room_str_buf: sf::InPointerBuffer<'a, u8>,
-    pub report_msgpack_buf: sf::InMapAliasBuffer<'a, u8>,
+    pub room_str: String,
+    pub report_msgpack: &'a [u8],
     pub user_id: Option<u128>,
 }
 
+impl<'a> ReportContext<'a> {
+    // Only the data is borrowed (not the buffer objects themselves), so they can still be moved when forwarding the command
+    fn new(
+        kind: ReportKind,
+        process_id: Option<u64>,
+        application_id: Option<u64>,
+        user_id: Option<u128>,
+        room_str_buf: &sf::InPointerBuffer<'a, u8>,
+        report_msgpack_buf: &sf::InMapAliasBuffer<'a, u8>,
+    ) -> Self {
+        Self {
+            kind,
+            process_id,
+            application_id,
+            room_str: room_str_buf.get_string(),
+            report_msgpack: unsafe {
+                core::slice::from_raw_parts(report_msgpack_buf.get_address(), report_msgpack_buf.get_size())
+            },
+            user_id,
+        }
+    }
+}
+
 pub struct PrepoServiceMitmServer<const S: u32> {
-    _info: sm::mitm::MitmProcessInfo,
+    info: sm::mitm::MitmProcessInfo,
+    // Opened the first time a report needs to be forwarded
+    real_service: Option<PrepoService>,
+    users: UserCache,
 }
 
 impl<const S: u32> PrepoServiceMitmServer<S> {
-    fn process_report(&self, ctx: ReportContext) {
-        let mut idx = 1;
-        let mut msgpack_path = format!(
-            "sdmc:/prepo/{:#X}-{:#X}-{:?}.msgpack",
-            ctx.process_id.unwrap_or(0),
-            ctx.application_id.unwrap_or(0),
-            ctx.kind
-        );
-        while fs::get_entry_type(msgpack_path.as_str()).is_ok() {
-            msgpack_path = format!(
-                "sdmc:/prepo/{:#X}-{:#X}-{:?}-{}.msgpack",
-                ctx.process_id.unwrap_or(0),
-                ctx.application_id.unwrap_or(0),
-                ctx.kind,
-                idx
-            );
-            idx += 1;
-        }
+    fn record_report(&mut self, ctx: &ReportContext) {
+        let redaction = policy::get_redaction();
+        let hash_user_ids = redaction.as_ref().is_some_and(|redaction| redaction.hash_user_ids);
+
+        // No point in asking for nicknames which aren't going to be saved
+        let user = if hash_user_ids {
+            None
+        } else {
+            ctx.user_id.map(|user_id| self.users.get(user_id).clone())
+        };
+        let metadata = ReportMetadata {
+            kind: ctx.kind,
+            room: ctx.room_str.as_bytes(),
+            process_id: ctx.process_id,
+            application_id: ctx.application_id,
+            user_id: ctx.user_id,
+            user_nickname: user.as_ref().and_then(|user| user.nickname.as_deref()),
+        };
 
-        if let Ok(mut msgpack_file) = fs::open_file(
-            msgpack_path.as_str(),
-            fs::FileOpenOption::Create() | fs::FileOpenOption::Write() | fs::FileOpenOption::Append(),
-        ) {
-            msgpack_file
-                .write_all(ctx.report_msgpack_buf.as_slice().unwrap())
-                .unwrap();
+        if let Err(e) = store::save_report(self.info.program_id.0, &metadata, ctx.report_msgpack, redaction.as_ref()) {
+            diag_log!(LmLogger { LogSeverity::Error, true } => "Unable to save report: {:#X}\n", e.get_value());
         }
 
         diag_log!(LmLogger { LogSeverity::Info, true } => "\nREPORT START\n");
 
         diag_log!(LmLogger { LogSeverity::Info, true } => "Kind: {:?}\n", ctx.kind);
-        diag_log!(LmLogger { LogSeverity::Info, true } => "Room: {}\n", ctx.room_str_buf.get_string());
-        diag_log!(LmLogger { LogSeverity::Info, true } => "Msgpack size: {}\n", ctx.report_msgpack_buf.get_size());
+        diag_log!(LmLogger { LogSeverity::Info, true } => "Room: {}\n", ctx.room_str);
+        diag_log!(LmLogger { LogSeverity::Info, true } => "Msgpack size: {}\n", ctx.report_msgpack.len());
+        if let Err(e) = msgpack::validate(ctx.report_msgpack) {
+            diag_log!(LmLogger { LogSeverity::Warn, true } => "Invalid msgpack: {}\n", e);
+        }
 
         if let Some(process_id) = ctx.process_id {
             diag_log!(LmLogger { LogSeverity::Info, true } => "Process (ID) sending the report: {:#X}\n", process_id);
@@ -105,85 +129,59 @@ impl<const S: u32> PrepoServiceMitmServer<S> {
         if let Some(application_id) = ctx.application_id {
             diag_log!(LmLogger { LogSeverity::Info, true } => "Application (ID) sending the report: {:#X}\n", application_id);
         }
-        if let Some(_user_id) = ctx.user_id {
-            let user_name = "TODOUser";
-            diag_log!(LmLogger { LogSeverity::Info, true } => "User sending the report: {}\n", user_name);
+        // Logs can end up shared too, so they get the same hash as the saved report
+        match (ctx.user_id, redaction.as_ref()) {
+            (Some(user_id), Some(redaction)) if hash_user_ids => {
+                let user_hash = redaction.get_rules().hash(&user_id.to_le_bytes());
+                diag_log!(LmLogger { LogSeverity::Info, true } => "User sending the report: hash:{:016x}\n", user_hash);
+            }
+            _ => {
+                if let Some(user) = user {
+                    let user_name = user.nickname.as_deref().unwrap_or("<unknown>");
+                    diag_log!(LmLogger { LogSeverity::Info, true } => "User sending the report: {} (0x{:032X})\n", user_name, user.user_id);
+                }
+            }
         }
 
         diag_log!(LmLogger { LogSeverity::Info, true } => "REPORT END\n");
     }
 
-    fn save_report_impl(
-        &self,
-        process_id: sf::ProcessId,
-        room_str_buf: sf::InPointerBuffer<'_, u8>,
-        report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
-    ) -> Result<()> {
-        let ctx = ReportContext {
-            kind: ReportKind::Normal,
-            process_id: Some(process_id.process_id),
-            application_id: None,
-            room_str_buf: room_str_buf,
-            report_msgpack_buf: report_msgpack_buf,
-            user_id: None,
-        };
-        self.process_report(ctx);
-        Ok(())
-    }
+    fn get_real_service(&mut self) -> Result<&mut PrepoService> {
+        if self.real_service.is_none() {
+            self.real_service = Some(prepo_ipc::open_service(get_service_name::<S>())?);
+        }
 
-    fn save_report_with_user_impl(
-        &self,
-        user_id: u128,
-        process_id: sf::ProcessId,
-        room_str_buf: sf::InPointerBuffer<'_, u8>,
-        report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
-    ) -> Result<()> {
-        let ctx = ReportContext {
-            kind: ReportKind::Normal,
-            process_id: Some(process_id.process_id),
-            application_id: None,
-            room_str_buf: room_str_buf,
-            report_msgpack_buf: report_msgpack_buf,
-            user_id: Some(user_id),
-        };
-        self.process_report(ctx);
-        Ok(())
+        // We just made sure it's there
+        Ok(self.real_service.as_mut().unwrap())
     }
 
-    fn save_system_report_impl(
-        &self,
-        application_id: u64,
-        room_str_buf: sf::InPointerBuffer<'_, u8>,
-        report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
-    ) -> Result<()> {
-        let ctx = ReportContext {
-            kind: ReportKind::System,
-            process_id: None,
-            application_id: Some(application_id),
-            room_str_buf: room_str_buf,
-            report_msgpack_buf: report_msgpack_buf,
-            user_id: None,
-        };
-        self.process_report(ctx);
-        Ok(())
-    }
+    /// Applies the policy for a report, where `forward` sends the same command to the real service
+    ///
+    /// Forwarded commands carrying a process ID must pass the original one through (see `prepo_ipc::get_passthrough_process_id`),
+    /// otherwise the kernel fills in ours and the real service attributes the report to us.
+    fn handle_report(&mut self, ctx: ReportContext, forward: impl FnOnce(&mut PrepoService) -> Result<()>) -> Result<()> {
+        let action = policy::get_action(ctx.room_str.as_bytes(), self.info.program_id.0);
+        if action == PolicyAction::Drop {
+            diag_log!(LmLogger { LogSeverity::Info, true } => "Dropping report for room '{}'\n", ctx.room_str);
+        }
 
-    fn save_system_report_with_user_impl(
-        &self,
-        user_id: u128,
-        application_id: u64,
-        room_str_buf: sf::InPointerBuffer<'_, u8>,
-        report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
-    ) -> Result<()> {
-        let ctx = ReportContext {
-            kind: ReportKind::System,
-            process_id: None,
-            application_id: Some(application_id),
-            room_str_buf: room_str_buf,
-            report_msgpack_buf: report_msgpack_buf,
-            user_id: Some(user_id),
-        };
-        self.process_report(ctx);
+        if action.should_record() {
+            self.record_report(&ctx);
+        }
+
+        // As far as the sender knows the report is now waiting to be sent, whatever we did with it
+        transmission::on_report_saved();
+
+        if action.should_forward() {
+            match self.get_real_service() {
+                // The real result is what the sender would have got without us
+                Ok(real_service) => return forward(real_service),
+                Err(e) => {
+                    // Don't break the sender just because we can't reach the real service
+                    diag_log!(LmLogger { LogSeverity::Error, true } => "Unable to open real '{}' to forward a report: {:#X}\n", get_service_name::<S>(), e.get_value());
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -195,7 +193,8 @@ impl<const S: u32> IPrepoServiceServer for PrepoServiceMitmServer<S> {
         room_str_buf: sf::InPointerBuffer<'_, u8>,
         report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
     ) -> Result<()> {
-        self.save_report_impl(process_id, room_str_buf, report_msgpack_buf)
+        let ctx = ReportContext::new(ReportKind::Normal, Some(process_id.process_id), None, None, &room_str_buf, &report_msgpack_buf);
+        self.handle_report(ctx, move |real_service| real_service.save_report_old(prepo_ipc::get_passthrough_process_id(process_id.process_id), room_str_buf, report_msgpack_buf))
     }
 
     fn save_report_with_user_old(
@@ -205,7 +204,8 @@ impl<const S: u32> IPrepoServiceServer for PrepoServiceMitmServer<S> {
         room_str_buf: sf::InPointerBuffer<'_, u8>,
         report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
     ) -> Result<()> {
-        self.save_report_with_user_impl(user_id, process_id, room_str_buf, report_msgpack_buf)
+        let ctx = ReportContext::new(ReportKind::Normal, Some(process_id.process_id), None, Some(user_id), &room_str_buf, &report_msgpack_buf);
+        self.handle_report(ctx, move |real_service| real_service.save_report_with_user_old(user_id, prepo_ipc::get_passthrough_process_id(process_id.process_id), room_str_buf, report_msgpack_buf))
     }
 
     fn save_report_old_2(
@@ -214,7 +214,8 @@ impl<const S: u32> IPrepoServiceServer for PrepoServiceMitmServer<S> {
         room_str_buf: sf::InPointerBuffer<'_, u8>,
         report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
     ) -> Result<()> {
-        self.save_report_impl(process_id, room_str_buf, report_msgpack_buf)
+        let ctx = ReportContext::new(ReportKind::Normal, Some(process_id.process_id), None, None, &room_str_buf, &report_msgpack_buf);
+        self.handle_report(ctx, move |real_service| real_service.save_report_old_2(prepo_ipc::get_passthrough_process_id(process_id.process_id), room_str_buf, report_msgpack_buf))
     }
 
     fn save_report_with_user_old_2(
@@ -224,7 +225,8 @@ impl<const S: u32> IPrepoServiceServer for PrepoServiceMitmServer<S> {
         room_str_buf: sf::InPointerBuffer<'_, u8>,
         report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
     ) -> Result<()> {
-        self.save_report_with_user_impl(user_id, process_id, room_str_buf, report_msgpack_buf)
+        let ctx = ReportContext::new(ReportKind::Normal, Some(process_id.process_id), None, Some(user_id), &room_str_buf, &report_msgpack_buf);
+        self.handle_report(ctx, move |real_service| real_service.save_report_with_user_old_2(user_id, prepo_ipc::get_passthrough_process_id(process_id.process_id), room_str_buf, report_msgpack_buf))
     }
 
     fn save_report(
@@ -233,7 +235,8 @@ impl<const S: u32> IPrepoServiceServer for PrepoServiceMitmServer<S> {
         room_str_buf: sf::InPointerBuffer<'_, u8>,
         report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
     ) -> Result<()> {
-        self.save_report_impl(process_id, room_str_buf, report_msgpack_buf)
+        let ctx = ReportContext::new(ReportKind::Normal, Some(process_id.process_id), None, None, &room_str_buf, &report_msgpack_buf);
+        self.handle_report(ctx, move |real_service| real_service.save_report(prepo_ipc::get_passthrough_process_id(process_id.process_id), room_str_buf, report_msgpack_buf))
     }
 
     fn save_report_with_user(
@@ -243,22 +246,37 @@ impl<const S: u32> IPrepoServiceServer for PrepoServiceMitmServer<S> {
         room_str_buf: sf::InPointerBuffer<'_, u8>,
         report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
     ) -> Result<()> {
-        self.save_report_with_user_impl(user_id, process_id, room_str_buf, report_msgpack_buf)
+        let ctx = ReportContext::new(ReportKind::Normal, Some(process_id.process_id), None, Some(user_id), &room_str_buf, &report_msgpack_buf);
+        self.handle_report(ctx, move |real_service| real_service.save_report_with_user(user_id, prepo_ipc::get_passthrough_process_id(process_id.process_id), room_str_buf, report_msgpack_buf))
     }
 
     fn request_immediate_transmission(&mut self) -> Result<()> {
         diag_log!(LmLogger { LogSeverity::Info, true } => "\nRequesting immediate transmission...\n");
-        Ok(())
+        match policy::get_transmission_mode() {
+            TransmissionMode::Emulate => {
+                transmission::request_immediate_transmission();
+                Ok(())
+            }
+            TransmissionMode::Forward => self.get_real_service()?.request_immediate_transmission(),
+        }
     }
 
     fn get_transmission_status(&mut self) -> Result<u32> {
-        diag_log!(LmLogger { LogSeverity::Info, true } => "\nSending transmission status...\n");
-        Ok(0)
+        let status = match policy::get_transmission_mode() {
+            TransmissionMode::Emulate => transmission::get_status() as u32,
+            TransmissionMode::Forward => self.get_real_service()?.get_transmission_status()?,
+        };
+        diag_log!(LmLogger { LogSeverity::Info, true } => "\nSending transmission status: {}\n", status);
+        Ok(status)
     }
 
     fn get_system_session_id(&mut self) -> Result<u64> {
-        diag_log!(LmLogger { LogSeverity::Info, true } => "\nSending session ID...\n");
-        Ok(0xBABABEBE)
+        let session_id = match policy::get_transmission_mode() {
+            TransmissionMode::Emulate => transmission::get_session_id(),
+            TransmissionMode::Forward => self.get_real_service()?.get_system_session_id()?,
+        };
+        diag_log!(LmLogger { LogSeverity::Info, true } => "\nSending session ID: {:#X}\n", session_id);
+        Ok(session_id)
     }
 
     fn save_system_report(
@@ -267,7 +285,8 @@ impl<const S: u32> IPrepoServiceServer for PrepoServiceMitmServer<S> {
         room_str_buf: sf::InPointerBuffer<'_, u8>,
         report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
     ) -> Result<()> {
-        self.save_system_report_impl(application_id, room_str_buf, report_msgpack_buf)
+        let ctx = ReportContext::new(ReportKind::System, None, Some(application_id), None, &room_str_buf, &report_msgpack_buf);
+        self.handle_report(ctx, move |real_service| real_service.save_system_report(application_id, room_str_buf, report_msgpack_buf))
     }
 
     fn save_system_report_with_user(
@@ -277,12 +296,60 @@ impl<const S: u32> IPrepoServiceServer for PrepoServiceMitmServer<S> {
         room_str_buf: sf::InPointerBuffer<'_, u8>,
         report_msgpack_buf: sf::InMapAliasBuffer<'_, u8>,
     ) -> Result<()> {
-        self.save_system_report_with_user_impl(
-            user_id,
-            application_id,
-            room_str_buf,
-            report_msgpack_buf,
-        )
+        let ctx = ReportContext::new(ReportKind::System, None, Some(application_id), Some(user_id), &room_str_buf, &report_msgpack_buf);
+        self.handle_report(ctx, move |real_service| {
+            real_service.save_system_report_with_user(user_id, application_id, room_str_buf, report_msgpack_buf)
+        })
+    }
+
+    // The commands below only matter to the system (or admin tools), so they're just passed to the real service
+
+    fn set_operation_mode(&mut self, mode: i64) -> Result<()> {
+        diag_log!(LmLogger { LogSeverity::Info, true } => "\nSetting operation mode: {}\n", mode);
+        self.get_real_service()?.set_operation_mode(mode)
+    }
+
+    fn clear_storage(&mut self) -> Result<()> {
+        diag_log!(LmLogger { LogSeverity::Info, true } => "\nClearing report storage...\n");
+        self.get_real_service()?.clear_storage()
+    }
+
+    fn clear_statistics(&mut self) -> Result<()> {
+        diag_log!(LmLogger { LogSeverity::Info, true } => "\nClearing statistics...\n");
+        self.get_real_service()?.clear_statistics()
+    }
+
+    fn get_storage_usage(&mut self) -> Result<(i64, i64)> {
+        self.get_real_service()?.get_storage_usage()
+    }
+
+    fn get_statistics(&mut self, statistics_buf: sf::OutMapAliasBuffer<'_, u8>) -> Result<()> {
+        self.get_real_service()?.get_statistics(statistics_buf)
+    }
+
+    fn get_throughput_history(&mut self, history_buf: sf::OutMapAliasBuffer<'_, u8>) -> Result<u32> {
+        self.get_real_service()?.get_throughput_history(history_buf)
+    }
+
+    fn get_last_upload_error(&mut self) -> Result<u32> {
+        self.get_real_service()?.get_last_upload_error()
+    }
+
+    fn get_application_upload_summary(&mut self, summary_buf: sf::OutMapAliasBuffer<'_, u8>) -> Result<u32> {
+        self.get_real_service()?.get_application_upload_summary(summary_buf)
+    }
+
+    fn is_user_agreement_check_enabled(&mut self) -> Result<bool> {
+        self.get_real_service()?.is_user_agreement_check_enabled()
+    }
+
+    fn set_user_agreement_check_enabled(&mut self, enabled: bool) -> Result<()> {
+        diag_log!(LmLogger { LogSeverity::Info, true } => "\nSetting user agreement check: {}\n", enabled);
+        self.get_real_service()?.set_user_agreement_check_enabled(enabled)
+    }
+
+    fn read_all_report_files(&mut self, report_files_buf: sf::OutMapAliasBuffer<'_, u8>) -> Result<u32> {
+        self.get_real_service()?.read_all_report_files(report_files_buf)
     }
 }
 
@@ -300,18 +367,81 @@ impl<const S: u32> server::ISessionObject for PrepoServiceMitmServer<S> {
 impl<const S: u32> server::IMitmServerObject for PrepoServiceMitmServer<S> {
     fn new(info: sm::mitm::MitmProcessInfo) -> Self {
         diag_log!(LmLogger { LogSeverity::Info, true } => "Opened '{}' from program {:#X}\n", get_service_name::<S>(), info.program_id.0);
-        Self { _info: info }
+        Self {
+            info,
+            real_service: None,
+            users: UserCache::new(),
+        }
     }
 }
 
+// Shared by all the services, see prepo_policy::session for why this matters
+static G_BOOT_STATE: Mutex<BootState> = Mutex::new(BootState::Booting);
+
+fn update_boot_state(program_id: u64) -> BootState {
+    let mut boot_state = G_BOOT_STATE.lock();
+    *boot_state = boot_state.update(program_id);
+    *boot_state
+}
+
 impl<const S: u32> server::IMitmService for PrepoServiceMitmServer<S> {
     fn get_name() -> sm::ServiceName {
         let name = get_service_name::<S>();
-        diag_log!(LmLogger { LogSeverity::Info, true } => "Registering mitm at service '{}'...\n", name);
+        // am might already be waiting on prepo:s, which is why we stay away from lm until boot is done
+        if get_service_kind::<S>() != ServiceKind::System {
+            diag_log!(LmLogger { LogSeverity::Info, true } => "Registering mitm at service '{}'...\n", name);
+        }
         sm::ServiceName::new(name)
     }
 
-    fn should_mitm(_info: sm::mitm::MitmProcessInfo) -> bool {
-        true
+    fn should_mitm(info: sm::mitm::MitmProcessInfo) -> bool {
+        let boot_state = update_boot_state(info.program_id.0);
+        // Nothing gets logged for forwarded sessions, for the same reason as above
+        session::get_session_decision(get_service_kind::<S>(), boot_state) == SessionDecision::Mitm
+    }
+}
+
+// Custom service (see prepo_ipc::ReportStore) letting clients like prepo-viewer delete reports without writing the store themselves
+
+pub struct ReportStoreServer;
+
+impl IReportStoreServer for ReportStoreServer {
+    fn delete_report(&mut self, program_id: u64, day: u32, counter: u32, is_system: bool) -> Result<()> {
+        let report = IndexEntry {
+            day,
+            program_id,
+            counter,
+            kind: if is_system { ReportKind::System } else { ReportKind::Normal },
+            size: 0,
+        };
+        diag_log!(LmLogger { LogSeverity::Info, true } => "Deleting report {}...\n", report.get_path());
+        store::delete_report(&report)
+    }
+}
+
+impl server::ISessionObject for ReportStoreServer {
+    fn try_handle_request_by_id(
+        &mut self,
+        req_id: u32,
+        protocol: nx::ipc::CommandProtocol,
+        server_ctx: &mut server::ServerContext,
+    ) -> Option<Result<()>> {
+        <Self as IReportStoreServer>::try_handle_request_by_id(self, req_id, protocol, server_ctx)
+    }
+}
+
+impl server::IServerObject for ReportStoreServer {
+    fn new() -> Self {
+        Self
+    }
+}
+
+impl server::IService for ReportStoreServer {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new(prepo_ipc::STORE_SERVICE_NAME)
+    }
+
+    fn get_max_sesssions() -> i32 {
+        2
     }
 }
@@ -0,0 +1,338 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use nx::diag::log::{lm::LmLogger, LogSeverity};
+use nx::fs::{self, Write};
+use nx::result::*;
+use nx::sync::Mutex;
+
+use prepo_policy::RedactionConfig;
+use prepo_report::report::{write_report_json, write_report_json_redacted, ReportMetadata};
+use prepo_report::store::{get_day, DayDir, IndexEntry, IndexLine, MAX_INDEX_LINE_LEN};
+
+use crate::time;
+
+// See prepo_report::store for the layout of the store
+pub const STORE_PATH: &str = "sdmc:/prepo";
+const INDEX_PATH: &str = "sdmc:/prepo/index.txt";
+const INDEX_TMP_PATH: &str = "sdmc:/prepo/index.tmp";
+
+/// Total size of the saved reports before the oldest ones start getting removed
+pub const MAX_STORE_SIZE: u64 = 0x4000000;
+
+// Once over the limit, reports are removed until this size is reached, so that the index isn't rewritten for every new report
+const EVICTION_TARGET_SIZE: u64 = MAX_STORE_SIZE / 10 * 9;
+
+// Reports are written through this small buffer, since lots of tiny writes to the SD card are really slow
+const REPORT_WRITE_BUF_SIZE: usize = 0x400;
+
+struct BufferedFileWriter {
+    file: fs::File,
+    buf: [u8; REPORT_WRITE_BUF_SIZE],
+    len: usize,
+    written_size: u64,
+    // First error while flushing from write_str, which can only report a plain fmt::Error
+    write_rc: Result<()>,
+}
+
+impl BufferedFileWriter {
+    fn new(file: fs::File) -> Self {
+        Self {
+            file,
+            buf: [0; REPORT_WRITE_BUF_SIZE],
+            len: 0,
+            written_size: 0,
+            write_rc: Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let len = core::mem::take(&mut self.len);
+        self.file.write_all(&self.buf[..len])
+    }
+
+    /// Flushes whatever is left, failing if any earlier write did
+    fn finish(&mut self) -> Result<()> {
+        core::mem::replace(&mut self.write_rc, Ok(()))?;
+        self.flush()
+    }
+}
+
+impl fmt::Write for BufferedFileWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut data = s.as_bytes();
+        self.written_size += data.len() as u64;
+        while !data.is_empty() {
+            if self.len == self.buf.len() {
+                if let Err(rc) = self.flush() {
+                    self.write_rc = Err(rc);
+                    return Err(fmt::Error);
+                }
+            }
+
+            let copy_len = data.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + copy_len].copy_from_slice(&data[..copy_len]);
+            self.len += copy_len;
+            data = &data[copy_len..];
+        }
+        Ok(())
+    }
+}
+
+/// Calls `on_line` for every complete line of the index, oldest reports first
+fn read_index(mut on_line: impl FnMut(&str) -> Result<()>) -> Result<()> {
+    let mut index_file = fs::open_file(INDEX_PATH, fs::FileOpenOption::Read())?;
+
+    let mut chunk_buf = [0u8; 0x200];
+    let mut line_buf = [0u8; MAX_INDEX_LINE_LEN];
+    let mut line_len = 0;
+    let mut line_too_long = false;
+    loop {
+        let read_size = index_file.read_array(&mut chunk_buf)?;
+        if read_size == 0 {
+            break;
+        }
+
+        for &byte in &chunk_buf[..read_size] {
+            if byte == b'\n' {
+                let line = match core::str::from_utf8(&line_buf[..line_len]) {
+                    Ok(line) => Some(line),
+                    // Only older versions wrote lines this long (without cutting the room), the entry is still there
+                    // and only the end of the room gets lost, along with whatever character it got cut at
+                    Err(e) if line_too_long => core::str::from_utf8(&line_buf[..e.valid_up_to()]).ok(),
+                    Err(_) => None,
+                };
+                if let Some(line) = line {
+                    on_line(line)?;
+                }
+                line_len = 0;
+                line_too_long = false;
+            } else if line_len < line_buf.len() {
+                line_buf[line_len] = byte;
+                line_len += 1;
+            } else {
+                line_too_long = true;
+            }
+        }
+    }
+
+    // A last line without line ending was cut while being written, so it's ignored
+    Ok(())
+}
+
+struct ReportStore {
+    // Next counter of every program which saved reports
+    counters: Vec<(u64, u32)>,
+    total_size: u64,
+}
+
+impl ReportStore {
+    const fn new() -> Self {
+        Self {
+            counters: Vec::new(),
+            total_size: 0,
+        }
+    }
+
+    fn add_entry(&mut self, entry: &IndexEntry) {
+        self.total_size += entry.size;
+        match self.counters.iter_mut().find(|(program_id, _)| *program_id == entry.program_id) {
+            Some((_, next_counter)) => *next_counter = (*next_counter).max(entry.counter + 1),
+            None => self.counters.push((entry.program_id, entry.counter + 1)),
+        }
+    }
+
+    fn take_counter(&mut self, program_id: u64) -> u32 {
+        match self.counters.iter_mut().find(|(id, _)| *id == program_id) {
+            Some((_, next_counter)) => {
+                let counter = *next_counter;
+                *next_counter += 1;
+                counter
+            }
+            None => {
+                self.counters.push((program_id, 1));
+                0
+            }
+        }
+    }
+
+    fn save_report(
+        &mut self,
+        program_id: u64,
+        metadata: &ReportMetadata,
+        msgpack_buf: &[u8],
+        redaction: Option<&RedactionConfig>,
+    ) -> Result<()> {
+        // Reports still get saved without a clock (like early on boot), just under 1970-01-01
+        let day = get_day(time::get_current_time().unwrap_or(0));
+        let mut entry = IndexEntry {
+            day,
+            program_id,
+            counter: self.take_counter(program_id),
+            kind: metadata.kind,
+            size: 0,
+        };
+
+        let _ = fs::create_directory(STORE_PATH);
+        let _ = fs::create_directory(format!("{}/{}", STORE_PATH, DayDir(day)).as_str());
+
+        let report_path = format!("{}/{}", STORE_PATH, entry.get_path());
+        // Leftovers from an index which got lost (counters start over then), appending to them would corrupt both reports
+        let _ = fs::remove_file(report_path.as_str());
+        let report_file = fs::open_file(
+            report_path.as_str(),
+            fs::FileOpenOption::Create() | fs::FileOpenOption::Write() | fs::FileOpenOption::Append(),
+        )?;
+        let mut writer = BufferedFileWriter::new(report_file);
+        // Write errors are the only ones which can happen here (and finish() tells about them), invalid reports are described in the document itself
+        let _ = match redaction {
+            Some(redaction) => write_report_json_redacted(metadata, msgpack_buf, &redaction.get_rules(), &mut writer),
+            None => write_report_json(metadata, msgpack_buf, &mut writer),
+        };
+        let write_rc = writer.finish();
+        entry.size = writer.written_size;
+        drop(writer);
+
+        if let Err(rc) = write_rc {
+            // Don't leave reports behind which aren't in the index, they would never get evicted
+            let _ = fs::remove_file(report_path.as_str());
+            return Err(rc);
+        }
+
+        let mut index_file = fs::open_file(
+            INDEX_PATH,
+            fs::FileOpenOption::Create() | fs::FileOpenOption::Write() | fs::FileOpenOption::Append(),
+        )?;
+        let index_line = IndexLine {
+            entry: &entry,
+            room: metadata.get_room(),
+        };
+        index_file.write_all(format!("{}\n", index_line).as_bytes())?;
+        drop(index_file);
+
+        self.total_size += entry.size;
+        if self.total_size > MAX_STORE_SIZE {
+            self.evict()?;
+        }
+        Ok(())
+    }
+
+    // Removes the oldest reports until the store is back under the target size, rewriting the index without them
+    fn evict(&mut self) -> Result<()> {
+        let mut total_size = self.total_size;
+        let mut evicted_count = 0usize;
+        let mut evicted_day = None;
+        rewrite_index(|entry| {
+            if total_size <= EVICTION_TARGET_SIZE {
+                return true;
+            }
+
+            let _ = fs::remove_file(format!("{}/{}", STORE_PATH, entry.get_path()).as_str());
+            total_size = total_size.saturating_sub(entry.size);
+            evicted_count += 1;
+
+            // Reports are saved in order, so the previous day has no reports left
+            if let Some(prev_day) = evicted_day {
+                if prev_day != entry.day {
+                    remove_day_dir(prev_day);
+                }
+            }
+            evicted_day = Some(entry.day);
+            false
+        })?;
+
+        // This one only goes away if all of its reports were evicted (directories with files can't be removed)
+        if let Some(day) = evicted_day {
+            remove_day_dir(day);
+        }
+
+        diag_log!(LmLogger { LogSeverity::Info, true } => "Evicted {} old reports, store size is now {:#X}\n", evicted_count, total_size);
+        self.total_size = total_size;
+        Ok(())
+    }
+
+    fn delete_report(&mut self, report: &IndexEntry) -> Result<()> {
+        let mut deleted_entry = None;
+        rewrite_index(|entry| {
+            let is_deleted = IndexEntry { size: report.size, ..*entry } == *report;
+            if is_deleted {
+                deleted_entry = Some(*entry);
+            }
+            !is_deleted
+        })?;
+
+        let remove_rc = fs::remove_file(format!("{}/{}", STORE_PATH, report.get_path()).as_str());
+        match deleted_entry {
+            Some(entry) => {
+                self.total_size = self.total_size.saturating_sub(entry.size);
+                // Fails unless it was the last report of its day
+                remove_day_dir(entry.day);
+                Ok(())
+            }
+            // Not in the index, so the report file is all there is to delete
+            None => remove_rc,
+        }
+    }
+}
+
+// Rewrites the index keeping only the entries for which keep_entry returns true, see prepo_report::store
+fn rewrite_index(mut keep_entry: impl FnMut(&IndexEntry) -> bool) -> Result<()> {
+    let _ = fs::remove_file(INDEX_TMP_PATH);
+    let new_index_file = fs::open_file(
+        INDEX_TMP_PATH,
+        fs::FileOpenOption::Create() | fs::FileOpenOption::Write() | fs::FileOpenOption::Append(),
+    )?;
+    let mut new_index = BufferedFileWriter::new(new_index_file);
+
+    read_index(|line| {
+        // Corrupted lines just get dropped from the index
+        let Some(entry) = IndexEntry::parse(line) else {
+            return Ok(());
+        };
+
+        if keep_entry(&entry) {
+            let _ = fmt::Write::write_str(&mut new_index, line);
+            let _ = fmt::Write::write_str(&mut new_index, "\n");
+        }
+        Ok(())
+    })?;
+    new_index.finish()?;
+    drop(new_index);
+
+    fs::remove_file(INDEX_PATH)?;
+    fs::rename_file(INDEX_TMP_PATH, INDEX_PATH)
+}
+
+fn remove_day_dir(day: u32) {
+    let _ = fs::remove_directory(format!("{}/{}", STORE_PATH, DayDir(day)).as_str());
+}
+
+static G_STORE: Mutex<ReportStore> = Mutex::new(ReportStore::new());
+
+/// Loads the store index from the SD card, the SD card must already be mounted
+pub fn load() {
+    let mut store = G_STORE.lock();
+    // The index is replaced by renaming the new one over it, which might not have happened
+    if fs::open_file(INDEX_PATH, fs::FileOpenOption::Read()).is_err() && fs::rename_file(INDEX_TMP_PATH, INDEX_PATH).is_ok() {
+        diag_log!(LmLogger { LogSeverity::Warn, true } => "Recovered the report index from an unfinished rewrite\n");
+    }
+    // A missing index is fine, it just means there are no reports yet
+    let _ = read_index(|line| {
+        if let Some(entry) = IndexEntry::parse(line) {
+            store.add_entry(&entry);
+        }
+        Ok(())
+    });
+
+    diag_log!(LmLogger { LogSeverity::Info, true } => "Report store size: {:#X} (max {:#X})\n", store.total_size, MAX_STORE_SIZE);
+}
+
+/// Saves a report sent by the given program (scrubbed first if there's a redaction config), removing the oldest ones if the store gets too big
+pub fn save_report(program_id: u64, metadata: &ReportMetadata, msgpack_buf: &[u8], redaction: Option<&RedactionConfig>) -> Result<()> {
+    G_STORE.lock().save_report(program_id, metadata, msgpack_buf, redaction)
+}
+
+/// Deletes a saved report along with its index line, the size of the given entry being ignored (the indexed one is used)
+pub fn delete_report(report: &IndexEntry) -> Result<()> {
+    G_STORE.lock().delete_report(report)
+}
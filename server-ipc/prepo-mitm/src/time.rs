@@ -0,0 +1,41 @@
+use nx::result::*;
+use nx::service;
+use nx::service::sm;
+use nx::version;
+
+// Just the bits of the time services needed to date reports
+
+ipc_sf_define_default_client_for_interface!(SystemClock);
+ipc_sf_define_interface_trait! {
+    trait SystemClock {
+        get_current_time [0, version::VersionInterval::all(), mut ]: () => (posix_time: i64) (posix_time: i64);
+    }
+}
+
+ipc_sf_define_default_client_for_interface!(StaticService);
+ipc_sf_define_interface_trait! {
+    trait StaticService {
+        get_standard_user_system_clock [0, version::VersionInterval::all(), mut ]: () => (clock: SystemClock) (clock: impl ISystemClockServer + 'static);
+    }
+}
+
+impl service::IService for StaticService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new("time:u")
+    }
+
+    fn as_domain() -> bool {
+        false
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Gets the current POSIX time of the user system clock
+pub fn get_current_time() -> Result<u64> {
+    let mut time = service::new_service_object::<StaticService>()?;
+    let mut clock = time.get_standard_user_system_clock()?;
+    Ok(clock.get_current_time()?.max(0) as u64)
+}
@@ -0,0 +1,93 @@
+use nx::arm;
+use nx::result::*;
+use nx::rand::{RandomService, Rng};
+use nx::service;
+use nx::sync::Mutex;
+
+use prepo_ipc::TransmissionStatus;
+
+// How long an emulated transmission takes, long enough for anything polling the status to see it going on
+const TRANSMISSION_DURATION_MS: u64 = 2000;
+
+/// Emulated transmission state, shared by all sessions like the real one
+///
+/// Saved reports make the status pending, and requesting a transmission makes it processing for a while and then idle again.
+/// Reports are never transmitted on their own, just like the real service when there's no network connection.
+struct TransmissionEmulator {
+    status: TransmissionStatus,
+    transmission_end_ms: u64,
+}
+
+impl TransmissionEmulator {
+    const fn new() -> Self {
+        Self {
+            status: TransmissionStatus::Idle,
+            transmission_end_ms: 0,
+        }
+    }
+
+    fn update(&mut self, now_ms: u64) {
+        if (self.status == TransmissionStatus::Processing) && (now_ms >= self.transmission_end_ms) {
+            self.status = TransmissionStatus::Idle;
+        }
+    }
+
+    fn on_report_saved(&mut self, now_ms: u64) {
+        self.update(now_ms);
+        // Reports saved while transmitting just go with the current transmission
+        if self.status == TransmissionStatus::Idle {
+            self.status = TransmissionStatus::Pending;
+        }
+    }
+
+    fn request_immediate_transmission(&mut self, now_ms: u64) {
+        self.update(now_ms);
+        if self.status == TransmissionStatus::Pending {
+            self.status = TransmissionStatus::Processing;
+            self.transmission_end_ms = now_ms + TRANSMISSION_DURATION_MS;
+        }
+    }
+
+    fn get_status(&mut self, now_ms: u64) -> TransmissionStatus {
+        self.update(now_ms);
+        self.status
+    }
+}
+
+static G_TRANSMISSION: Mutex<TransmissionEmulator> = Mutex::new(TransmissionEmulator::new());
+
+fn get_now_ms() -> u64 {
+    let tick_frequency = arm::get_system_tick_frequency();
+    ((arm::get_system_tick() as u128) * 1000 / (tick_frequency.max(1) as u128)) as u64
+}
+
+pub fn on_report_saved() {
+    G_TRANSMISSION.lock().on_report_saved(get_now_ms());
+}
+
+pub fn request_immediate_transmission() {
+    G_TRANSMISSION.lock().request_immediate_transmission(get_now_ms());
+}
+
+pub fn get_status() -> TransmissionStatus {
+    G_TRANSMISSION.lock().get_status(get_now_ms())
+}
+
+fn generate_session_id() -> Result<u64> {
+    let mut rand = service::new_service_object::<RandomService>()?;
+    // Zero is left out, it's what we use for "not generated yet"
+    Ok(<RandomService as Rng>::random_range(&mut rand, 1..u64::MAX))
+}
+
+// Zero means not generated yet
+static G_SESSION_ID: Mutex<u64> = Mutex::new(0);
+
+/// Gets the system session ID, which is random and stays the same until the next boot (like the real one)
+pub fn get_session_id() -> u64 {
+    let mut session_id = G_SESSION_ID.lock();
+    while *session_id == 0 {
+        // The tick is far from random but still different every boot, should the random service ever fail
+        *session_id = generate_session_id().unwrap_or_else(|_| arm::get_system_tick().rotate_left(17) ^ 0x9E3779B97F4A7C15);
+    }
+    *session_id
+}
@@ -0,0 +1,564 @@
+//! Per-report policies for the `prepo-mitm` example, loaded from `sdmc:/config/prepo-mitm/policy.toml`
+//!
+//! The config looks like this, where every key is optional:
+//!
+//! ```toml
+//! # Used for reports not matching any rule: "save" (to the SD card only), "forward" (to the real service), "drop" or
+//! # "record" (save, then forward)
+//! default_action = "save"
+//! # Transmission status and session ID: "emulate" (answered by the MitM itself) or "forward" (asked to the real service)
+//! transmission = "emulate"
+//!
+//! # Rules are checked in order and the first matching one wins, so specific rules go before general ones (their names
+//! # are just for readability)
+//! [rule.quiet-launcher]
+//! # Exact room, or a prefix when ending with '*'
+//! room = "olv_*"
+//! # Program sending the report
+//! program_id = 0x0100000000001000
+//! action = "drop"
+//!
+//! # Scrubbing of recorded reports before they get saved, see prepo_report::redact
+//! [redact]
+//! # Keys (at any depth) left out of the report
+//! drop_keys = ["ip_address", "mac_address"]
+//! # Keys whose values get replaced by a salted hash
+//! hash_keys = ["nsa_id", "friend_code"]
+//! # Strings longer than this many bytes get cut
+//! max_string_len = 64
+//! # Hash the sender's user ID too (and leave out their nickname)
+//! hash_user_ids = true
+//! # Changing the salt makes hashes incomparable with the ones saved before
+//! hash_salt = 0x1234
+//! ```
+//!
+//! A rule without `room` or `program_id` matches any room or program.
+//! Unlike in `lm-filter`, where there's a rule per program and a later one for the same program overrides an earlier one,
+//! rules here overlap on purpose (like a catch-all rule after some exceptions), which is why the first match wins instead.
+//! The `session` module decides which sessions get taken over in the first place.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod session;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use prepo_report::redact::RedactionRules;
+use sd_config::{Document, Table};
+
+pub const POLICY_CONFIG_PATH: &str = "sdmc:/config/prepo-mitm/policy.toml";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PolicyAction {
+    /// Pass the report to the real service untouched
+    Forward,
+    /// Swallow the report, telling the sender it was saved
+    Drop,
+    /// Save the report to the SD card and then forward it
+    Record,
+    /// Save the report to the SD card without forwarding it, so nothing leaves the console
+    Save,
+}
+
+impl PolicyAction {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "forward" => Some(Self::Forward),
+            "drop" => Some(Self::Drop),
+            "record" => Some(Self::Record),
+            "save" => Some(Self::Save),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Forward => "forward",
+            Self::Drop => "drop",
+            Self::Record => "record",
+            Self::Save => "save",
+        }
+    }
+
+    pub const fn should_record(self) -> bool {
+        matches!(self, Self::Record | Self::Save)
+    }
+
+    pub const fn should_forward(self) -> bool {
+        matches!(self, Self::Forward | Self::Record)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TransmissionMode {
+    /// Answer transmission commands with a state machine in the MitM
+    Emulate,
+    /// Pass transmission commands to the real service
+    Forward,
+}
+
+impl TransmissionMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "emulate" => Some(Self::Emulate),
+            "forward" => Some(Self::Forward),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Emulate => "emulate",
+            Self::Forward => "forward",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyError {
+    Parse(sd_config::ParseError),
+    /// A table other than `[rule.<name>]` or `[redact]` was found
+    UnknownTable,
+    /// A key had an unexpected type or value
+    InvalidValue(&'static str),
+    /// A rule has no `action`
+    MissingAction(String),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "{}", e),
+            Self::UnknownTable => write!(f, "unknown table, expected [rule.<name>] or [redact]"),
+            Self::InvalidValue(key) => write!(f, "invalid value for \"{}\"", key),
+            Self::MissingAction(rule_name) => write!(f, "rule \"{}\" has no action", rule_name),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, PolicyError>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RoomPattern {
+    Exact(String),
+    Prefix(String),
+}
+
+impl RoomPattern {
+    pub fn parse(pattern: &str) -> Self {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => Self::Prefix(String::from(prefix)),
+            None => Self::Exact(String::from(pattern)),
+        }
+    }
+
+    pub fn matches(&self, room: &[u8]) -> bool {
+        match self {
+            Self::Exact(exact) => room == exact.as_bytes(),
+            Self::Prefix(prefix) => room.starts_with(prefix.as_bytes()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyRule {
+    pub name: String,
+    pub room: Option<RoomPattern>,
+    pub program_id: Option<u64>,
+    pub action: PolicyAction,
+}
+
+impl PolicyRule {
+    pub fn matches(&self, room: &[u8], program_id: u64) -> bool {
+        self.room.as_ref().is_none_or(|pattern| pattern.matches(room))
+            && self.program_id.is_none_or(|id| id == program_id)
+    }
+}
+
+fn parse_rule(name: &str, table: &Table) -> Result<PolicyRule> {
+    let room = table
+        .get("room")
+        .map(|value| value.as_str().map(RoomPattern::parse).ok_or(PolicyError::InvalidValue("room")))
+        .transpose()?;
+    let program_id = table
+        .get("program_id")
+        .map(|value| value.as_u64().ok_or(PolicyError::InvalidValue("program_id")))
+        .transpose()?;
+    let action = table
+        .get("action")
+        .ok_or_else(|| PolicyError::MissingAction(String::from(name)))?
+        .as_str()
+        .and_then(PolicyAction::parse)
+        .ok_or(PolicyError::InvalidValue("action"))?;
+
+    Ok(PolicyRule {
+        name: String::from(name),
+        room,
+        program_id,
+        action,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RedactionConfig {
+    pub drop_keys: Vec<String>,
+    pub hash_keys: Vec<String>,
+    pub max_string_len: Option<usize>,
+    pub hash_user_ids: bool,
+    pub hash_salt: u64,
+}
+
+impl RedactionConfig {
+    pub fn get_rules(&self) -> RedactionRules<'_, String> {
+        RedactionRules {
+            drop_keys: &self.drop_keys,
+            hash_keys: &self.hash_keys,
+            max_string_len: self.max_string_len,
+            hash_user_ids: self.hash_user_ids,
+            hash_salt: self.hash_salt,
+        }
+    }
+}
+
+fn parse_key_list(table: &Table, key: &'static str) -> Result<Vec<String>> {
+    match table.get(key) {
+        Some(value) => value
+            .as_array()
+            .ok_or(PolicyError::InvalidValue(key))?
+            .iter()
+            .map(|item| item.as_str().map(String::from).ok_or(PolicyError::InvalidValue(key)))
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn parse_redaction(table: &Table) -> Result<RedactionConfig> {
+    let max_string_len = table
+        .get("max_string_len")
+        .map(|value| {
+            value
+                .as_integer()
+                .and_then(|len| usize::try_from(len).ok())
+                .ok_or(PolicyError::InvalidValue("max_string_len"))
+        })
+        .transpose()?;
+    let hash_user_ids = table
+        .get("hash_user_ids")
+        .map(|value| value.as_bool().ok_or(PolicyError::InvalidValue("hash_user_ids")))
+        .transpose()?
+        .unwrap_or(false);
+    let hash_salt = table
+        .get("hash_salt")
+        .map(|value| value.as_u64().ok_or(PolicyError::InvalidValue("hash_salt")))
+        .transpose()?
+        .unwrap_or(0);
+
+    Ok(RedactionConfig {
+        drop_keys: parse_key_list(table, "drop_keys")?,
+        hash_keys: parse_key_list(table, "hash_keys")?,
+        max_string_len,
+        hash_user_ids,
+        hash_salt,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyConfig {
+    pub default_action: PolicyAction,
+    pub transmission_mode: TransmissionMode,
+    pub rules: Vec<PolicyRule>,
+    /// Recorded reports are saved as they are without a `[redact]` table
+    pub redaction: Option<RedactionConfig>,
+}
+
+impl PolicyConfig {
+    /// Config saving every report without forwarding any, used when there's no config file
+    pub const fn new() -> Self {
+        Self {
+            default_action: PolicyAction::Save,
+            transmission_mode: TransmissionMode::Emulate,
+            rules: Vec::new(),
+            redaction: None,
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<Self> {
+        let doc = Document::parse(input).map_err(PolicyError::Parse)?;
+
+        let default_action = doc
+            .get_root()
+            .get("default_action")
+            .map(|value| value.as_str().and_then(PolicyAction::parse).ok_or(PolicyError::InvalidValue("default_action")))
+            .transpose()?
+            .unwrap_or(PolicyAction::Save);
+        let transmission_mode = doc
+            .get_root()
+            .get("transmission")
+            .map(|value| value.as_str().and_then(TransmissionMode::parse).ok_or(PolicyError::InvalidValue("transmission")))
+            .transpose()?
+            .unwrap_or(TransmissionMode::Emulate);
+
+        if doc
+            .tables
+            .iter()
+            .skip(1)
+            .any(|table| !table.name.starts_with("rule.") && (table.name != "redact"))
+        {
+            return Err(PolicyError::UnknownTable);
+        }
+
+        let rules = doc
+            .iter_subtables("rule")
+            .map(|(name, table)| parse_rule(name, table))
+            .collect::<Result<Vec<_>>>()?;
+        let redaction = doc.get_table("redact").map(parse_redaction).transpose()?;
+
+        Ok(Self {
+            default_action,
+            transmission_mode,
+            rules,
+            redaction,
+        })
+    }
+
+    /// Gets the first rule matching a report, if any
+    pub fn find_rule(&self, room: &[u8], program_id: u64) -> Option<&PolicyRule> {
+        self.rules.iter().find(|rule| rule.matches(room, program_id))
+    }
+
+    pub fn get_action(&self, room: &[u8], program_id: u64) -> PolicyAction {
+        self.find_rule(room, program_id)
+            .map(|rule| rule.action)
+            .unwrap_or(self.default_action)
+    }
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    const APP_PROGRAM_ID: u64 = 0x0100000000001000;
+    const OTHER_PROGRAM_ID: u64 = 0x010000000000100D;
+
+    fn parse(input: &str) -> PolicyConfig {
+        PolicyConfig::parse(input).unwrap()
+    }
+
+    #[test]
+    fn default_config_saves_everything() {
+        for config in [PolicyConfig::new(), parse("")] {
+            assert_eq!(config, PolicyConfig::default());
+            assert_eq!(config.get_action(b"some_room", APP_PROGRAM_ID), PolicyAction::Save);
+            assert_eq!(config.transmission_mode, TransmissionMode::Emulate);
+            assert_eq!(config.redaction, None);
+        }
+    }
+
+    #[test]
+    fn default_action_applies_without_matching_rules() {
+        let config = parse(
+            r#"
+            default_action = "forward"
+            transmission = "forward"
+
+            [rule.quiet]
+            program_id = 0x0100000000001000
+            action = "drop"
+            "#,
+        );
+        assert_eq!(config.transmission_mode, TransmissionMode::Forward);
+        assert_eq!(config.get_action(b"some_room", APP_PROGRAM_ID), PolicyAction::Drop);
+        assert_eq!(config.find_rule(b"some_room", OTHER_PROGRAM_ID), None);
+        assert_eq!(config.get_action(b"some_room", OTHER_PROGRAM_ID), PolicyAction::Forward);
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let config = parse(
+            r#"
+            [rule.keep-this-one]
+            room = "olv_post"
+            action = "record"
+
+            [rule.quiet-olv]
+            room = "olv_*"
+            action = "drop"
+
+            [rule.everything]
+            action = "forward"
+            "#,
+        );
+        assert_eq!(config.find_rule(b"olv_post", APP_PROGRAM_ID).unwrap().name, "keep-this-one");
+        assert_eq!(config.get_action(b"olv_post", APP_PROGRAM_ID), PolicyAction::Record);
+        assert_eq!(config.find_rule(b"olv_get", APP_PROGRAM_ID).unwrap().name, "quiet-olv");
+        assert_eq!(config.get_action(b"olv_get", APP_PROGRAM_ID), PolicyAction::Drop);
+        assert_eq!(config.get_action(b"other_room", APP_PROGRAM_ID), PolicyAction::Forward);
+    }
+
+    #[test]
+    fn room_patterns() {
+        let exact = RoomPattern::parse("olv_post");
+        assert_eq!(exact, RoomPattern::Exact(String::from("olv_post")));
+        assert!(exact.matches(b"olv_post"));
+        assert!(!exact.matches(b"olv_post2"));
+        assert!(!exact.matches(b"olv_pos"));
+
+        let prefix = RoomPattern::parse("olv_*");
+        assert_eq!(prefix, RoomPattern::Prefix(String::from("olv_")));
+        assert!(prefix.matches(b"olv_"));
+        assert!(prefix.matches(b"olv_post"));
+        assert!(!prefix.matches(b"olv"));
+        assert!(!prefix.matches(b"other_olv_post"));
+
+        // Just '*' matches any room
+        assert!(RoomPattern::parse("*").matches(b""));
+        assert!(RoomPattern::parse("*").matches(b"anything"));
+    }
+
+    #[test]
+    fn rules_with_only_room_or_program() {
+        let config = parse(
+            r#"
+            [rule.both]
+            room = "both_room"
+            program_id = 0x0100000000001000
+            action = "record"
+
+            [rule.only-room]
+            room = "room_*"
+            action = "drop"
+
+            [rule.only-program]
+            program_id = 0x010000000000100D
+            action = "forward"
+            "#,
+        );
+        assert_eq!(config.get_action(b"both_room", APP_PROGRAM_ID), PolicyAction::Record);
+        // Both have to match
+        assert_eq!(config.get_action(b"both_room", OTHER_PROGRAM_ID), PolicyAction::Forward);
+        assert_eq!(config.get_action(b"room_a", APP_PROGRAM_ID), PolicyAction::Drop);
+        assert_eq!(config.get_action(b"room_a", OTHER_PROGRAM_ID), PolicyAction::Drop);
+        assert_eq!(config.get_action(b"other_room", OTHER_PROGRAM_ID), PolicyAction::Forward);
+        assert_eq!(config.get_action(b"other_room", APP_PROGRAM_ID), PolicyAction::Save);
+    }
+
+    #[test]
+    fn missing_action() {
+        let res = PolicyConfig::parse(
+            r#"
+            [rule.no-action]
+            room = "some_room"
+            "#,
+        );
+        assert_eq!(res, Err(PolicyError::MissingAction(String::from("no-action"))));
+    }
+
+    #[test]
+    fn unknown_tables() {
+        assert_eq!(PolicyConfig::parse("[rules.typo]\naction = \"drop\"\n"), Err(PolicyError::UnknownTable));
+        assert_eq!(PolicyConfig::parse("[redaction]\n"), Err(PolicyError::UnknownTable));
+    }
+
+    #[test]
+    fn invalid_values() {
+        for (input, key) in [
+            ("default_action = \"keep\"", "default_action"),
+            ("default_action = 1", "default_action"),
+            ("transmission = \"emulated\"", "transmission"),
+            ("[rule.a]\naction = \"save_it\"", "action"),
+            ("[rule.a]\naction = true", "action"),
+            ("[rule.a]\nroom = 1\naction = \"drop\"", "room"),
+            ("[rule.a]\nprogram_id = \"0x0100000000001000\"\naction = \"drop\"", "program_id"),
+        ] {
+            assert_eq!(PolicyConfig::parse(input), Err(PolicyError::InvalidValue(key)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn redaction() {
+        let config = parse(
+            r#"
+            [redact]
+            drop_keys = ["ip_address", "mac_address"]
+            hash_keys = ["nsa_id"]
+            max_string_len = 64
+            hash_user_ids = true
+            hash_salt = 0x1234
+            "#,
+        );
+        let redaction = config.redaction.unwrap();
+        assert_eq!(redaction.drop_keys, [String::from("ip_address"), String::from("mac_address")]);
+        assert_eq!(redaction.hash_keys, [String::from("nsa_id")]);
+        assert_eq!(redaction.max_string_len, Some(64));
+        assert!(redaction.hash_user_ids);
+        assert_eq!(redaction.hash_salt, 0x1234);
+
+        let rules = redaction.get_rules();
+        assert_eq!(rules.drop_keys, redaction.drop_keys.as_slice());
+        assert_eq!(rules.hash_keys, redaction.hash_keys.as_slice());
+        assert_eq!(rules.max_string_len, Some(64));
+        assert!(rules.hash_user_ids);
+        assert_eq!(rules.hash_salt, 0x1234);
+    }
+
+    #[test]
+    fn empty_redaction() {
+        let redaction = parse("[redact]\n").redaction.unwrap();
+        assert_eq!(
+            redaction,
+            RedactionConfig {
+                drop_keys: Vec::new(),
+                hash_keys: Vec::new(),
+                max_string_len: None,
+                hash_user_ids: false,
+                hash_salt: 0,
+            }
+        );
+        assert_eq!(parse("[redact]\ndrop_keys = []\n").redaction.unwrap().drop_keys, Vec::<String>::new());
+    }
+
+    #[test]
+    fn redaction_max_string_len_range() {
+        assert_eq!(parse("[redact]\nmax_string_len = 0").redaction.unwrap().max_string_len, Some(0));
+        assert_eq!(
+            parse("[redact]\nmax_string_len = 0x7FFFFFFF").redaction.unwrap().max_string_len,
+            Some(0x7FFFFFFF)
+        );
+        assert_eq!(
+            PolicyConfig::parse("[redact]\nmax_string_len = -1"),
+            Err(PolicyError::InvalidValue("max_string_len"))
+        );
+    }
+
+    #[test]
+    fn invalid_redaction_values() {
+        for (input, key) in [
+            ("drop_keys = \"ip_address\"", "drop_keys"),
+            ("drop_keys = [\"ip_address\", 1]", "drop_keys"),
+            ("hash_keys = [true]", "hash_keys"),
+            ("hash_keys = [[\"nsa_id\"]]", "hash_keys"),
+            ("max_string_len = \"64\"", "max_string_len"),
+            ("hash_user_ids = 1", "hash_user_ids"),
+            ("hash_salt = \"salt\"", "hash_salt"),
+        ] {
+            let input = format!("[redact]\n{}\n", input);
+            assert_eq!(PolicyConfig::parse(&input), Err(PolicyError::InvalidValue(key)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(matches!(PolicyConfig::parse("[rule.a"), Err(PolicyError::Parse(_))));
+    }
+}
@@ -0,0 +1,125 @@
+//! Which `prepo` sessions the MitM should take over, depending on the service, the program opening it and how far boot has gone
+//!
+//! `am` opens `prepo:s` while the system is still booting and waits on it before launching anything else, so taking over
+//! that session (where we log through `lm` and write to the SD card) ends up stalling boot. Sessions to `prepo:s` are thus
+//! left to the real service until boot is done, which is assumed once the home menu or any application opens any `prepo` service.
+
+/// Program ID of `am`, which opens `prepo:s` during boot
+pub const AM_PROGRAM_ID: u64 = 0x0100000000000023;
+
+/// Program ID of the home menu (`qlaunch`)
+pub const QLAUNCH_PROGRAM_ID: u64 = 0x0100000000001000;
+
+// Applications (and their add-on content, patches...) live above every system program ID
+const APPLICATION_PROGRAM_ID_MIN: u64 = 0x0100000000010000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ServiceKind {
+    /// `prepo:a`
+    Admin,
+    /// `prepo:a2`
+    Admin2,
+    /// `prepo:m`
+    Manager,
+    /// `prepo:u`
+    User,
+    /// `prepo:s`
+    System,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BootState {
+    Booting,
+    Done,
+}
+
+impl BootState {
+    /// Gets the boot state after some program opened a session, boot being done never goes back
+    pub fn update(self, program_id: u64) -> Self {
+        match self {
+            Self::Booting if is_post_boot_program(program_id) => Self::Done,
+            _ => self,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SessionDecision {
+    /// Handle the session in the MitM
+    Mitm,
+    /// Let the program talk to the real service directly
+    Forward,
+}
+
+/// Checks whether a program is only launched once boot is done
+pub fn is_post_boot_program(program_id: u64) -> bool {
+    (program_id == QLAUNCH_PROGRAM_ID) || (program_id >= APPLICATION_PROGRAM_ID_MIN)
+}
+
+/// Decides what to do with a new session, where `boot_state` must already account for the program opening it (see `BootState::update`)
+pub fn get_session_decision(service_kind: ServiceKind, boot_state: BootState) -> SessionDecision {
+    match (service_kind, boot_state) {
+        (ServiceKind::System, BootState::Booting) => SessionDecision::Forward,
+        // Anything opened later (am included) no longer blocks boot, and gets the system commands forwarded by the MitM
+        _ => SessionDecision::Mitm,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Some system program other than am and qlaunch
+    const OTHER_SYSTEM_PROGRAM_ID: u64 = 0x0100000000000006;
+    const APPLICATION_PROGRAM_ID: u64 = 0x01006F8002326000;
+
+    #[test]
+    fn am_during_boot_is_forwarded() {
+        let boot_state = BootState::Booting.update(AM_PROGRAM_ID);
+        assert_eq!(boot_state, BootState::Booting);
+        assert_eq!(get_session_decision(ServiceKind::System, boot_state), SessionDecision::Forward);
+    }
+
+    #[test]
+    fn am_after_boot_is_mitm() {
+        let boot_state = BootState::Booting.update(QLAUNCH_PROGRAM_ID).update(AM_PROGRAM_ID);
+        assert_eq!(boot_state, BootState::Done);
+        assert_eq!(get_session_decision(ServiceKind::System, boot_state), SessionDecision::Mitm);
+    }
+
+    #[test]
+    fn other_services_during_boot_are_mitm() {
+        for service_kind in [ServiceKind::Admin, ServiceKind::Admin2, ServiceKind::Manager, ServiceKind::User] {
+            assert_eq!(get_session_decision(service_kind, BootState::Booting), SessionDecision::Mitm);
+        }
+    }
+
+    #[test]
+    fn other_programs_during_boot() {
+        // Other system programs don't end boot, so their prepo:s sessions are left alone too
+        let boot_state = BootState::Booting.update(OTHER_SYSTEM_PROGRAM_ID);
+        assert_eq!(boot_state, BootState::Booting);
+        assert_eq!(get_session_decision(ServiceKind::System, boot_state), SessionDecision::Forward);
+        assert_eq!(get_session_decision(ServiceKind::User, boot_state), SessionDecision::Mitm);
+
+        // Applications do, including their own prepo:s session
+        let boot_state = boot_state.update(APPLICATION_PROGRAM_ID);
+        assert_eq!(boot_state, BootState::Done);
+        assert_eq!(get_session_decision(ServiceKind::System, boot_state), SessionDecision::Mitm);
+    }
+
+    #[test]
+    fn boot_state_transitions() {
+        assert!(!is_post_boot_program(AM_PROGRAM_ID));
+        assert!(!is_post_boot_program(OTHER_SYSTEM_PROGRAM_ID));
+        assert!(!is_post_boot_program(APPLICATION_PROGRAM_ID_MIN - 1));
+        assert!(is_post_boot_program(APPLICATION_PROGRAM_ID_MIN));
+        assert!(is_post_boot_program(QLAUNCH_PROGRAM_ID));
+
+        assert_eq!(BootState::Booting.update(QLAUNCH_PROGRAM_ID), BootState::Done);
+        // Boot being done never goes back
+        for program_id in [AM_PROGRAM_ID, OTHER_SYSTEM_PROGRAM_ID, QLAUNCH_PROGRAM_ID, APPLICATION_PROGRAM_ID] {
+            assert_eq!(BootState::Done.update(program_id), BootState::Done);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Host tool converting raw play report captures (`.msgpack` files) into the JSON documents written by the `prepo-mitm` example
+//!
+//! Older versions of `prepo-mitm` saved reports as `<process ID>-<application ID>-<kind>[-<index>].msgpack`, so the metadata
+//! is taken from the file name when it follows that pattern.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use prepo_report::msgpack;
+use prepo_report::report::{ReportJson, ReportKind, ReportMetadata};
+
+fn usage() -> ExitCode {
+    eprintln!("Usage: prepo-report-dump <.msgpack file or directory>...");
+    ExitCode::FAILURE
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))?, 16).ok()
+}
+
+// Like "0x51-0x0-Normal" or "0x0-0x0100000000001000-System-2"
+fn get_metadata_from_name(path: &Path) -> Option<ReportMetadata<'static>> {
+    let file_stem = path.file_stem()?.to_str()?;
+    let mut parts = file_stem.split('-');
+    let process_id = parse_hex(parts.next()?)?;
+    let application_id = parse_hex(parts.next()?)?;
+    let kind = ReportKind::parse(parts.next()?)?;
+
+    // Zero was written for IDs which were not known
+    Some(ReportMetadata {
+        kind,
+        room: &[],
+        process_id: Some(process_id).filter(|&id| id != 0),
+        application_id: Some(application_id).filter(|&id| id != 0),
+        user_id: None,
+        user_nickname: None,
+    })
+}
+
+fn collect_captures(path: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for entry in entries {
+            collect_captures(&entry, out)?;
+        }
+    } else if path.extension().is_some_and(|ext| ext == "msgpack") {
+        out.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut inputs = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-h" | "--help" => return usage(),
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+    if inputs.is_empty() {
+        return usage();
+    }
+
+    let mut captures = Vec::new();
+    for input in &inputs {
+        if let Err(e) = collect_captures(input, &mut captures) {
+            eprintln!("{}: {}", input.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let mut failed = false;
+    for capture in &captures {
+        let msgpack_buf = match fs::read(capture) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("{}: {}", capture.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        if let Err(e) = msgpack::validate(&msgpack_buf) {
+            eprintln!("{}: {}", capture.display(), e);
+            failed = true;
+        }
+
+        let metadata = get_metadata_from_name(capture).unwrap_or(ReportMetadata {
+            kind: ReportKind::Normal,
+            room: &[],
+            process_id: None,
+            application_id: None,
+            user_id: None,
+            user_nickname: None,
+        });
+        if writeln!(out, "{}", ReportJson { metadata: &metadata, msgpack_buf: &msgpack_buf }).is_err() {
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match (out.flush(), failed) {
+        (Ok(()), false) => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
@@ -0,0 +1,304 @@
+//! Conversion of MessagePack values into JSON, formatting straight into a `core::fmt::Write`
+//!
+//! JSON has no direct equivalent for a few MessagePack types, which are written like this:
+//! - Binary data: hex string
+//! - Extension values: `{"ext_type": <type>, "data": <hex string>}`
+//! - Non-finite floats: `null`
+//! - Map keys which are not strings: numbers, booleans and nil become strings with their JSON text,
+//!   while binary keys become hex strings and arrays, maps and extension values become `"<array>"`, `"<map>"` or `"<ext>"`
+//!
+//! Values can also be scrubbed while writing them, see the `redact` module.
+
+use core::fmt;
+
+use crate::msgpack::{self, DecodeError, Reader, Token};
+use crate::redact::{HashStr, KeyAction, RedactionRules};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JsonError {
+    Decode(DecodeError),
+    /// The output refused the data
+    Write,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "invalid MessagePack: {}", e),
+            Self::Write => write!(f, "unable to write output"),
+        }
+    }
+}
+
+impl From<DecodeError> for JsonError {
+    fn from(e: DecodeError) -> Self {
+        Self::Decode(e)
+    }
+}
+
+impl From<fmt::Error> for JsonError {
+    fn from(_: fmt::Error) -> Self {
+        Self::Write
+    }
+}
+
+pub type Result<T> = core::result::Result<T, JsonError>;
+
+/// Displays raw bytes as a quoted and escaped JSON string, replacing invalid UTF-8 sequences
+pub struct JsonStr<'a>(pub &'a [u8]);
+
+// Writes string contents with JSON escapes, without the quotes
+fn write_escaped(s: &[u8], out: &mut impl fmt::Write) -> fmt::Result {
+    for chunk in s.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' => out.write_str("\\\"")?,
+                '\\' => out.write_str("\\\\")?,
+                '\n' => out.write_str("\\n")?,
+                '\r' => out.write_str("\\r")?,
+                '\t' => out.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+                c => out.write_char(c)?,
+            }
+        }
+        if !chunk.invalid().is_empty() {
+            out.write_str("\\ufffd")?;
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for JsonStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        write_escaped(self.0, f)?;
+        f.write_str("\"")
+    }
+}
+
+/// Displays raw bytes as a quoted lowercase hex string
+pub struct HexStr<'a>(pub &'a [u8]);
+
+impl fmt::Display for HexStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        f.write_str("\"")
+    }
+}
+
+fn write_float(out: &mut impl fmt::Write, value: f64) -> fmt::Result {
+    if value.is_finite() {
+        write!(out, "{}", value)
+    } else {
+        out.write_str("null")
+    }
+}
+
+fn write_key(reader: &mut Reader, out: &mut impl fmt::Write) -> Result<()> {
+    match reader.read_token()? {
+        Token::Str(s) => write!(out, "{}", JsonStr(s))?,
+        Token::Nil => out.write_str("\"null\"")?,
+        Token::Bool(b) => write!(out, "\"{}\"", b)?,
+        Token::Int(i) => write!(out, "\"{}\"", i)?,
+        Token::UInt(u) => write!(out, "\"{}\"", u)?,
+        Token::F32(value) => write!(out, "\"{}\"", value)?,
+        Token::F64(value) => write!(out, "\"{}\"", value)?,
+        Token::Bin(data) => write!(out, "{}", HexStr(data))?,
+        Token::Ext(..) => out.write_str("\"<ext>\"")?,
+        Token::Array(len) => {
+            for _ in 0..len {
+                reader.skip_value()?;
+            }
+            out.write_str("\"<array>\"")?;
+        }
+        Token::Map(len) => {
+            for _ in 0..(2 * len as u64) {
+                reader.skip_value()?;
+            }
+            out.write_str("\"<map>\"")?;
+        }
+    }
+    Ok(())
+}
+
+// Reads a whole value, returning its raw data
+fn read_raw_value<'a>(reader: &mut Reader<'a>) -> Result<&'a [u8]> {
+    let value_data = reader.get_remaining();
+    reader.skip_value()?;
+    Ok(&value_data[..value_data.len() - reader.get_remaining().len()])
+}
+
+fn write_str<K: AsRef<str>>(s: &[u8], out: &mut impl fmt::Write, rules: Option<&RedactionRules<K>>) -> Result<()> {
+    match rules.map(|rules| rules.truncate_str(s)) {
+        Some((kept, true)) => {
+            out.write_str("\"")?;
+            write_escaped(kept, out)?;
+            out.write_str("...\"")?;
+        }
+        _ => write!(out, "{}", JsonStr(s))?,
+    }
+    Ok(())
+}
+
+fn write_value_at<K: AsRef<str>>(reader: &mut Reader, out: &mut impl fmt::Write, depth: usize, rules: Option<&RedactionRules<K>>) -> Result<()> {
+    match reader.read_token()? {
+        Token::Nil => out.write_str("null")?,
+        Token::Bool(b) => write!(out, "{}", b)?,
+        Token::Int(i) => write!(out, "{}", i)?,
+        Token::UInt(u) => write!(out, "{}", u)?,
+        Token::F32(value) => write_float(out, value as f64)?,
+        Token::F64(value) => write_float(out, value)?,
+        Token::Str(s) => write_str(s, out, rules)?,
+        Token::Bin(data) => write!(out, "{}", HexStr(data))?,
+        Token::Ext(ext_type, data) => write!(out, "{{\"ext_type\":{},\"data\":{}}}", ext_type, HexStr(data))?,
+        Token::Array(len) => {
+            if depth >= msgpack::MAX_DEPTH {
+                return Err(DecodeError::TooDeep.into());
+            }
+            out.write_str("[")?;
+            for i in 0..len {
+                if i > 0 {
+                    out.write_str(",")?;
+                }
+                write_value_at(reader, out, depth + 1, rules)?;
+            }
+            out.write_str("]")?;
+        }
+        Token::Map(len) => {
+            if depth >= msgpack::MAX_DEPTH {
+                return Err(DecodeError::TooDeep.into());
+            }
+            out.write_str("{")?;
+            let mut written_count = 0;
+            for _ in 0..len {
+                let key_data = read_raw_value(reader)?;
+                let key_action = match (rules, Reader::new(key_data).read_token()?) {
+                    (Some(rules), Token::Str(key)) => rules.get_key_action(key),
+                    _ => KeyAction::Keep,
+                };
+                if key_action == KeyAction::Drop {
+                    reader.skip_value()?;
+                    continue;
+                }
+
+                if written_count > 0 {
+                    out.write_str(",")?;
+                }
+                written_count += 1;
+                write_key(&mut Reader::new(key_data), out)?;
+                out.write_str(":")?;
+                match (rules, key_action) {
+                    (Some(rules), KeyAction::Hash) => write!(out, "{}", HashStr(rules.hash(read_raw_value(reader)?)))?,
+                    _ => write_value_at(reader, out, depth + 1, rules)?,
+                }
+            }
+            out.write_str("}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the next value of the reader as JSON
+///
+/// Invalid data is only noticed once reached, so use `write_json` to avoid writing partial documents.
+pub fn write_value(reader: &mut Reader, out: &mut impl fmt::Write) -> Result<()> {
+    write_value_at::<&str>(reader, out, 0, None)
+}
+
+/// Like `write_value`, scrubbing values as the rules say
+pub fn write_value_redacted<K: AsRef<str>>(reader: &mut Reader, out: &mut impl fmt::Write, rules: &RedactionRules<K>) -> Result<()> {
+    write_value_at(reader, out, 0, Some(rules))
+}
+
+/// Writes a whole MessagePack buffer (a single value) as JSON, checking that it is valid before writing anything
+pub fn write_json(msgpack_buf: &[u8], out: &mut impl fmt::Write) -> Result<()> {
+    msgpack::validate(msgpack_buf)?;
+    write_value(&mut Reader::new(msgpack_buf), out)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::String;
+    use std::vec::Vec;
+
+    // Report bodies with the JSON they should turn into, next to each other in `testdata`
+    const FIXTURES: &[(&str, &[u8], &str)] = &[
+        ("launch", include_bytes!("../testdata/launch.msgpack"), include_str!("../testdata/launch.json")),
+        ("game_event", include_bytes!("../testdata/game_event.msgpack"), include_str!("../testdata/game_event.json")),
+        ("invalid_utf8", include_bytes!("../testdata/invalid_utf8.msgpack"), include_str!("../testdata/invalid_utf8.json")),
+        ("large", include_bytes!("../testdata/large.msgpack"), include_str!("../testdata/large.json")),
+    ];
+
+    fn nested_arrays(depth: usize) -> Vec<u8> {
+        let mut data = std::vec![0x91; depth - 1];
+        data.push(0x90);
+        data
+    }
+
+    #[test]
+    fn fixtures() {
+        for &(name, msgpack_buf, expected_json) in FIXTURES {
+            let mut out = String::new();
+            write_json(msgpack_buf, &mut out).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(out, expected_json.trim_end(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn malformed_input_writes_nothing() {
+        for &(name, msgpack_buf, _) in FIXTURES {
+            for len in 0..msgpack_buf.len() {
+                let mut out = String::new();
+                assert_eq!(write_json(&msgpack_buf[..len], &mut out), Err(DecodeError::UnexpectedEnd.into()), "{} cut at {}", name, len);
+                assert!(out.is_empty());
+            }
+        }
+
+        let mut out = String::new();
+        assert_eq!(write_json(&[0x92, 0x01, 0xC1], &mut out), Err(DecodeError::InvalidType(0xC1).into()));
+        assert_eq!(write_json(&[0x01, 0x02, 0x03], &mut out), Err(DecodeError::TrailingData(2).into()));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn write_value_stops_at_bad_data() {
+        let mut out = String::new();
+        let mut reader = Reader::new(&[0x93, 0x01, 0xC1, 0x02]);
+        assert_eq!(write_value(&mut reader, &mut out), Err(DecodeError::InvalidType(0xC1).into()));
+        assert_eq!(out, "[1,");
+    }
+
+    #[test]
+    fn max_depth() {
+        let mut out = String::new();
+        write_json(&nested_arrays(msgpack::MAX_DEPTH), &mut out).unwrap();
+        assert_eq!(out.len(), 2 * msgpack::MAX_DEPTH);
+
+        let mut out = String::new();
+        assert_eq!(write_json(&nested_arrays(msgpack::MAX_DEPTH + 1), &mut out), Err(DecodeError::TooDeep.into()));
+        // Also checked while writing, for values which were not validated first
+        assert_eq!(
+            write_value(&mut Reader::new(&nested_arrays(msgpack::MAX_DEPTH + 1)), &mut out),
+            Err(DecodeError::TooDeep.into())
+        );
+    }
+
+    #[test]
+    fn failing_output() {
+        struct FullWriter;
+
+        impl fmt::Write for FullWriter {
+            fn write_str(&mut self, _s: &str) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+
+        assert_eq!(write_json(FIXTURES[0].1, &mut FullWriter), Err(JsonError::Write));
+    }
+}
@@ -0,0 +1,12 @@
+//! Decoding of the play reports received by the `prepo-mitm` example
+//!
+//! Reports are MessagePack documents, which get converted here into self-describing JSON documents along with the report metadata.
+//! The `store` module describes how those documents are laid out on the SD card.
+
+#![no_std]
+
+pub mod json;
+pub mod msgpack;
+pub mod redact;
+pub mod report;
+pub mod store;
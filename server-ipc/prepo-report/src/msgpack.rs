@@ -0,0 +1,296 @@
+//! Minimal allocation-free MessagePack reader
+//!
+//! Values are read as a stream of tokens, where arrays and maps are followed by their elements (maps alternating keys and values).
+
+use core::fmt;
+
+/// Deepest nesting of arrays and maps accepted, which bounds the recursion when walking values
+pub const MAX_DEPTH: usize = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The buffer ended before the expected amount of data could be read
+    UnexpectedEnd,
+    /// The type byte 0xC1, which is never used
+    InvalidType(u8),
+    /// Arrays and maps were nested deeper than `MAX_DEPTH`
+    TooDeep,
+    /// There was data left after the top-level value
+    TrailingData(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of data"),
+            Self::InvalidType(ty) => write!(f, "invalid type byte 0x{:02X}", ty),
+            Self::TooDeep => write!(f, "values nested deeper than {} levels", MAX_DEPTH),
+            Self::TrailingData(size) => write!(f, "0x{:X} bytes of trailing data", size),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, DecodeError>;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Token<'a> {
+    Nil,
+    Bool(bool),
+    /// Negative integers (positive ones are always `UInt`)
+    Int(i64),
+    UInt(u64),
+    F32(f32),
+    F64(f64),
+    /// Raw string bytes, which are not guaranteed to be valid UTF-8
+    Str(&'a [u8]),
+    Bin(&'a [u8]),
+    /// Followed by this many values
+    Array(u32),
+    /// Followed by this many key/value pairs
+    Map(u32),
+    Ext(i8, &'a [u8]),
+}
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub const fn get_remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.read_array()?))
+    }
+
+    fn read_ext(&mut self, len: usize) -> Result<Token<'a>> {
+        let ext_type = self.read_u8()? as i8;
+        Ok(Token::Ext(ext_type, self.read_bytes(len)?))
+    }
+
+    pub fn read_token(&mut self) -> Result<Token<'a>> {
+        let ty = self.read_u8()?;
+        let token = match ty {
+            0x00..=0x7F => Token::UInt(ty as u64),
+            0x80..=0x8F => Token::Map((ty & 0xF) as u32),
+            0x90..=0x9F => Token::Array((ty & 0xF) as u32),
+            0xA0..=0xBF => Token::Str(self.read_bytes((ty & 0x1F) as usize)?),
+            0xC0 => Token::Nil,
+            0xC1 => return Err(DecodeError::InvalidType(ty)),
+            0xC2 => Token::Bool(false),
+            0xC3 => Token::Bool(true),
+            0xC4 => {
+                let len = self.read_u8()? as usize;
+                Token::Bin(self.read_bytes(len)?)
+            }
+            0xC5 => {
+                let len = self.read_u16()? as usize;
+                Token::Bin(self.read_bytes(len)?)
+            }
+            0xC6 => {
+                let len = self.read_u32()? as usize;
+                Token::Bin(self.read_bytes(len)?)
+            }
+            0xC7 => {
+                let len = self.read_u8()? as usize;
+                self.read_ext(len)?
+            }
+            0xC8 => {
+                let len = self.read_u16()? as usize;
+                self.read_ext(len)?
+            }
+            0xC9 => {
+                let len = self.read_u32()? as usize;
+                self.read_ext(len)?
+            }
+            0xCA => Token::F32(f32::from_bits(self.read_u32()?)),
+            0xCB => Token::F64(f64::from_bits(self.read_u64()?)),
+            0xCC => Token::UInt(self.read_u8()? as u64),
+            0xCD => Token::UInt(self.read_u16()? as u64),
+            0xCE => Token::UInt(self.read_u32()? as u64),
+            0xCF => Token::UInt(self.read_u64()?),
+            0xD0 => Token::Int(self.read_u8()? as i8 as i64),
+            0xD1 => Token::Int(self.read_u16()? as i16 as i64),
+            0xD2 => Token::Int(self.read_u32()? as i32 as i64),
+            0xD3 => Token::Int(self.read_u64()? as i64),
+            0xD4 => self.read_ext(1)?,
+            0xD5 => self.read_ext(2)?,
+            0xD6 => self.read_ext(4)?,
+            0xD7 => self.read_ext(8)?,
+            0xD8 => self.read_ext(16)?,
+            0xD9 => {
+                let len = self.read_u8()? as usize;
+                Token::Str(self.read_bytes(len)?)
+            }
+            0xDA => {
+                let len = self.read_u16()? as usize;
+                Token::Str(self.read_bytes(len)?)
+            }
+            0xDB => {
+                let len = self.read_u32()? as usize;
+                Token::Str(self.read_bytes(len)?)
+            }
+            0xDC => Token::Array(self.read_u16()? as u32),
+            0xDD => Token::Array(self.read_u32()?),
+            0xDE => Token::Map(self.read_u16()? as u32),
+            0xDF => Token::Map(self.read_u32()?),
+            0xE0..=0xFF => Token::Int(ty as i8 as i64),
+        };
+        Ok(token)
+    }
+
+    fn skip_value_at(&mut self, depth: usize) -> Result<()> {
+        let element_count = match self.read_token()? {
+            Token::Array(len) => len as u64,
+            Token::Map(len) => 2 * len as u64,
+            _ => return Ok(()),
+        };
+        if depth >= MAX_DEPTH {
+            return Err(DecodeError::TooDeep);
+        }
+
+        // Every element takes at least a byte, so bogus lengths just fail with UnexpectedEnd
+        for _ in 0..element_count {
+            self.skip_value_at(depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Skips a whole value, including all the elements of arrays and maps
+    pub fn skip_value(&mut self) -> Result<()> {
+        self.skip_value_at(0)
+    }
+}
+
+/// Checks that the buffer holds exactly one well-formed value
+pub fn validate(data: &[u8]) -> Result<()> {
+    let mut reader = Reader::new(data);
+    reader.skip_value()?;
+    match reader.get_remaining().len() {
+        0 => Ok(()),
+        trailing_size => Err(DecodeError::TrailingData(trailing_size)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    // Arrays nested `depth` levels deep, the innermost one empty
+    fn nested_arrays(depth: usize) -> Vec<u8> {
+        let mut data = std::vec![0x91; depth - 1];
+        data.push(0x90);
+        data
+    }
+
+    #[test]
+    fn read_tokens() {
+        let mut reader = Reader::new(&[0x92, 0xA2, b'h', b'i', 0xD0, 0x80, 0xC4, 0x01, 0xFF, 0xD4, 0x05, 0xAA, 0xCB, 0x3F, 0xF8, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(reader.read_token(), Ok(Token::Array(2)));
+        assert_eq!(reader.read_token(), Ok(Token::Str(b"hi")));
+        assert_eq!(reader.read_token(), Ok(Token::Int(-128)));
+        assert_eq!(reader.read_token(), Ok(Token::Bin(&[0xFF])));
+        assert_eq!(reader.read_token(), Ok(Token::Ext(5, &[0xAA])));
+        assert_eq!(reader.read_token(), Ok(Token::F64(1.5)));
+        assert!(reader.is_empty());
+        assert_eq!(reader.read_token(), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn truncated() {
+        // Every kind of length or payload cut short
+        let values: &[&[u8]] = &[
+            &[0xCD, 0x01],
+            &[0xCF, 0, 0, 0, 0, 0, 0, 0],
+            &[0xCB, 0x3F, 0xF8],
+            &[0xA3, b'a', b'b'],
+            &[0xD9],
+            &[0xDA, 0x00, 0x05, b'a'],
+            &[0xC4, 0x02, 0x00],
+            &[0xC7, 0x02, 0x01, 0x00],
+            &[0xD6, 0x01, 0x00],
+            &[0x92, 0x01],
+            &[0x81, 0xA1, b'a'],
+            &[0xDC, 0x00],
+        ];
+        for value in values {
+            assert_eq!(validate(value), Err(DecodeError::UnexpectedEnd), "{:02X?}", value);
+        }
+        assert_eq!(validate(&[]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn bogus_lengths() {
+        // Huge element counts and sizes fail once the data runs out, without allocating or looping for long
+        assert_eq!(validate(&[0xDD, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(validate(&[0xDF, 0xFF, 0xFF, 0xFF, 0xFF]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(validate(&[0xDB, 0xFF, 0xFF, 0xFF, 0xFF, b'a']), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(validate(&[0xC9, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn invalid_type() {
+        assert_eq!(validate(&[0xC1]), Err(DecodeError::InvalidType(0xC1)));
+        assert_eq!(validate(&[0x81, 0xA1, b'a', 0x91, 0xC1]), Err(DecodeError::InvalidType(0xC1)));
+    }
+
+    #[test]
+    fn trailing_data() {
+        assert_eq!(validate(&[0xC0, 0xC0]), Err(DecodeError::TrailingData(1)));
+        assert_eq!(validate(&[0x91, 0x01, 0x02, 0x03]), Err(DecodeError::TrailingData(2)));
+    }
+
+    #[test]
+    fn max_depth() {
+        assert_eq!(validate(&nested_arrays(MAX_DEPTH)), Ok(()));
+        assert_eq!(validate(&nested_arrays(MAX_DEPTH + 1)), Err(DecodeError::TooDeep));
+
+        // Maps count towards the depth too
+        let mut data = std::vec![0x81, 0xC0];
+        data.extend(nested_arrays(MAX_DEPTH));
+        assert_eq!(validate(&data), Err(DecodeError::TooDeep));
+
+        // Values past the limit are rejected before reading their elements
+        let mut reader = Reader::new(&[0x91; MAX_DEPTH + 1]);
+        assert_eq!(reader.skip_value(), Err(DecodeError::TooDeep));
+    }
+}
@@ -0,0 +1,276 @@
+//! Scrubbing of reports before they are saved or shared
+//!
+//! Values under some keys (at any depth) can be dropped or replaced by a salted hash, long strings can be truncated, and the
+//! user ID in the metadata can be hashed too (leaving out the nickname). Hashes are FNV-1a, which is enough to tell values
+//! apart across reports without showing them, but not meant to withstand a determined attacker knowing the salt.
+
+use core::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    Keep,
+    /// Leave out both the key and its value
+    Drop,
+    /// Replace the value by its hash
+    Hash,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RedactionRules<'a, K: AsRef<str>> {
+    pub drop_keys: &'a [K],
+    pub hash_keys: &'a [K],
+    /// Strings longer than this many bytes get cut (at a character boundary) and end with `...`
+    pub max_string_len: Option<usize>,
+    pub hash_user_ids: bool,
+    pub hash_salt: u64,
+}
+
+impl<K: AsRef<str>> RedactionRules<'_, K> {
+    pub fn get_key_action(&self, key: &[u8]) -> KeyAction {
+        if self.drop_keys.iter().any(|drop_key| drop_key.as_ref().as_bytes() == key) {
+            KeyAction::Drop
+        } else if self.hash_keys.iter().any(|hash_key| hash_key.as_ref().as_bytes() == key) {
+            KeyAction::Hash
+        } else {
+            KeyAction::Keep
+        }
+    }
+
+    pub fn hash(&self, data: &[u8]) -> u64 {
+        hash(self.hash_salt, data)
+    }
+
+    /// Gets the part of a string to keep, and whether it was truncated
+    pub fn truncate_str<'s>(&self, s: &'s [u8]) -> (&'s [u8], bool) {
+        match self.max_string_len {
+            Some(max_len) if s.len() > max_len => {
+                // Don't leave half a UTF-8 sequence behind
+                let mut len = max_len;
+                while (len > 0) && ((s[len] & 0xC0) == 0x80) {
+                    len -= 1;
+                }
+                (&s[..len], true)
+            }
+            _ => (s, false),
+        }
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF29CE484222325;
+const FNV_PRIME: u64 = 0x100000001B3;
+
+/// Salted 64-bit FNV-1a
+pub fn hash(salt: u64, data: &[u8]) -> u64 {
+    salt.to_le_bytes()
+        .iter()
+        .chain(data)
+        .fold(FNV_OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Displays a hash as a quoted JSON string, like `"hash:0123456789abcdef"`
+pub struct HashStr(pub u64);
+
+impl fmt::Display for HashStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"hash:{:016x}\"", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::json;
+    use crate::msgpack::Reader;
+    use crate::report::{self, ReportKind, ReportMetadata};
+    use std::format;
+    use std::string::String;
+    use std::vec::Vec;
+
+    const NO_KEYS: &[&str] = &[];
+    const USER_ID: u128 = 0x0123456789ABCDEF0123456789ABCDEF;
+
+    fn rules<'a>(drop_keys: &'a [&'a str], hash_keys: &'a [&'a str], max_string_len: Option<usize>) -> RedactionRules<'a, &'a str> {
+        RedactionRules {
+            drop_keys,
+            hash_keys,
+            max_string_len,
+            hash_user_ids: false,
+            hash_salt: 0x1234,
+        }
+    }
+
+    fn str_value(s: &str) -> Vec<u8> {
+        assert!(s.len() < 32);
+        let mut data = std::vec![0xA0 | s.len() as u8];
+        data.extend_from_slice(s.as_bytes());
+        data
+    }
+
+    // Fixmap with the given (already encoded) keys and values
+    fn map_value(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut data = std::vec![0x80 | entries.len() as u8];
+        for (key, value) in entries {
+            data.extend(str_value(key));
+            data.extend_from_slice(value);
+        }
+        data
+    }
+
+    fn array_value(items: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = std::vec![0x90 | items.len() as u8];
+        for item in items {
+            data.extend_from_slice(item);
+        }
+        data
+    }
+
+    fn to_json(msgpack_buf: &[u8], rules: &RedactionRules<&str>) -> String {
+        let mut out = String::new();
+        json::write_value_redacted(&mut Reader::new(msgpack_buf), &mut out, rules).unwrap();
+        out
+    }
+
+    fn metadata() -> ReportMetadata<'static> {
+        ReportMetadata {
+            kind: ReportKind::Normal,
+            room: b"test_room\0\0",
+            process_id: Some(0x51),
+            application_id: Some(0x0100000000001000),
+            user_id: Some(USER_ID),
+            user_nickname: Some("nickname"),
+        }
+    }
+
+    #[test]
+    fn drops_keys() {
+        let report = map_value(&[("secret", str_value("x")), ("a", std::vec![0x01]), ("b", std::vec![0x02])]);
+        assert_eq!(to_json(&report, &rules(&["secret"], NO_KEYS, None)), r#"{"a":1,"b":2}"#);
+
+        let report = map_value(&[("a", std::vec![0x01]), ("secret", str_value("x"))]);
+        assert_eq!(to_json(&report, &rules(&["secret"], NO_KEYS, None)), r#"{"a":1}"#);
+
+        let report = map_value(&[("secret", str_value("x"))]);
+        assert_eq!(to_json(&report, &rules(&["secret"], NO_KEYS, None)), "{}");
+    }
+
+    #[test]
+    fn drop_wins_over_hash() {
+        let report = map_value(&[("id", str_value("x"))]);
+        assert_eq!(to_json(&report, &rules(&["id"], &["id"], None)), "{}");
+    }
+
+    #[test]
+    fn hashes_keys() {
+        let report = map_value(&[("id", str_value("abc"))]);
+        let rules = rules(NO_KEYS, &["id"], None);
+        // The hash covers the whole MessagePack value, not just the string contents
+        let expected = format!("{{\"id\":{}}}", HashStr(rules.hash(&str_value("abc"))));
+        assert_eq!(to_json(&report, &rules), expected);
+
+        let other_salt_rules = RedactionRules { hash_salt: 0x5678, ..rules };
+        assert_ne!(to_json(&report, &other_salt_rules), expected);
+    }
+
+    #[test]
+    fn hash_is_salted_fnv1a() {
+        // Plain 64-bit FNV-1a of the salt bytes followed by the data
+        let unsalted_fnv1a = |data: &[u8]| data.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME));
+        assert_eq!(unsalted_fnv1a(b"a"), 0xAF63DC4C8601EC8C);
+        let mut salted_data = 0x1234u64.to_le_bytes().to_vec();
+        salted_data.extend_from_slice(b"data");
+        assert_eq!(hash(0x1234, b"data"), unsalted_fnv1a(&salted_data));
+        assert_ne!(hash(0x1234, b"data"), hash(0x1235, b"data"));
+        assert_eq!(format!("{}", HashStr(0xAF63DC4C8601EC8C)), "\"hash:af63dc4c8601ec8c\"");
+    }
+
+    #[test]
+    fn hashes_user_ids() {
+        let report = map_value(&[]);
+        let hash_rules = RedactionRules {
+            hash_user_ids: true,
+            ..rules(NO_KEYS, NO_KEYS, None)
+        };
+        let user_id_hash = hash_rules.hash(&USER_ID.to_le_bytes());
+
+        let mut out = String::new();
+        report::write_report_json_redacted(&metadata(), &report, &hash_rules, &mut out).unwrap();
+        assert_eq!(
+            out,
+            format!(
+                "{{\"kind\":\"Normal\",\"room\":\"test_room\",\"process_id\":\"0x51\",\"application_id\":\"0x0100000000001000\",\"user_id\":{},\"report\":{{}}}}",
+                HashStr(user_id_hash)
+            )
+        );
+
+        // Same salt, same hash, so reports from the same user can still be told apart from others'
+        let mut same_salt_out = String::new();
+        report::write_report_json_redacted(&metadata(), &report, &hash_rules, &mut same_salt_out).unwrap();
+        assert_eq!(same_salt_out, out);
+
+        let other_salt_rules = RedactionRules { hash_salt: 0x5678, ..hash_rules };
+        let mut other_salt_out = String::new();
+        report::write_report_json_redacted(&metadata(), &report, &other_salt_rules, &mut other_salt_out).unwrap();
+        assert!(!other_salt_out.contains(&format!("{:016x}", user_id_hash)));
+        assert!(!other_salt_out.contains("user_nickname"));
+    }
+
+    #[test]
+    fn truncates_at_char_boundary() {
+        // 'é' is two bytes and '😀' four, so cutting inside them has to back off to where they start
+        let rules = rules(NO_KEYS, NO_KEYS, Some(2));
+        assert_eq!(rules.truncate_str("héllo".as_bytes()), ("h".as_bytes(), true));
+        assert_eq!(RedactionRules { max_string_len: Some(3), ..rules }.truncate_str("héllo".as_bytes()), ("hé".as_bytes(), true));
+        assert_eq!(RedactionRules { max_string_len: Some(3), ..rules }.truncate_str("a😀".as_bytes()), ("a".as_bytes(), true));
+        assert_eq!(RedactionRules { max_string_len: Some(0), ..rules }.truncate_str("😀".as_bytes()), ("".as_bytes(), true));
+
+        // Strings up to the limit are kept whole
+        assert_eq!(rules.truncate_str(b"ab"), (&b"ab"[..], false));
+        assert_eq!(RedactionRules { max_string_len: None, ..rules }.truncate_str(b"abc"), (&b"abc"[..], false));
+
+        let report = map_value(&[("text", str_value("héllo")), ("short", str_value("h"))]);
+        assert_eq!(to_json(&report, &rules), r#"{"text":"h...","short":"h"}"#);
+    }
+
+    #[test]
+    fn redacts_nested_values() {
+        let report = map_value(&[
+            (
+                "outer",
+                map_value(&[
+                    ("secret", std::vec![0x01]),
+                    ("list", array_value(&[map_value(&[("secret", std::vec![0x02]), ("id", str_value("abc"))]), str_value("long text")])),
+                ]),
+            ),
+            ("id", std::vec![0x03]),
+        ]);
+        let rules = rules(&["secret"], &["id"], Some(4));
+        let expected = format!(
+            r#"{{"outer":{{"list":[{{"id":{}}},"long..."]}},"id":{}}}"#,
+            HashStr(rules.hash(&str_value("abc"))),
+            HashStr(rules.hash(&[0x03]))
+        );
+        assert_eq!(to_json(&report, &rules), expected);
+    }
+
+    #[test]
+    fn disabled_rules_change_nothing() {
+        let report = map_value(&[
+            ("id", str_value("abc")),
+            ("list", array_value(&[std::vec![0x01], map_value(&[("secret", str_value("a long string"))])])),
+        ]);
+        let rules = rules(NO_KEYS, NO_KEYS, None);
+
+        let mut plain_out = String::new();
+        json::write_value(&mut Reader::new(&report), &mut plain_out).unwrap();
+        assert_eq!(to_json(&report, &rules), plain_out);
+
+        let mut plain_report_out = String::new();
+        report::write_report_json(&metadata(), &report, &mut plain_report_out).unwrap();
+        let mut redacted_report_out = String::new();
+        report::write_report_json_redacted(&metadata(), &report, &rules, &mut redacted_report_out).unwrap();
+        assert_eq!(redacted_report_out, plain_report_out);
+        assert!(redacted_report_out.contains(r#""user_nickname":"nickname""#));
+    }
+}
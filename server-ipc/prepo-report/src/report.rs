@@ -0,0 +1,137 @@
+//! Self-describing JSON documents for play reports, holding both the report metadata and its contents
+//!
+//! Documents look like this (IDs are hex strings, since JSON numbers can't hold 64-bit values safely):
+//!
+//! ```json
+//! {"kind":"Normal","room":"...","process_id":"0x51","application_id":"0x0100000000001000","user_id":"0x...","user_nickname":"...","report":{...}}
+//! ```
+//!
+//! IDs which are not known for the command the report came from are left out. If the report is not valid MessagePack,
+//! `report` is `null` and the document gets a `decode_error` description and the `raw_report` data as a hex string instead.
+//!
+//! When redacting (see the `redact` module) invalid reports don't get `raw_report`, since there's no telling what's in there,
+//! and a hashed `user_id` looks like `"hash:0123456789abcdef"` and comes without `user_nickname`.
+
+use core::fmt;
+
+use crate::json::{self, HexStr, JsonStr};
+use crate::msgpack;
+use crate::redact::{HashStr, RedactionRules};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReportKind {
+    /// Sent by applications (`SaveReport` and similar commands)
+    Normal,
+    /// Sent by system programs (`SaveSystemReport` and similar commands)
+    System,
+}
+
+impl ReportKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "Normal" => Some(Self::Normal),
+            "System" => Some(Self::System),
+            _ => None,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "Normal",
+            Self::System => "System",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReportMetadata<'a> {
+    pub kind: ReportKind,
+    /// Event ID string, trailing NUL bytes are ignored
+    pub room: &'a [u8],
+    pub process_id: Option<u64>,
+    pub application_id: Option<u64>,
+    pub user_id: Option<u128>,
+    /// Profile nickname of `user_id`, when it could be resolved
+    pub user_nickname: Option<&'a str>,
+}
+
+impl ReportMetadata<'_> {
+    pub fn get_room(&self) -> &[u8] {
+        let room_len = self.room.iter().position(|&b| b == 0).unwrap_or(self.room.len());
+        &self.room[..room_len]
+    }
+}
+
+fn write_report_json_at<K: AsRef<str>>(
+    metadata: &ReportMetadata,
+    msgpack_buf: &[u8],
+    rules: Option<&RedactionRules<K>>,
+    out: &mut impl fmt::Write,
+) -> fmt::Result {
+    write!(out, "{{\"kind\":\"{}\",\"room\":{}", metadata.kind.name(), JsonStr(metadata.get_room()))?;
+    if let Some(process_id) = metadata.process_id {
+        write!(out, ",\"process_id\":\"0x{:X}\"", process_id)?;
+    }
+    if let Some(application_id) = metadata.application_id {
+        write!(out, ",\"application_id\":\"0x{:016X}\"", application_id)?;
+    }
+    match (metadata.user_id, rules) {
+        (Some(user_id), Some(rules)) if rules.hash_user_ids => {
+            write!(out, ",\"user_id\":{}", HashStr(rules.hash(&user_id.to_le_bytes())))?;
+        }
+        (Some(user_id), _) => {
+            write!(out, ",\"user_id\":\"0x{:032X}\"", user_id)?;
+            if let Some(user_nickname) = metadata.user_nickname {
+                write!(out, ",\"user_nickname\":{}", JsonStr(user_nickname.as_bytes()))?;
+            }
+        }
+        (None, _) => {}
+    }
+
+    out.write_str(",\"report\":")?;
+    match msgpack::validate(msgpack_buf) {
+        Ok(()) => {
+            // Already validated, so this can only fail while writing
+            let mut reader = msgpack::Reader::new(msgpack_buf);
+            match rules {
+                Some(rules) => json::write_value_redacted(&mut reader, out, rules),
+                None => json::write_value(&mut reader, out),
+            }
+            .map_err(|_| fmt::Error)?;
+        }
+        Err(e) => {
+            write!(out, "null,\"decode_error\":\"{}\"", e)?;
+            if rules.is_none() {
+                write!(out, ",\"raw_report\":{}", HexStr(msgpack_buf))?;
+            }
+        }
+    }
+    out.write_str("}")
+}
+
+/// Writes the whole document for a report, see the module docs for its layout
+pub fn write_report_json(metadata: &ReportMetadata, msgpack_buf: &[u8], out: &mut impl fmt::Write) -> fmt::Result {
+    write_report_json_at::<&str>(metadata, msgpack_buf, None, out)
+}
+
+/// Like `write_report_json`, scrubbing the report as the rules say
+pub fn write_report_json_redacted<K: AsRef<str>>(
+    metadata: &ReportMetadata,
+    msgpack_buf: &[u8],
+    rules: &RedactionRules<K>,
+    out: &mut impl fmt::Write,
+) -> fmt::Result {
+    write_report_json_at(metadata, msgpack_buf, Some(rules), out)
+}
+
+/// Displays the document for a report, like `write_report_json`
+pub struct ReportJson<'a, 'b> {
+    pub metadata: &'b ReportMetadata<'a>,
+    pub msgpack_buf: &'b [u8],
+}
+
+impl fmt::Display for ReportJson<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_report_json(self.metadata, self.msgpack_buf, f)
+    }
+}
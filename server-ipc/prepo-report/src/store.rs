@@ -0,0 +1,213 @@
+//! Layout of the report store written by `prepo-mitm`
+//!
+//! Reports are grouped in a directory per day (`YYYY-MM-DD`, UTC) and named after the program sending them plus a per-program counter:
+//!
+//! ```text
+//! <store>/2024-05-17/0100000000001000-00042-Normal.json
+//! ```
+//!
+//! Every saved report gets a line appended to the index file, in the order they were saved, like this:
+//!
+//! ```text
+//! <day> <program ID> <counter> <kind> <size> <room>
+//! ```
+//!
+//! where the day is the amount of days since 1970-01-01 and the rest are in the same format as in the file name (the size being decimal).
+//! The room goes until the end of the line (control characters replaced by `?`, cut to `MAX_INDEX_ROOM_LEN` bytes), and lines written
+//! by older versions don't have it.
+//! The oldest reports are thus always the first lines of the index, which is what makes evicting them cheap.
+//!
+//! Only `prepo-mitm` writes the store, other programs ask it to delete reports through its `prepo:st` service.
+//! The index is only ever rewritten (when evicting or deleting reports) by writing the new one to a temporary file, removing
+//! the old one and renaming the new one over it. A temporary index without an index thus means a rewrite was cut right before
+//! renaming, and the temporary one is the one to use.
+
+use core::fmt;
+
+use crate::report::ReportKind;
+
+pub const INDEX_FILE_NAME: &str = "index.txt";
+pub const INDEX_TMP_FILE_NAME: &str = "index.tmp";
+
+/// Longest room kept in index lines (in bytes), the room is sent by programs and could otherwise be arbitrarily long
+pub const MAX_INDEX_ROOM_LEN: usize = 0x40;
+
+/// Longest index line (without the line ending) an `IndexLine` can be formatted as
+// Day, program ID, counter, kind and size at their longest, each followed by a space, and then the room
+pub const MAX_INDEX_LINE_LEN: usize = 10 + 1 + 16 + 1 + 10 + 1 + 6 + 1 + 20 + 1 + MAX_INDEX_ROOM_LEN;
+
+/// Gets the (year, month, day) of the given amount of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+pub const fn get_day(unix_secs: u64) -> u32 {
+    (unix_secs / 86400) as u32
+}
+
+/// Formats a day (as in `get_day`) as its directory name, `YYYY-MM-DD`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DayDir(pub u32);
+
+impl fmt::Display for DayDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.0 as i64);
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub day: u32,
+    pub program_id: u64,
+    pub counter: u32,
+    pub kind: ReportKind,
+    pub size: u64,
+}
+
+impl IndexEntry {
+    /// Parses an index line (without the line ending), ignoring the room
+    pub fn parse(line: &str) -> Option<Self> {
+        parse_line(line).map(|(entry, _)| entry)
+    }
+
+    /// Gets the path of the report, relative to the store
+    pub fn get_path(&self) -> ReportPath<'_> {
+        ReportPath(self)
+    }
+}
+
+/// Parses an index line (without the line ending) into its entry and room, the room being empty in lines without one
+pub fn parse_line(line: &str) -> Option<(IndexEntry, &str)> {
+    let mut parts = line.splitn(6, ' ');
+    let entry = IndexEntry {
+        day: parts.next()?.parse().ok()?,
+        program_id: u64::from_str_radix(parts.next()?, 16).ok()?,
+        counter: parts.next()?.parse().ok()?,
+        kind: ReportKind::parse(parts.next()?)?,
+        size: parts.next()?.parse().ok()?,
+    };
+    Some((entry, parts.next().unwrap_or("")))
+}
+
+/// Formats the index line of an entry, without the line ending
+pub struct IndexLine<'a> {
+    pub entry: &'a IndexEntry,
+    /// Room as sent with the report (see `ReportMetadata::get_room`)
+    pub room: &'a [u8],
+}
+
+impl fmt::Display for IndexLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entry = self.entry;
+        write!(f, "{} {:016X} {:05} {} {} ", entry.day, entry.program_id, entry.counter, entry.kind.name(), entry.size)?;
+        let mut room_len = 0;
+        for chunk in self.room.utf8_chunks() {
+            // Invalid sequences become a single '?' too
+            let invalid = if chunk.invalid().is_empty() { None } else { Some('?') };
+            for c in chunk.valid().chars().chain(invalid) {
+                // Line endings would break the index
+                let c = if c.is_control() { '?' } else { c };
+                room_len += c.len_utf8();
+                if room_len > MAX_INDEX_ROOM_LEN {
+                    return Ok(());
+                }
+                fmt::Write::write_char(f, c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats the path of a report relative to the store, like `2024-05-17/0100000000001000-00042-Normal.json`
+pub struct ReportPath<'a>(&'a IndexEntry);
+
+impl fmt::Display for ReportPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{:016X}-{:05}-{}.json", DayDir(self.0.day), self.0.program_id, self.0.counter, self.0.kind.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::format;
+
+    const ENTRY: IndexEntry = IndexEntry {
+        day: 19860,
+        program_id: 0x0100000000001000,
+        counter: 42,
+        kind: ReportKind::Normal,
+        size: 1234,
+    };
+
+    #[test]
+    fn day_dirs() {
+        assert_eq!(format!("{}", DayDir(0)), "1970-01-01");
+        assert_eq!(format!("{}", DayDir(19860)), "2024-05-17");
+        assert_eq!(format!("{}", DayDir(11016)), "2000-02-29");
+    }
+
+    #[test]
+    fn report_paths() {
+        assert_eq!(format!("{}", ENTRY.get_path()), "2024-05-17/0100000000001000-00042-Normal.json");
+    }
+
+    #[test]
+    fn line_round_trip() {
+        let line = format!("{}", IndexLine { entry: &ENTRY, room: b"some_room" });
+        assert_eq!(line, "19860 0100000000001000 00042 Normal 1234 some_room");
+        assert_eq!(parse_line(&line), Some((ENTRY, "some_room")));
+        assert_eq!(IndexEntry::parse(&line), Some(ENTRY));
+    }
+
+    #[test]
+    fn lines_without_room() {
+        assert_eq!(parse_line("19860 0100000000001000 00042 Normal 1234"), Some((ENTRY, "")));
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line("19860 0100000000001000 00042 Normal"), None);
+        assert_eq!(parse_line("19860 0100000000001000 00042 Weird 1234 room"), None);
+        assert_eq!(parse_line("19860 XYZ 00042 Normal 1234 room"), None);
+    }
+
+    #[test]
+    fn room_control_chars_and_invalid_utf8() {
+        let line = format!("{}", IndexLine { entry: &ENTRY, room: b"a\nb\xFFc" });
+        assert_eq!(parse_line(&line), Some((ENTRY, "a?b?c")));
+    }
+
+    #[test]
+    fn long_rooms_are_cut() {
+        let longest_entry = IndexEntry {
+            day: u32::MAX,
+            program_id: u64::MAX,
+            counter: u32::MAX,
+            kind: ReportKind::System,
+            size: u64::MAX,
+        };
+        // Multi-byte characters must not be split either
+        let room = "é".repeat(0x200);
+        let line = format!("{}", IndexLine { entry: &longest_entry, room: room.as_bytes() });
+        assert_eq!(line.len(), MAX_INDEX_LINE_LEN);
+
+        let (entry, line_room) = parse_line(&line).unwrap();
+        assert_eq!(entry, longest_entry);
+        assert_eq!(line_room, "é".repeat(MAX_INDEX_ROOM_LEN / 2));
+    }
+}
@@ -0,0 +1,11 @@
+//! UI state of the `prepo-viewer` example, kept apart from its rendering and input handling
+//!
+//! The viewer just feeds button presses to `state::ViewerState` and draws whatever it says, all the navigation logic lives
+//! there.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod state;
+pub mod tree;
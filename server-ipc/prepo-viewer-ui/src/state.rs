@@ -0,0 +1,451 @@
+//! Navigation state of the viewer: a report list grouped by program and room, a tree view of a single report, and delete confirmation
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use prepo_report::store::IndexEntry;
+
+use crate::tree::TreeLine;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReportItem {
+    pub entry: IndexEntry,
+    pub room: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Confirm,
+    Back,
+    Delete,
+    Exit,
+}
+
+/// What the viewer needs to do after an input, everything else is handled by the state itself
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    None,
+    /// Load the report and pass it to `ViewerState::show_report`
+    OpenReport(usize),
+    /// Delete the report and call `ViewerState::remove_item` if that worked
+    DeleteReport(usize),
+    Exit,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Row {
+    /// Header of the reports with the same program and room, starting at the given item
+    Group { first_item: usize, count: usize },
+    Report(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReportView {
+    pub item_idx: usize,
+    /// Lines of the report tree, or why they couldn't be loaded
+    pub lines: core::result::Result<Vec<TreeLine>, String>,
+    pub scroll: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Screen {
+    List,
+    Report(ReportView),
+    ConfirmDelete { item_idx: usize },
+}
+
+pub struct ViewerState {
+    items: Vec<ReportItem>,
+    rows: Vec<Row>,
+    // Row index, always a report row unless there are no reports
+    selected: usize,
+    scroll: usize,
+    page_size: usize,
+    screen: Screen,
+    status: Option<String>,
+}
+
+fn build_rows(items: &[ReportItem]) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let mut group_row = 0;
+    for (item_idx, item) in items.iter().enumerate() {
+        let is_new_group = match item_idx.checked_sub(1).map(|prev_idx| &items[prev_idx]) {
+            Some(prev_item) => (prev_item.entry.program_id != item.entry.program_id) || (prev_item.room != item.room),
+            None => true,
+        };
+        if is_new_group {
+            group_row = rows.len();
+            rows.push(Row::Group {
+                first_item: item_idx,
+                count: 0,
+            });
+        }
+        if let Row::Group { count, .. } = &mut rows[group_row] {
+            *count += 1;
+        }
+        rows.push(Row::Report(item_idx));
+    }
+    rows
+}
+
+impl ViewerState {
+    /// Creates the state for the given reports (in any order), showing `page_size` rows or lines at once
+    pub fn new(mut items: Vec<ReportItem>, page_size: usize) -> Self {
+        items.sort_by(|a, b| {
+            (a.entry.program_id, &a.room, a.entry.day, a.entry.counter).cmp(&(b.entry.program_id, &b.room, b.entry.day, b.entry.counter))
+        });
+        let rows = build_rows(&items);
+
+        let mut state = Self {
+            items,
+            rows,
+            selected: 0,
+            scroll: 0,
+            page_size: page_size.max(1),
+            screen: Screen::List,
+            status: None,
+        };
+        state.select_report_row(0, true);
+        state
+    }
+
+    pub fn get_items(&self) -> &[ReportItem] {
+        &self.items
+    }
+
+    pub fn get_item(&self, item_idx: usize) -> Option<&ReportItem> {
+        self.items.get(item_idx)
+    }
+
+    pub fn get_screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// Gets a message to show along with the current screen, like errors from the last action
+    pub fn get_status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = Some(status);
+    }
+
+    /// Gets the rows of the list which fit in the screen, along with the index of the first one
+    pub fn get_visible_rows(&self) -> (usize, &[Row]) {
+        let end = (self.scroll + self.page_size).min(self.rows.len());
+        (self.scroll, &self.rows[self.scroll..end])
+    }
+
+    pub fn is_selected(&self, row_idx: usize) -> bool {
+        !self.items.is_empty() && (row_idx == self.selected)
+    }
+
+    /// Gets the tree lines of the open report which fit in the screen, if any
+    pub fn get_visible_lines(&self) -> &[TreeLine] {
+        match &self.screen {
+            Screen::Report(ReportView { lines: Ok(lines), scroll, .. }) => {
+                let end = (scroll + self.page_size).min(lines.len());
+                &lines[*scroll..end]
+            }
+            _ => &[],
+        }
+    }
+
+    // Selects the nearest report row from the given row, looking forward or backward
+    fn select_report_row(&mut self, from_row: usize, forward: bool) {
+        if self.rows.is_empty() {
+            self.selected = 0;
+            self.scroll = 0;
+            return;
+        }
+
+        let found = if forward {
+            (from_row..self.rows.len()).find(|&row_idx| matches!(self.rows[row_idx], Row::Report(_)))
+        } else {
+            (0..=from_row.min(self.rows.len() - 1))
+                .rev()
+                .find(|&row_idx| matches!(self.rows[row_idx], Row::Report(_)))
+        };
+        if let Some(row_idx) = found {
+            self.selected = row_idx;
+        }
+
+        // Keep the group header in sight when going up to the first report of a group
+        let first_visible = match self.selected.checked_sub(1).map(|prev_row| self.rows[prev_row]) {
+            Some(Row::Group { .. }) => self.selected - 1,
+            _ => self.selected,
+        };
+        if first_visible < self.scroll {
+            self.scroll = first_visible;
+        } else if self.selected >= self.scroll + self.page_size {
+            self.scroll = self.selected + 1 - self.page_size;
+        }
+    }
+
+    fn move_selection(&mut self, steps: usize, forward: bool) {
+        for _ in 0..steps {
+            let prev_selected = self.selected;
+            if forward {
+                self.select_report_row(self.selected + 1, true);
+            } else if self.selected > 0 {
+                self.select_report_row(self.selected - 1, false);
+            }
+            if self.selected == prev_selected {
+                break;
+            }
+        }
+    }
+
+    fn scroll_report(&mut self, steps: usize, forward: bool) {
+        let page_size = self.page_size;
+        if let Screen::Report(ReportView { lines: Ok(lines), scroll, .. }) = &mut self.screen {
+            let max_scroll = lines.len().saturating_sub(page_size);
+            *scroll = if forward { (*scroll + steps).min(max_scroll) } else { scroll.saturating_sub(steps) };
+        }
+    }
+
+    fn get_selected_item(&self) -> Option<usize> {
+        match self.rows.get(self.selected) {
+            Some(Row::Report(item_idx)) => Some(*item_idx),
+            _ => None,
+        }
+    }
+
+    pub fn handle_input(&mut self, input: Input) -> Action {
+        // Messages only stay until the next input
+        self.status = None;
+
+        if input == Input::Exit {
+            return Action::Exit;
+        }
+
+        match &self.screen {
+            Screen::List => match input {
+                Input::Up => self.move_selection(1, false),
+                Input::Down => self.move_selection(1, true),
+                Input::PageUp => self.move_selection(self.page_size, false),
+                Input::PageDown => self.move_selection(self.page_size, true),
+                Input::Confirm => {
+                    if let Some(item_idx) = self.get_selected_item() {
+                        return Action::OpenReport(item_idx);
+                    }
+                }
+                Input::Delete => {
+                    if let Some(item_idx) = self.get_selected_item() {
+                        self.screen = Screen::ConfirmDelete { item_idx };
+                    }
+                }
+                _ => {}
+            },
+            Screen::Report(view) => match input {
+                Input::Up => self.scroll_report(1, false),
+                Input::Down => self.scroll_report(1, true),
+                Input::PageUp => self.scroll_report(self.page_size, false),
+                Input::PageDown => self.scroll_report(self.page_size, true),
+                Input::Back => self.screen = Screen::List,
+                Input::Delete => {
+                    self.screen = Screen::ConfirmDelete { item_idx: view.item_idx };
+                }
+                _ => {}
+            },
+            Screen::ConfirmDelete { item_idx } => match input {
+                Input::Confirm => return Action::DeleteReport(*item_idx),
+                Input::Back => self.screen = Screen::List,
+                _ => {}
+            },
+        }
+        Action::None
+    }
+
+    /// Shows a report after `Action::OpenReport`
+    pub fn show_report(&mut self, item_idx: usize, lines: core::result::Result<Vec<TreeLine>, String>) {
+        self.screen = Screen::Report(ReportView { item_idx, lines, scroll: 0 });
+    }
+
+    /// Removes a report after `Action::DeleteReport`, going back to the list
+    pub fn remove_item(&mut self, item_idx: usize) {
+        if item_idx >= self.items.len() {
+            return;
+        }
+
+        self.items.remove(item_idx);
+        self.rows = build_rows(&self.items);
+        self.screen = Screen::List;
+
+        // Stay around the same place, on the report which took the place of the removed one (or the one before it)
+        self.scroll = self.scroll.min(self.rows.len().saturating_sub(1));
+        let selected = self.selected.min(self.rows.len().saturating_sub(1));
+        self.select_report_row(selected, true);
+        if !matches!(self.rows.get(self.selected), Some(Row::Report(_))) {
+            self.select_report_row(selected, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use prepo_report::report::ReportKind;
+
+    fn item(program_id: u64, room: &str, counter: u32) -> ReportItem {
+        ReportItem {
+            entry: IndexEntry {
+                day: 19000,
+                program_id,
+                counter,
+                kind: ReportKind::Normal,
+                size: 0x100,
+            },
+            room: String::from(room),
+        }
+    }
+
+    fn get_selected_row(state: &ViewerState) -> Option<usize> {
+        (0..state.rows.len()).find(|&row_idx| state.is_selected(row_idx))
+    }
+
+    // A single group with the given amount of reports
+    fn single_group(count: u32, page_size: usize) -> ViewerState {
+        ViewerState::new((0..count).map(|counter| item(1, "room", counter)).collect(), page_size)
+    }
+
+    #[test]
+    fn groups_by_program_and_room() {
+        let state = ViewerState::new(
+            vec![item(2, "a", 0), item(1, "b", 0), item(1, "a", 1), item(1, "a", 0)],
+            10,
+        );
+
+        let rooms: Vec<_> = state.get_items().iter().map(|item| (item.entry.program_id, item.room.as_str(), item.entry.counter)).collect();
+        assert_eq!(rooms, vec![(1, "a", 0), (1, "a", 1), (1, "b", 0), (2, "a", 0)]);
+        assert_eq!(state.get_visible_rows(), (0, &[
+            Row::Group { first_item: 0, count: 2 },
+            Row::Report(0),
+            Row::Report(1),
+            Row::Group { first_item: 2, count: 1 },
+            Row::Report(2),
+            Row::Group { first_item: 3, count: 1 },
+            Row::Report(3),
+        ][..]));
+    }
+
+    #[test]
+    fn selection_skips_group_rows() {
+        let mut state = ViewerState::new(vec![item(1, "a", 0), item(1, "a", 1), item(1, "b", 0), item(2, "a", 0)], 10);
+        assert_eq!(get_selected_row(&state), Some(1));
+
+        let expected_rows = [2, 4, 6, 6];
+        for expected_row in expected_rows {
+            assert_eq!(state.handle_input(Input::Down), Action::None);
+            assert_eq!(get_selected_row(&state), Some(expected_row));
+        }
+
+        let expected_rows = [4, 2, 1, 1];
+        for expected_row in expected_rows {
+            state.handle_input(Input::Up);
+            assert_eq!(get_selected_row(&state), Some(expected_row));
+        }
+    }
+
+    #[test]
+    fn paging() {
+        // Rows are the group header and then reports 0-19
+        let mut state = single_group(20, 5);
+        assert_eq!(state.get_visible_rows().0, 0);
+
+        state.handle_input(Input::PageDown);
+        assert_eq!(get_selected_row(&state), Some(6));
+        assert_eq!(state.get_visible_rows().0, 2);
+        assert_eq!(state.get_visible_rows().1.len(), 5);
+
+        // Stops at the last report
+        for _ in 0..10 {
+            state.handle_input(Input::PageDown);
+        }
+        assert_eq!(get_selected_row(&state), Some(20));
+        assert_eq!(state.get_visible_rows(), (16, &[Row::Report(15), Row::Report(16), Row::Report(17), Row::Report(18), Row::Report(19)][..]));
+
+        // Going back to the top brings the group header back in sight
+        for _ in 0..10 {
+            state.handle_input(Input::PageUp);
+        }
+        assert_eq!(get_selected_row(&state), Some(1));
+        assert_eq!(state.get_visible_rows().0, 0);
+    }
+
+    #[test]
+    fn open_and_delete_report() {
+        let mut state = single_group(3, 10);
+        state.handle_input(Input::Down);
+        assert_eq!(state.handle_input(Input::Confirm), Action::OpenReport(1));
+
+        state.show_report(1, Ok(Vec::new()));
+        state.handle_input(Input::Delete);
+        assert_eq!(state.get_screen(), &Screen::ConfirmDelete { item_idx: 1 });
+        state.handle_input(Input::Back);
+        assert_eq!(state.get_screen(), &Screen::List);
+
+        state.handle_input(Input::Delete);
+        assert_eq!(state.handle_input(Input::Confirm), Action::DeleteReport(1));
+    }
+
+    #[test]
+    fn remove_item_selects_next_report() {
+        let mut state = single_group(3, 10);
+        state.handle_input(Input::Down);
+        assert_eq!(get_selected_row(&state), Some(2));
+
+        state.remove_item(1);
+        assert_eq!(state.get_screen(), &Screen::List);
+        assert_eq!(state.get_items().len(), 2);
+        // The report after the removed one took its place
+        assert_eq!(get_selected_row(&state), Some(2));
+        assert_eq!(state.handle_input(Input::Confirm), Action::OpenReport(1));
+        assert_eq!(state.get_item(1).map(|item| item.entry.counter), Some(2));
+    }
+
+    #[test]
+    fn remove_last_item_selects_previous_report() {
+        let mut state = single_group(3, 10);
+        state.handle_input(Input::PageDown);
+        assert_eq!(get_selected_row(&state), Some(3));
+
+        state.remove_item(2);
+        assert_eq!(get_selected_row(&state), Some(2));
+        assert_eq!(state.handle_input(Input::Confirm), Action::OpenReport(1));
+    }
+
+    #[test]
+    fn remove_item_skips_removed_group() {
+        let mut state = ViewerState::new(vec![item(1, "a", 0), item(2, "b", 0), item(3, "c", 0)], 10);
+        state.handle_input(Input::Down);
+        assert_eq!(get_selected_row(&state), Some(3));
+
+        // The whole group goes away, so the selection lands on the report of the next group instead of its header
+        state.remove_item(1);
+        assert_eq!(state.get_visible_rows().1.len(), 4);
+        assert_eq!(get_selected_row(&state), Some(3));
+        assert_eq!(state.handle_input(Input::Confirm), Action::OpenReport(1));
+        assert_eq!(state.get_item(1).map(|item| item.entry.program_id), Some(3));
+    }
+
+    #[test]
+    fn remove_every_item() {
+        let mut state = single_group(2, 10);
+        state.remove_item(0);
+        state.remove_item(0);
+        // Out of range, nothing happens
+        state.remove_item(0);
+
+        assert!(state.get_items().is_empty());
+        assert_eq!(get_selected_row(&state), None);
+        assert_eq!(state.handle_input(Input::Confirm), Action::None);
+        assert_eq!(state.handle_input(Input::Delete), Action::None);
+        assert_eq!(state.get_screen(), &Screen::List);
+    }
+}
@@ -0,0 +1,246 @@
+//! Flattening of the JSON report documents into lines of a key/value tree
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+// Way deeper than any report, just to keep broken documents from blowing up the stack
+const MAX_DEPTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeLine {
+    pub depth: usize,
+    /// Object key, or `[index]` for array items
+    pub key: String,
+    /// Value as text, or the item count (like `{3}` or `[3]`) for objects and arrays
+    pub value: String,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TreeError {
+    /// Byte offset where the document stopped making sense
+    pub offset: usize,
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid JSON at offset {}", self.offset)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, TreeError>;
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    lines: Vec<TreeLine>,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self) -> Result<T> {
+        Err(TreeError { offset: self.pos })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.input.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error()
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32> {
+        let hex = self.input.get(self.pos..self.pos + 4).and_then(|hex| core::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u32::from_str_radix(hex, 16).ok()) {
+            Some(value) => {
+                self.pos += 4;
+                Ok(value)
+            }
+            None => self.error(),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while self.input.get(self.pos).is_some_and(|&b| (b != b'"') && (b != b'\\')) {
+                self.pos += 1;
+            }
+            match core::str::from_utf8(&self.input[start..self.pos]) {
+                Ok(chunk) => out.push_str(chunk),
+                Err(_) => return self.error(),
+            }
+
+            match self.input.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escape = self.input.get(self.pos).copied();
+                    self.pos += 1;
+                    let c = match escape {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let mut code = self.parse_hex4()?;
+                            // Surrogate pairs come as two escapes
+                            if (0xD800..0xDC00).contains(&code) && self.input.get(self.pos..self.pos + 2) == Some(b"\\u") {
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return self.error(),
+                    };
+                    out.push(c);
+                }
+                _ => return self.error(),
+            }
+        }
+    }
+
+    // Numbers, booleans and null are shown just as they are written
+    fn parse_literal(&mut self) -> Result<String> {
+        let start = self.pos;
+        while self.input.get(self.pos).is_some_and(|&b| b.is_ascii_alphanumeric() || (b == b'-') || (b == b'+') || (b == b'.')) {
+            self.pos += 1;
+        }
+
+        let literal = core::str::from_utf8(&self.input[start..self.pos]).unwrap_or("");
+        let is_valid = matches!(literal, "true" | "false" | "null") || literal.parse::<f64>().is_ok();
+        if is_valid {
+            Ok(String::from(literal))
+        } else {
+            self.pos = start;
+            self.error()
+        }
+    }
+
+    // Parses the members of an object (after its opening brace), returning their count
+    fn parse_members(&mut self, depth: usize) -> Result<usize> {
+        let mut count = 0;
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(count);
+        }
+
+        loop {
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            self.parse_value(depth, key)?;
+            count += 1;
+
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(count);
+                }
+                _ => return self.error(),
+            }
+        }
+    }
+
+    // Parses the items of an array (after its opening bracket), returning their count
+    fn parse_items(&mut self, depth: usize) -> Result<usize> {
+        let mut count = 0;
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(count);
+        }
+
+        loop {
+            self.parse_value(depth, format!("[{}]", count))?;
+            count += 1;
+
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(count);
+                }
+                _ => return self.error(),
+            }
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize, key: String) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return self.error();
+        }
+
+        match self.peek() {
+            Some(open @ (b'{' | b'[')) => {
+                self.pos += 1;
+                let line_idx = self.lines.len();
+                self.lines.push(TreeLine {
+                    depth,
+                    key,
+                    value: String::new(),
+                });
+
+                let value = if open == b'{' {
+                    format!("{{{}}}", self.parse_members(depth + 1)?)
+                } else {
+                    format!("[{}]", self.parse_items(depth + 1)?)
+                };
+                self.lines[line_idx].value = value;
+            }
+            Some(b'"') => {
+                let value = self.parse_string()?;
+                self.lines.push(TreeLine { depth, key, value });
+            }
+            Some(_) => {
+                let value = self.parse_literal()?;
+                self.lines.push(TreeLine { depth, key, value });
+            }
+            None => return self.error(),
+        }
+        Ok(())
+    }
+}
+
+/// Flattens a JSON document into tree lines, where the members of a top-level object are the lines at depth 0
+pub fn parse_tree(json: &str) -> Result<Vec<TreeLine>> {
+    let mut parser = Parser {
+        input: json.as_bytes(),
+        pos: 0,
+        lines: Vec::new(),
+    };
+
+    if parser.peek() == Some(b'{') {
+        parser.pos += 1;
+        parser.parse_members(0)?;
+    } else {
+        parser.parse_value(0, String::new())?;
+    }
+
+    if parser.peek().is_some() {
+        return parser.error();
+    }
+    Ok(parser.lines)
+}
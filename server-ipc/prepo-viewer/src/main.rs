@@ -0,0 +1,280 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use nx::diag::abort;
+use nx::diag::log::lm::LmLogger;
+use nx::fs;
+use nx::fs::FileOpenOption;
+use nx::gpu;
+use nx::gpu::canvas::AlphaBlend;
+use nx::gpu::canvas::BufferedCanvas;
+use nx::gpu::canvas::Canvas;
+use nx::gpu::canvas::RGBA8;
+use nx::input;
+use nx::result::*;
+use nx::service;
+use nx::service::hid;
+use nx::svc;
+use nx::sync::RwLock;
+use nx::util;
+
+use prepo_ipc::{IReportStoreClient, ReportStore};
+use prepo_report::report::ReportKind;
+use prepo_report::store::{self, DayDir, IndexEntry};
+use prepo_viewer_ui::state::{Action, Input, ReportItem, Row, Screen, ViewerState};
+use prepo_viewer_ui::tree;
+
+use core::panic;
+
+nx::rrt0_define_module_name!("prepo-viewer");
+
+// Where prepo-mitm saves reports, see prepo_report::store
+const STORE_PATH: &str = "sdmc:/prepo";
+
+// Bitmap font at scale 2, so 16x16 characters
+const TEXT_SCALE: u32 = 2;
+const CHAR_WIDTH: i32 = 16;
+const LINE_HEIGHT: i32 = 20;
+const MAX_LINE_CHARS: usize = 78;
+const MARGIN: i32 = 12;
+const ROWS_Y: i32 = 50;
+const PAGE_SIZE: usize = 30;
+
+#[no_mangle]
+pub fn initialize_heap(hbl_heap: util::PointerAndSize) -> util::PointerAndSize {
+    if hbl_heap.is_valid() {
+        hbl_heap
+    } else {
+        let heap_size: usize = 0x10000000;
+        let heap_address = svc::set_heap_size(heap_size).unwrap();
+        util::PointerAndSize::new(heap_address, heap_size)
+    }
+}
+
+fn get_index_path() -> String {
+    format!("{}/{}", STORE_PATH, store::INDEX_FILE_NAME)
+}
+
+fn get_index_tmp_path() -> String {
+    format!("{}/{}", STORE_PATH, store::INDEX_TMP_FILE_NAME)
+}
+
+fn get_report_path(entry: &IndexEntry) -> String {
+    format!("{}/{}", STORE_PATH, entry.get_path())
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>> {
+    let mut file = fs::open_file(path, FileOpenOption::Read())?;
+
+    let mut data = Vec::new();
+    let mut chunk_buf = [0u8; 0x1000];
+    loop {
+        let read_size = file.read_array(&mut chunk_buf)?;
+        if read_size == 0 {
+            break;
+        }
+        data.extend_from_slice(&chunk_buf[..read_size]);
+    }
+    Ok(data)
+}
+
+fn load_items() -> Vec<ReportItem> {
+    // prepo-mitm might be right in the middle of swapping a rewritten index in (see prepo_report::store), and no index at
+    // all just means no reports saved yet
+    let index_data = read_file(get_index_path().as_str())
+        .or_else(|_| read_file(get_index_tmp_path().as_str()))
+        .unwrap_or_default();
+    String::from_utf8_lossy(&index_data)
+        .lines()
+        .filter_map(store::parse_line)
+        .map(|(entry, room)| ReportItem {
+            entry,
+            room: String::from(room),
+        })
+        .collect()
+}
+
+fn load_report(entry: &IndexEntry) -> core::result::Result<Vec<tree::TreeLine>, String> {
+    let report_data = read_file(get_report_path(entry).as_str()).map_err(|e| format!("Unable to read report: {:#X}", e.get_value()))?;
+    tree::parse_tree(String::from_utf8_lossy(&report_data).as_ref()).map_err(|e| format!("Unable to parse report: {}", e))
+}
+
+// prepo-mitm is the only one writing the store (it would otherwise lose track of its size), so it's asked to delete the report
+fn delete_report(entry: &IndexEntry) -> Result<()> {
+    let mut report_store = service::new_service_object::<ReportStore>()?;
+    report_store.delete_report(entry.program_id, entry.day, entry.counter, entry.kind == ReportKind::System)
+}
+
+// The bitmap font only has ASCII characters
+fn make_line(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '?' })
+        .take(MAX_LINE_CHARS)
+        .collect()
+}
+
+fn get_row_text(state: &ViewerState, row: &Row) -> String {
+    match *row {
+        Row::Group { first_item, count } => {
+            let item = &state.get_items()[first_item];
+            make_line(format!("{:016X}  {}  ({})", item.entry.program_id, item.room, count).as_str())
+        }
+        Row::Report(item_idx) => {
+            let entry = &state.get_items()[item_idx].entry;
+            make_line(format!("    {}  #{:05}  {}  {} bytes", DayDir(entry.day), entry.counter, entry.kind.name(), entry.size).as_str())
+        }
+    }
+}
+
+fn get_item_title(item: &ReportItem) -> String {
+    make_line(format!("{:016X} {} {} #{:05}", item.entry.program_id, item.room, DayDir(item.entry.day), item.entry.counter).as_str())
+}
+
+fn draw_row(surface: &mut BufferedCanvas<'_, RGBA8>, text: &str, color: RGBA8, row: usize) {
+    surface.draw_ascii_bitmap_text(text, color, TEXT_SCALE, MARGIN, ROWS_Y + LINE_HEIGHT * row as i32, AlphaBlend::None);
+}
+
+fn render_state(surface: &mut BufferedCanvas<'_, RGBA8>, state: &ViewerState) {
+    let c_text = RGBA8::new_scaled(0xFF, 0xFF, 0xFF, 0xFF);
+    let c_dim = RGBA8::new_scaled(0x90, 0x90, 0x90, 0xFF);
+    let c_highlight = RGBA8::new_scaled(65, 105, 225, 255);
+    let c_error = RGBA8::new_scaled(0xFF, 0x60, 0x60, 0xFF);
+
+    let (title, help) = match state.get_screen() {
+        Screen::List => {
+            let (first_row, rows) = state.get_visible_rows();
+            for (i, row) in rows.iter().enumerate() {
+                let color = match row {
+                    Row::Group { .. } => c_dim,
+                    Row::Report(_) => c_text,
+                };
+                if state.is_selected(first_row + i) {
+                    surface.draw_rect(MARGIN - 4, ROWS_Y + LINE_HEIGHT * i as i32 - 2, (CHAR_WIDTH * MAX_LINE_CHARS as i32 + 8) as u32, LINE_HEIGHT as u32, c_highlight, AlphaBlend::None);
+                }
+                draw_row(surface, get_row_text(state, row).as_str(), color, i);
+            }
+            if state.get_items().is_empty() {
+                draw_row(surface, "No reports saved yet", c_dim, 0);
+            }
+
+            (format!("prepo-viewer - {} reports", state.get_items().len()), "Up/Down: select  Left/Right: page  A: open  X: delete  +: exit")
+        }
+        Screen::Report(view) => {
+            let title = state.get_item(view.item_idx).map(get_item_title).unwrap_or_default();
+            match &view.lines {
+                Ok(_) => {
+                    for (i, line) in state.get_visible_lines().iter().enumerate() {
+                        let text = format!("{:indent$}{}: {}", "", line.key, line.value, indent = line.depth * 2);
+                        draw_row(surface, make_line(text.as_str()).as_str(), c_text, i);
+                    }
+                }
+                Err(e) => draw_row(surface, make_line(e.as_str()).as_str(), c_error, 0),
+            }
+
+            (title, "Up/Down: scroll  Left/Right: page  B: back  X: delete  +: exit")
+        }
+        Screen::ConfirmDelete { item_idx } => {
+            if let Some(item) = state.get_item(*item_idx) {
+                draw_row(surface, "Delete this report?", c_text, 0);
+                draw_row(surface, get_item_title(item).as_str(), c_text, 1);
+            }
+
+            (String::from("prepo-viewer"), "A: delete  B: cancel")
+        }
+    };
+
+    surface.draw_ascii_bitmap_text(make_line(title.as_str()).as_str(), c_text, TEXT_SCALE, MARGIN, MARGIN, AlphaBlend::None);
+    let footer_y = ROWS_Y + LINE_HEIGHT * (PAGE_SIZE as i32 + 1);
+    if let Some(status) = state.get_status() {
+        surface.draw_ascii_bitmap_text(make_line(status).as_str(), c_error, TEXT_SCALE, MARGIN, footer_y - LINE_HEIGHT, AlphaBlend::None);
+    }
+    surface.draw_ascii_bitmap_text(help, c_dim, TEXT_SCALE, MARGIN, footer_y, AlphaBlend::None);
+}
+
+fn get_inputs(input_ctx: &input::Context) -> Vec<Input> {
+    let mut inputs = Vec::new();
+    for controller in [hid::NpadIdType::Handheld, hid::NpadIdType::No1].iter().cloned() {
+        let mut player = input_ctx.get_player(controller);
+
+        let buttons_down = player.get_buttons_down();
+        for (button, input) in [
+            (hid::NpadButton::Up(), Input::Up),
+            (hid::NpadButton::Down(), Input::Down),
+            (hid::NpadButton::Left(), Input::PageUp),
+            (hid::NpadButton::Right(), Input::PageDown),
+            (hid::NpadButton::A(), Input::Confirm),
+            (hid::NpadButton::B(), Input::Back),
+            (hid::NpadButton::X(), Input::Delete),
+            (hid::NpadButton::Plus(), Input::Exit),
+        ] {
+            if buttons_down.contains(button) {
+                inputs.push(input);
+            }
+        }
+    }
+    inputs
+}
+
+#[no_mangle]
+fn main() {
+    fs::initialize_fspsrv_session().expect("Error starting filesystem services");
+    fs::mount_sd_card("sdmc").expect("Failed to mount sd card");
+
+    let supported_style_tags = hid::NpadStyleTag::Handheld()
+        | hid::NpadStyleTag::FullKey()
+        | hid::NpadStyleTag::JoyDual()
+        | hid::NpadStyleTag::JoyLeft()
+        | hid::NpadStyleTag::JoyRight();
+    let input_ctx = input::Context::new(supported_style_tags, 2).expect("Error getting input context");
+
+    let mut canvas_manager = {
+        let gpu_ctx = gpu::Context::new(gpu::NvDrvServiceKind::Applet, gpu::ViServiceKind::System, 0x800000).expect("Error getting gpu context");
+        nx::gpu::canvas::CanvasManager::new_stray(
+            alloc::sync::Arc::new(RwLock::new(gpu_ctx)),
+            Default::default(),
+            3,
+            gpu::BlockLinearHeights::FourGobs,
+        )
+        .expect("Error getting canvas manager")
+    };
+
+    let mut state = ViewerState::new(load_items(), PAGE_SIZE);
+    let c_background = RGBA8::new_scaled(0x20, 0x20, 0x28, 0xFF);
+
+    'render: loop {
+        for input in get_inputs(&input_ctx) {
+            match state.handle_input(input) {
+                Action::None => {}
+                Action::OpenReport(item_idx) => {
+                    let lines = load_report(&state.get_items()[item_idx].entry);
+                    state.show_report(item_idx, lines);
+                }
+                Action::DeleteReport(item_idx) => match delete_report(&state.get_items()[item_idx].entry) {
+                    Ok(()) => state.remove_item(item_idx),
+                    Err(e) => state.set_status(format!("Unable to delete report (is prepo-mitm running?): {:#X}", e.get_value())),
+                },
+                Action::Exit => break 'render,
+            }
+        }
+
+        let state_ref = &state;
+        let _ = canvas_manager.render(Some(c_background), move |surface| {
+            render_state(surface, state_ref);
+            Ok(())
+        });
+
+        let _ = canvas_manager.wait_vsync_event(None);
+    }
+
+    fs::unmount_all();
+}
+
+#[panic_handler]
+fn panic_handler(info: &panic::PanicInfo) -> ! {
+    util::simple_panic_handler::<LmLogger>(info, abort::AbortLevel::FatalThrow())
+}
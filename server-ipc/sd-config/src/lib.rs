@@ -0,0 +1,554 @@
+//! Parser for the small TOML-like config files the sysmodule examples read from the SD card
+//!
+//! Only the subset of TOML these configs need is supported: comments, `[table]` headers (dotted names are kept as a single name,
+//! like `program.0x0100000000001000`) and `key = value` lines, where values are basic strings, integers (decimal or `0x` hex,
+//! `_` separators allowed), booleans and single-line arrays of those.
+//!
+//! `read_config` reads the files themselves, through whichever file API the caller uses.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A table header was not closed with `]` or had an empty name
+    InvalidTableHeader,
+    /// A line was neither a table header nor a `key = value` pair
+    ExpectedKeyValue,
+    /// The key of a `key = value` pair was empty or had unsupported characters
+    InvalidKey,
+    /// A value was not a supported string, integer, boolean or array
+    InvalidValue,
+    /// A string was not closed with `"`
+    UnterminatedString,
+    /// A string had an unsupported escape sequence
+    InvalidEscape,
+    /// An integer did not fit in 64 bits
+    IntegerOverflow,
+    /// Something other than a comment followed a value
+    TrailingCharacters,
+    /// The same key appeared twice in a table
+    DuplicateKey,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let desc = match self {
+            Self::InvalidTableHeader => "invalid table header",
+            Self::ExpectedKeyValue => "expected a key = value pair",
+            Self::InvalidKey => "invalid key",
+            Self::InvalidValue => "invalid value",
+            Self::UnterminatedString => "unterminated string",
+            Self::InvalidEscape => "invalid escape sequence",
+            Self::IntegerOverflow => "integer out of range",
+            Self::TrailingCharacters => "unexpected characters after value",
+            Self::DuplicateKey => "duplicate key",
+        };
+        f.write_str(desc)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line where the error was found
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, ParseError>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Self::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Gets an integer as a `u64`, since hex values (like program IDs) are stored with their raw bits
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_integer().map(|i| i as u64)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(values) => Some(values.as_slice()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Table {
+    /// Empty for the root table (the pairs before any header)
+    pub name: String,
+    pub entries: Vec<(String, Value)>,
+}
+
+impl Table {
+    pub const fn new(name: String) -> Self {
+        Self {
+            name,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Document {
+    /// Tables in the order they appear, the first one is always the root table
+    pub tables: Vec<Table>,
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || (c == '_') || (c == '-') || (c == '.')
+}
+
+// Removes a trailing comment, as long as the '#' is not inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_string(input: &str) -> core::result::Result<(String, &str), ParseErrorKind> {
+    let mut s = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((s, &input[i + 1..])),
+            '\\' => {
+                let escaped = match chars.next().map(|(_, c)| c) {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    _ => return Err(ParseErrorKind::InvalidEscape),
+                };
+                s.push(escaped);
+            }
+            c => s.push(c),
+        }
+    }
+    Err(ParseErrorKind::UnterminatedString)
+}
+
+fn parse_integer(token: &str) -> core::result::Result<i64, ParseErrorKind> {
+    let (negative, token) = match token.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, token.strip_prefix('+').unwrap_or(token)),
+    };
+    let (radix, digits) = match token.strip_prefix("0x") {
+        Some(digits) => (16, digits),
+        None => (10, token),
+    };
+    if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') {
+        return Err(ParseErrorKind::InvalidValue);
+    }
+
+    let mut value: u64 = 0;
+    for c in digits.chars().filter(|&c| c != '_') {
+        let digit = c.to_digit(radix).ok_or(ParseErrorKind::InvalidValue)?;
+        value = value
+            .checked_mul(radix as u64)
+            .and_then(|v| v.checked_add(digit as u64))
+            .ok_or(ParseErrorKind::IntegerOverflow)?;
+    }
+
+    if radix == 16 {
+        // Hex values keep their raw bits, so the full u64 range (program IDs, for instance) can be used
+        match negative {
+            true => Err(ParseErrorKind::InvalidValue),
+            false => Ok(value as i64),
+        }
+    } else if negative {
+        0i64.checked_sub_unsigned(value).ok_or(ParseErrorKind::IntegerOverflow)
+    } else {
+        i64::try_from(value).map_err(|_| ParseErrorKind::IntegerOverflow)
+    }
+}
+
+// Parses a value from the start of the input, returning it and the rest of the input
+fn parse_value(input: &str) -> core::result::Result<(Value, &str), ParseErrorKind> {
+    let input = input.trim_start();
+    if let Some(rest) = input.strip_prefix('"') {
+        let (s, rest) = parse_string(rest)?;
+        return Ok((Value::String(s), rest));
+    }
+
+    if let Some(mut rest) = input.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), after));
+            }
+
+            let (value, after) = parse_value(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err(ParseErrorKind::InvalidValue);
+            }
+        }
+    }
+
+    let token_len = input
+        .find(|c: char| c.is_whitespace() || (c == ',') || (c == ']'))
+        .unwrap_or(input.len());
+    let (token, rest) = input.split_at(token_len);
+    let value = match token {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => Value::Integer(parse_integer(token)?),
+    };
+    Ok((value, rest))
+}
+
+fn parse_table_header(line: &str) -> core::result::Result<String, ParseErrorKind> {
+    let name = line
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .ok_or(ParseErrorKind::InvalidTableHeader)?
+        .trim();
+    if name.is_empty() || !name.chars().all(is_key_char) {
+        return Err(ParseErrorKind::InvalidTableHeader);
+    }
+    Ok(String::from(name))
+}
+
+fn parse_key_value(line: &str) -> core::result::Result<(String, Value), ParseErrorKind> {
+    let (key, value) = line.split_once('=').ok_or(ParseErrorKind::ExpectedKeyValue)?;
+    let key = key.trim();
+    if key.is_empty() || !key.chars().all(is_key_char) {
+        return Err(ParseErrorKind::InvalidKey);
+    }
+
+    let (value, rest) = parse_value(value)?;
+    if !rest.trim().is_empty() {
+        return Err(ParseErrorKind::TrailingCharacters);
+    }
+    Ok((String::from(key), value))
+}
+
+impl Document {
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut tables = alloc::vec![Table::new(String::new())];
+        for (line_idx, line) in input.lines().enumerate() {
+            let error = |kind| ParseError { line: line_idx + 1, kind };
+
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                let name = parse_table_header(line).map_err(error)?;
+                tables.push(Table::new(name));
+            } else {
+                let (key, value) = parse_key_value(line).map_err(error)?;
+                // There's always at least the root table
+                let table = tables.last_mut().unwrap();
+                if table.get(&key).is_some() {
+                    return Err(error(ParseErrorKind::DuplicateKey));
+                }
+                table.entries.push((key, value));
+            }
+        }
+        Ok(Self { tables })
+    }
+
+    pub fn get_root(&self) -> &Table {
+        &self.tables[0]
+    }
+
+    /// Gets the first table with the given name
+    pub fn get_table(&self, name: &str) -> Option<&Table> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+
+    /// Iterates over the tables whose name starts with `prefix` followed by a dot, along with the rest of their name
+    ///
+    /// For instance, with prefix `program` this yields `[program.0x0100000000001000]` as `"0x0100000000001000"`.
+    pub fn iter_subtables<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a Table)> + 'a {
+        self.tables.iter().filter_map(move |table| {
+            let sub_name = table.name.strip_prefix(prefix)?.strip_prefix('.')?;
+            Some((sub_name, table))
+        })
+    }
+}
+
+/// Configs are just a few lines, anything bigger than this is surely not one (and would eat the sysmodules' tiny heaps)
+pub const MAX_CONFIG_SIZE: usize = 0x2000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadError<E> {
+    /// The read function failed
+    Read(E),
+    /// The file is bigger than `MAX_CONFIG_SIZE`
+    TooBig,
+    /// The file is not valid UTF-8, starting at the given (1-based) line
+    InvalidUtf8 { line: usize },
+}
+
+impl<E: fmt::Debug> fmt::Display for ReadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(e) => write!(f, "read error: {:?}", e),
+            Self::TooBig => write!(f, "bigger than {:#X} bytes", MAX_CONFIG_SIZE),
+            Self::InvalidUtf8 { line } => write!(f, "line {}: invalid UTF-8", line),
+        }
+    }
+}
+
+/// Reads a config file through the given function, which fills a buffer and returns how much it read (zero at the end)
+///
+/// Files bigger than `MAX_CONFIG_SIZE` or which aren't valid UTF-8 are rejected as a whole, like the parser does with invalid ones.
+pub fn read_config<E>(mut read_fn: impl FnMut(&mut [u8]) -> core::result::Result<usize, E>) -> core::result::Result<String, ReadError<E>> {
+    let mut config_data = Vec::new();
+    let mut chunk_buf = [0u8; 0x200];
+    loop {
+        let read_size = read_fn(&mut chunk_buf).map_err(ReadError::Read)?;
+        if read_size == 0 {
+            break;
+        }
+        if config_data.len() + read_size > MAX_CONFIG_SIZE {
+            return Err(ReadError::TooBig);
+        }
+        config_data.extend_from_slice(&chunk_buf[..read_size]);
+    }
+
+    String::from_utf8(config_data).map_err(|e| {
+        let valid_data = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+        let line = valid_data.iter().filter(|&&byte| byte == b'\n').count() + 1;
+        ReadError::InvalidUtf8 { line }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    // Reads the data in chunks of at most the given size, like a file would
+    fn read_data(mut data: &[u8], max_chunk_size: usize) -> core::result::Result<String, ReadError<()>> {
+        read_config(|buf: &mut [u8]| -> core::result::Result<usize, ()> {
+            let read_size = data.len().min(buf.len()).min(max_chunk_size);
+            buf[..read_size].copy_from_slice(&data[..read_size]);
+            data = &data[read_size..];
+            Ok(read_size)
+        })
+    }
+
+    fn parse_error(input: &str) -> ParseError {
+        Document::parse(input).unwrap_err()
+    }
+
+    #[test]
+    fn read_config_chunks() {
+        let config = "key = 1\n".repeat(100);
+        assert_eq!(read_data(config.as_bytes(), 7), Ok(config));
+        assert_eq!(read_data(b"", 7), Ok(String::new()));
+    }
+
+    #[test]
+    fn read_config_limit() {
+        let data = vec![b'#'; MAX_CONFIG_SIZE];
+        assert_eq!(read_data(&data, usize::MAX).map(|config| config.len()), Ok(MAX_CONFIG_SIZE));
+
+        let data = vec![b'#'; MAX_CONFIG_SIZE + 1];
+        assert_eq!(read_data(&data, usize::MAX), Err(ReadError::TooBig));
+        assert_eq!(read_data(&data, 7), Err(ReadError::TooBig));
+    }
+
+    #[test]
+    fn read_config_invalid_utf8() {
+        assert_eq!(read_data(b"key = \"\xFF\"", 3), Err(ReadError::InvalidUtf8 { line: 1 }));
+        assert_eq!(read_data(b"a = 1\nb = 2\nc = \"\xC3\"\n", 3), Err(ReadError::InvalidUtf8 { line: 3 }));
+        // Characters split across reads are fine
+        assert_eq!(read_data("key = \"é\"".as_bytes(), 1), Ok(String::from("key = \"é\"")));
+    }
+
+    #[test]
+    fn read_config_error() {
+        assert_eq!(read_config(|_| Err(5)), Err(ReadError::Read(5)));
+    }
+
+    #[test]
+    fn tables_in_order() {
+        let doc = Document::parse(
+            r#"
+            root_key = 1
+
+            [program.0x0100000000001000]
+            action = "deny"
+            [ other ]
+            [program.0x010000000000100D]
+            action = "allow"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(doc.tables.len(), 4);
+        assert_eq!(doc.get_root().name, "");
+        assert_eq!(doc.get_root().get("root_key"), Some(&Value::Integer(1)));
+        assert_eq!(doc.get_root().get("action"), None);
+        assert_eq!(doc.get_table("other").map(|table| table.entries.len()), Some(0));
+        assert_eq!(doc.get_table("missing"), None);
+
+        let subtables: Vec<_> = doc
+            .iter_subtables("program")
+            .map(|(name, table)| (name, table.get("action").and_then(Value::as_str)))
+            .collect();
+        assert_eq!(subtables, [("0x0100000000001000", Some("deny")), ("0x010000000000100D", Some("allow"))]);
+        // Only whole name parts count
+        assert_eq!(doc.iter_subtables("prog").count(), 0);
+    }
+
+    #[test]
+    fn values() {
+        let doc = Document::parse(
+            r#"
+            string = "a \"quoted\" \\ string\twith\nescapes"
+            empty_string = ""
+            decimal = 1_000
+            negative = -42
+            positive = +7
+            hex = 0xFFFF_FFFF_FFFF_FFFF
+            min = -9223372036854775808
+            yes = true
+            no = false
+            array = [1, "two", [false], ]
+            empty_array = []
+            "#,
+        )
+        .unwrap();
+        let root = doc.get_root();
+
+        assert_eq!(root.get("string").and_then(Value::as_str), Some("a \"quoted\" \\ string\twith\nescapes"));
+        assert_eq!(root.get("empty_string").and_then(Value::as_str), Some(""));
+        assert_eq!(root.get("decimal").and_then(Value::as_integer), Some(1000));
+        assert_eq!(root.get("negative").and_then(Value::as_integer), Some(-42));
+        assert_eq!(root.get("positive").and_then(Value::as_integer), Some(7));
+        assert_eq!(root.get("hex").and_then(Value::as_u64), Some(u64::MAX));
+        assert_eq!(root.get("min").and_then(Value::as_integer), Some(i64::MIN));
+        assert_eq!(root.get("yes").and_then(Value::as_bool), Some(true));
+        assert_eq!(root.get("no").and_then(Value::as_bool), Some(false));
+        assert_eq!(
+            root.get("array").and_then(Value::as_array),
+            Some(
+                [
+                    Value::Integer(1),
+                    Value::String(String::from("two")),
+                    Value::Array(vec![Value::Boolean(false)])
+                ]
+                .as_slice()
+            )
+        );
+        assert_eq!(root.get("empty_array").and_then(Value::as_array), Some([].as_slice()));
+
+        // Getters of other types don't convert anything
+        assert_eq!(root.get("decimal").and_then(Value::as_str), None);
+        assert_eq!(root.get("string").and_then(Value::as_integer), None);
+        assert_eq!(root.get("yes").and_then(Value::as_integer), None);
+    }
+
+    #[test]
+    fn comments() {
+        let doc = Document::parse(
+            r##"
+            # Whole line comment
+            key = "not # a comment" # but this is
+            [table] # here too
+            escaped = "\"#\"" # and here
+            "##,
+        )
+        .unwrap();
+        assert_eq!(doc.get_root().get("key").and_then(Value::as_str), Some("not # a comment"));
+        assert_eq!(doc.get_table("table").and_then(|table| table.get("escaped")).and_then(Value::as_str), Some("\"#\""));
+    }
+
+    #[test]
+    fn errors() {
+        for (input, kind) in [
+            ("[table", ParseErrorKind::InvalidTableHeader),
+            ("[]", ParseErrorKind::InvalidTableHeader),
+            ("[bad name]", ParseErrorKind::InvalidTableHeader),
+            ("just some words", ParseErrorKind::ExpectedKeyValue),
+            ("= 1", ParseErrorKind::InvalidKey),
+            ("bad key = 1", ParseErrorKind::InvalidKey),
+            ("key =", ParseErrorKind::InvalidValue),
+            ("key = yes", ParseErrorKind::InvalidValue),
+            ("key = 1_", ParseErrorKind::InvalidValue),
+            ("key = -0x1", ParseErrorKind::InvalidValue),
+            ("key = [1 2]", ParseErrorKind::InvalidValue),
+            ("key = [1", ParseErrorKind::InvalidValue),
+            ("key = \"open", ParseErrorKind::UnterminatedString),
+            ("key = \"\\q\"", ParseErrorKind::InvalidEscape),
+            ("key = 9223372036854775808", ParseErrorKind::IntegerOverflow),
+            ("key = -9223372036854775809", ParseErrorKind::IntegerOverflow),
+            ("key = 0x1_0000_0000_0000_0000", ParseErrorKind::IntegerOverflow),
+            ("key = 1 2", ParseErrorKind::TrailingCharacters),
+            ("key = \"a\" b", ParseErrorKind::TrailingCharacters),
+            ("key = 1\nkey = 2", ParseErrorKind::DuplicateKey),
+        ] {
+            assert_eq!(parse_error(input).kind, kind, "{}", input);
+        }
+    }
+
+    #[test]
+    fn error_lines() {
+        assert_eq!(parse_error("a = 1\n\n# comment\nb = ?\n").line, 4);
+        // The same key in another table is fine, the duplicate is reported where it appears again
+        assert_eq!(parse_error("a = 1\n[t]\na = 1\nb = 1\nb = 2").line, 5);
+    }
+}
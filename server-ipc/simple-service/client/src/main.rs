@@ -1,17 +1,23 @@
 #![no_std]
 #![no_main]
 
+#[macro_use]
 extern crate nx;
 
 use core::panic;
 use nx::diag::abort;
-use nx::diag::log::lm::LmLogger;
-use nx::ipc::sf;
+use nx::diag::log::{lm::LmLogger, LogSeverity};
+use nx::ipc::sf::{self, IObject};
+use nx::ipc::CommandProtocol;
 use nx::service;
 use nx::svc;
 use nx::util;
+use nx::wait;
 
-use simple_service_server::{DemoService, IDemoServiceClient};
+use simple_service_server::{
+    DemoDomainService, DemoService, ICounterClient, IDemoDomainServiceClient, IDemoServiceClient,
+    get_job_result_for, get_protocol_id, DEMO_DOMAIN_SERVICE_MAX_SESSIONS, MAX_SESSION_ENTRIES, POINTER_BUFFER_SIZE,
+};
 
 #[no_mangle]
 pub fn initialize_heap(hbl_heap: util::PointerAndSize) -> util::PointerAndSize {
@@ -24,20 +30,235 @@ pub fn initialize_heap(hbl_heap: util::PointerAndSize) -> util::PointerAndSize {
     }
 }
 
-#[no_mangle]
-pub fn main() {
+fn check(name: &str, ok: bool) -> bool {
+    if ok {
+        diag_log!(LmLogger { LogSeverity::Info, true } => "{}: OK", name);
+    } else {
+        diag_log!(LmLogger { LogSeverity::Error, true } => "{}: FAILED", name);
+    }
+    ok
+}
+
+fn skip(name: &str) -> bool {
+    diag_log!(LmLogger { LogSeverity::Info, true } => "{}: skipped, not supported over TIPC", name);
+    true
+}
+
+fn get_protocol_name(protocol: CommandProtocol) -> &'static str {
+    match protocol {
+        CommandProtocol::Cmif => "CMIF",
+        CommandProtocol::Tipc => "TIPC",
+    }
+}
+
+fn open_demo_service(protocol: CommandProtocol) -> DemoService {
     let mut demo_service_client = service::new_service_object::<DemoService>().unwrap();
 
-    let demo = "demo";
-    let mut omed = [0u8; 0x100];
+    // The session itself is the same, only the way our requests are laid out changes
+    let mut info = demo_service_client.get_info();
+    info.protocol = protocol;
+    demo_service_client.set_info(info);
+    demo_service_client
+}
+
+fn sum_bytes(buf: &[u8]) -> u64 {
+    buf.iter().map(|&byte| byte as u64).sum()
+}
+
+fn check_job(demo_service_client: &mut DemoService) -> bool {
+    let mut all_ok = true;
+    let event = demo_service_client.start_job(100, 200).unwrap();
+
+    // The job takes a while, so it can't be done yet
+    let (done, _) = demo_service_client.get_job_result().unwrap();
+    all_ok &= check("start_job", !done && wait::wait_handles(&[event.handle], 0).is_err());
+
+    // Other requests are still handled while the job runs
+    all_ok &= check("request while job runs", demo_service_client.get_sum_and_product(1, 2).unwrap() == (3, 2));
+
+    // Once the event is signaled the result is there
+    let signaled = wait::wait_handles(&[event.handle], 1_000_000_000).is_ok();
+    let (done, result) = demo_service_client.get_job_result().unwrap();
+    all_ok &= check("get_job_result", signaled && done && (result == get_job_result_for(100)));
+    let _ = svc::close_handle(event.handle);
+
+    all_ok
+}
+
+fn check_session_state(demo_service_client: &mut DemoService, protocol: CommandProtocol) -> bool {
+    let mut all_ok = true;
+    let process_id = svc::get_process_id(svc::CURRENT_PROCESS_PSEUDO_HANDLE).unwrap();
+    // Other clients might be connected too
+    let session_count = demo_service_client.get_session_count().unwrap();
+
+    let mut sessions: [DemoService; 3] = core::array::from_fn(|_| open_demo_service(protocol));
+    all_ok &= check("session count", demo_service_client.get_session_count().unwrap() == session_count + 3);
+
+    // Every session gets a different state, the first one doesn't even register
+    for (i, session) in sessions.iter_mut().enumerate() {
+        if i != 0 {
+            session.register_client(sf::ProcessId::new()).unwrap();
+        }
+        for _ in 0..=i {
+            session.increment_session_counter().unwrap();
+        }
+        session.set_entry(i as u32, i as u64 * 0x100).unwrap();
+    }
+    let isolated = sessions.iter_mut().enumerate().all(|(i, session)| {
+        let expected_process_id = if i == 0 { 0 } else { process_id };
+        (session.get_client_process_id().unwrap() == expected_process_id)
+            && (session.increment_session_counter().unwrap() == i as u64 + 2)
+            && (0..3).all(|key| {
+                let expected_entry = if key == i as u32 { (true, i as u64 * 0x100) } else { (false, 0) };
+                session.get_entry(key).unwrap() == expected_entry
+            })
+    });
+    all_ok &= check("session isolation", isolated);
+
+    // The first session already has key 0, so it only has room for the rest of the keys
+    let stored = (0..=MAX_SESSION_ENTRIES as u32).map(|key| sessions[0].set_entry(key, 1).unwrap());
+    all_ok &= check("session entry limit", stored.eq((0..=MAX_SESSION_ENTRIES).map(|i| i < MAX_SESSION_ENTRIES)));
+
+    let first_value = sessions[0].increment_shared_counter().unwrap();
+    let second_value = sessions[1].increment_shared_counter().unwrap();
+    all_ok &= check("shared state", second_value == first_value + 1);
+
+    // The sessions (and their state in the server) go away here
+    all_ok
+}
+
+fn check_domain_service() -> bool {
+    let mut all_ok = true;
+    let mut domain_service_client = service::new_service_object::<DemoDomainService>().unwrap();
+
+    // All of these live in the one domain session, each one under its own object ID
+    let mut counters = [0, 1000, 2000].map(|initial_value| Some(domain_service_client.open_counter(initial_value).unwrap()));
+    for (i, counter) in counters.iter_mut().flatten().enumerate() {
+        counter.increment(i as u64 + 1).unwrap();
+    }
+    all_ok &= check("domain object routing", counters.iter_mut().flatten().enumerate().all(|(i, counter)| counter.get_value().unwrap() == (i as u64 * 1000) + (i as u64 + 1)));
+    all_ok &= check("domain open objects", domain_service_client.get_open_counter_count().unwrap() == 3);
+
+    // Dropping an object just closes its ID in the domain, the others (and the session) stay usable
+    counters[1] = None;
+    all_ok &= check("domain object close", domain_service_client.get_open_counter_count().unwrap() == 2);
+    let values = counters.iter_mut().flatten().map(|counter| counter.increment(1).unwrap());
+    all_ok &= check("domain routing after close", values.eq([2, 2004]));
 
-    demo_service_client.sample_command(
-        0x7,
-        0x82,
-        sf::InAutoSelectBuffer::from_array(demo.as_bytes()),
-        sf::InOutAutoSelectBuffer::from_mut_array(&mut omed),
-    ).unwrap();
+    // However many objects the domain above has it's just one session, so there's room for exactly this many more
+    let extra_sessions: [_; DEMO_DOMAIN_SERVICE_MAX_SESSIONS as usize - 1] = core::array::from_fn(|_| service::new_service_object::<DemoDomainService>());
+    let over_limit_session = service::new_service_object::<DemoDomainService>();
+    all_ok &= check("domain service session limit", extra_sessions.iter().all(|session| session.is_ok()) && over_limit_session.is_err());
 
+    all_ok
+}
+
+fn check_demo_service(protocol: CommandProtocol) -> bool {
+    diag_log!(LmLogger { LogSeverity::Info, true } => "Checking the demo service over {}", get_protocol_name(protocol));
+    let mut demo_service_client = open_demo_service(protocol);
+    let mut all_ok = true;
+    // TIPC has no pointer buffers (so no auto-select ones either) and no sub-interface objects
+    let is_tipc = matches!(protocol, CommandProtocol::Tipc);
+
+    all_ok &= check("get_session_protocol", demo_service_client.get_session_protocol().unwrap() == get_protocol_id(protocol));
+
+    if is_tipc {
+        all_ok &= skip("sample_command");
+    } else {
+        let demo = "demo";
+        let mut omed = [0u8; 0x100];
+
+        demo_service_client.sample_command(
+            0x7,
+            0x82,
+            sf::InAutoSelectBuffer::from_array(demo.as_bytes()),
+            sf::InOutAutoSelectBuffer::from_mut_array(&mut omed),
+        ).unwrap();
+        all_ok &= check("sample_command", omed.starts_with(b"omed"));
+    }
+
+    let (sum, product) = demo_service_client.get_sum_and_product(0x7, 0x82).unwrap();
+    all_ok &= check("get_sum_and_product", (sum == 0x89) && (product == 0x38E));
+
+    // The same data goes through both buffer kinds: map-alias buffers get the memory mapped into the server, pointer buffers get it copied
+    let mut in_data = [0u8; 0x200];
+    for (i, byte) in in_data.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let expected_sum = sum_bytes(&in_data);
+    let sum = demo_service_client.sum_map_alias_buffer(sf::InMapAliasBuffer::from_array(&in_data)).unwrap();
+    all_ok &= check("sum_map_alias_buffer", sum == expected_sum);
+    if is_tipc {
+        all_ok &= skip("sum_pointer_buffer");
+    } else {
+        let sum = demo_service_client.sum_pointer_buffer(sf::InPointerBuffer::from_array(&in_data)).unwrap();
+        all_ok &= check("sum_pointer_buffer", sum == expected_sum);
+    }
+
+    let mut out_data = [0u8; 0x100];
+    if is_tipc {
+        all_ok &= skip("fill_auto_select_buffer");
+    } else {
+        let size = demo_service_client.fill_auto_select_buffer(0xAA, sf::OutAutoSelectBuffer::from_mut_array(&mut out_data)).unwrap();
+        all_ok &= check("fill_auto_select_buffer", (size == out_data.len() as u64) && out_data.iter().all(|&byte| byte == 0xAA));
+    }
+    let size = demo_service_client.fill_map_alias_buffer(0xBB, sf::OutMapAliasBuffer::from_mut_array(&mut out_data)).unwrap();
+    all_ok &= check("fill_map_alias_buffer", (size == out_data.len() as u64) && out_data.iter().all(|&byte| byte == 0xBB));
+    if is_tipc {
+        all_ok &= skip("fill_pointer_buffer");
+    } else {
+        // Pointer buffers have to fit in the server's pointer buffer
+        let mut out_data = [0u8; POINTER_BUFFER_SIZE];
+        let size = demo_service_client.fill_pointer_buffer(0xCC, sf::OutPointerBuffer::from_mut_array(&mut out_data)).unwrap();
+        all_ok &= check("fill_pointer_buffer", (size == out_data.len() as u64) && out_data.iter().all(|&byte| byte == 0xCC));
+    }
+
+    // We keep our handles after copying one to the server, so the event can be checked before and after signaling it
+    let (writable_handle, readable_handle) = svc::create_event().unwrap();
+    let signaled_before = demo_service_client.is_event_signaled(sf::CopyHandle { handle: readable_handle }).unwrap();
+    svc::signal_event(writable_handle).unwrap();
+    let signaled_after = demo_service_client.is_event_signaled(sf::CopyHandle { handle: readable_handle }).unwrap();
+    all_ok &= check("is_event_signaled", !signaled_before && signaled_after);
+    let _ = svc::close_handle(writable_handle);
+    let _ = svc::close_handle(readable_handle);
+
+    let event = demo_service_client.create_signaled_event().unwrap();
+    all_ok &= check("create_signaled_event", wait::wait_handles(&[event.handle], 0).is_ok());
+    let _ = svc::close_handle(event.handle);
+
+    all_ok &= check_job(&mut demo_service_client);
+
+    let process_id = demo_service_client.get_process_id(sf::ProcessId::new()).unwrap();
+    all_ok &= check("get_process_id", process_id == svc::get_process_id(svc::CURRENT_PROCESS_PSEUDO_HANDLE).unwrap());
+
+    if is_tipc {
+        all_ok &= skip("open_counter");
+    } else {
+        // Every counter object is a separate session with its own state
+        let mut counter_a = demo_service_client.open_counter(10).unwrap();
+        let mut counter_b = demo_service_client.open_counter(100).unwrap();
+        counter_a.increment(5).unwrap();
+        counter_b.increment(1).unwrap();
+        all_ok &= check("open_counter", (counter_a.get_value().unwrap() == 15) && (counter_b.get_value().unwrap() == 101));
+    }
+
+    all_ok &= check_session_state(&mut demo_service_client, protocol);
+    all_ok
+}
+
+#[no_mangle]
+pub fn main() {
+    let mut all_ok = true;
+    all_ok &= check_demo_service(CommandProtocol::Cmif);
+    all_ok &= check_demo_service(CommandProtocol::Tipc);
+    // Domains only exist in CMIF
+    all_ok &= check_domain_service();
+
+    if all_ok {
+        diag_log!(LmLogger { LogSeverity::Info, true } => "All demo service commands work");
+    } else {
+        diag_log!(LmLogger { LogSeverity::Error, true } => "Some demo service commands failed");
+    }
 }
 
 #[panic_handler]
@@ -0,0 +1,62 @@
+//! Host tool checking the golden encodings of the `simple-service` demo interface, failing if any of them changed
+//!
+//! With `--dump` it prints the current encodings instead, laid out like the `expected` arrays in `golden.rs`.
+
+use std::process::ExitCode;
+
+use simple_service_codec::golden::{GoldenVector, GOLDEN_VECTORS};
+use simple_service_codec::hipc;
+
+fn usage() -> ExitCode {
+    eprintln!("Usage: simple-service-codec-check [--dump]");
+    ExitCode::FAILURE
+}
+
+fn dump(vector: &GoldenVector) {
+    let mut buf = [0u8; hipc::MESSAGE_SIZE];
+    match vector.encode(&mut buf) {
+        Ok(size) => {
+            println!("// {}", vector.name);
+            println!("expected: &[");
+            for chunk in buf[..size].chunks(0x10) {
+                let bytes = chunk.iter().map(|byte| format!("0x{:02X}", byte)).collect::<Vec<_>>();
+                println!("    {},", bytes.join(", "));
+            }
+            println!("],");
+        }
+        Err(e) => eprintln!("{}: {}", vector.name, e),
+    }
+}
+
+fn main() -> ExitCode {
+    let mut dump_only = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--dump" => dump_only = true,
+            _ => return usage(),
+        }
+    }
+
+    if dump_only {
+        GOLDEN_VECTORS.iter().for_each(dump);
+        return ExitCode::SUCCESS;
+    }
+
+    let mut failed_count = 0;
+    for vector in GOLDEN_VECTORS {
+        match vector.check() {
+            Ok(()) => println!("ok      {}", vector.name),
+            Err(e) => {
+                println!("FAILED  {}: {}", vector.name, e);
+                failed_count += 1;
+            }
+        }
+    }
+
+    println!("{} of {} golden vectors failed", failed_count, GOLDEN_VECTORS.len());
+    if failed_count > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
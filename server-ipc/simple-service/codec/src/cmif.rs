@@ -0,0 +1,71 @@
+//! CMIF layout, the start of the (aligned) data words of requests and responses
+//!
+//! Requests start with an `SFCI` header holding the command ID, responses with an `SFCO` header holding the result, and the
+//! raw data of the command comes right after them. Domain messages, which have an extra header before these, aren't covered.
+
+use crate::DecodeError;
+
+pub const IN_HEADER_MAGIC: u32 = u32::from_le_bytes(*b"SFCI");
+pub const OUT_HEADER_MAGIC: u32 = u32::from_le_bytes(*b"SFCO");
+
+pub const HEADER_SIZE: usize = 0x10;
+
+// HIPC command types, responses leave it as 0
+pub const COMMAND_TYPE_RESPONSE: u16 = 0;
+pub const COMMAND_TYPE_CLOSE: u16 = 2;
+pub const COMMAND_TYPE_REQUEST: u16 = 4;
+pub const COMMAND_TYPE_CONTROL: u16 = 5;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub magic: u32,
+    pub version: u32,
+    /// Command ID in requests, result in responses
+    pub value: u32,
+    pub token: u32,
+}
+
+impl Header {
+    pub const fn new_in(command_id: u32) -> Self {
+        Self {
+            magic: IN_HEADER_MAGIC,
+            version: 0,
+            value: command_id,
+            token: 0,
+        }
+    }
+
+    pub const fn new_out(result: u32) -> Self {
+        Self {
+            magic: OUT_HEADER_MAGIC,
+            version: 0,
+            value: result,
+            token: 0,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut buf = [0; HEADER_SIZE];
+        for (chunk, word) in buf.chunks_exact_mut(4).zip([self.magic, self.version, self.value, self.token]) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        buf
+    }
+
+    /// Parses the header at the start of the data words, checking its magic
+    pub fn parse(data: &[u8], expected_magic: u32) -> Result<Self, DecodeError> {
+        let bytes = data.get(..HEADER_SIZE).ok_or(DecodeError::UnexpectedEnd)?;
+        let word = |i: usize| u32::from_le_bytes([bytes[4 * i], bytes[4 * i + 1], bytes[4 * i + 2], bytes[4 * i + 3]]);
+
+        let header = Self {
+            magic: word(0),
+            version: word(1),
+            value: word(2),
+            token: word(3),
+        };
+        if header.magic != expected_magic {
+            return Err(DecodeError::InvalidMagic(header.magic));
+        }
+        Ok(header)
+    }
+}
@@ -0,0 +1,710 @@
+//! Layout of every command of the demo interfaces, and encoding/decoding of their requests and responses
+//!
+//! Raw arguments are laid out in order after the CMIF header, each one aligned to its own size. Buffers are turned into
+//! descriptors like this:
+//!
+//! - Map-alias buffers: send (A) descriptors for input ones, receive (B) ones for output ones
+//! - Pointer buffers: send statics (X) for input ones, receive list (C) entries for output ones, whose sizes also go in a
+//!   table of `u16`s after the raw data
+//! - Auto-select buffers: both kinds, with the pointer one used if the buffer fits in the server's pointer buffer and the
+//!   other one left empty, input-output ones are always map-aliased as exchange (W) buffers
+//!
+//! Sub-interface objects are returned as move handles, since these aren't domain sessions. Failed responses have no raw
+//! data or handles. Output pointer buffers are copied by the kernel into the receive list, which responses don't describe.
+
+use crate::cmif;
+use crate::hipc::{self, BufferDescriptor, Message, MessageView, RecvListEntry, StaticDescriptor};
+use crate::{DecodeError, EncodeError};
+
+pub const MAX_RAW_ARGS: usize = 4;
+pub const MAX_BUFFER_ARGS: usize = 4;
+pub const MAX_HANDLE_ARGS: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RawKind {
+    U8,
+    Bool,
+    U32,
+    U64,
+    /// Asks the kernel to send the process ID, the raw data only has a placeholder for it
+    ProcessId,
+}
+
+impl RawKind {
+    pub const fn get_size(self) -> usize {
+        match self {
+            Self::U8 | Self::Bool => 1,
+            Self::U32 => 4,
+            Self::U64 | Self::ProcessId => 8,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BufferKind {
+    InAutoSelect,
+    InOutAutoSelect,
+    OutAutoSelect,
+    InMapAlias,
+    OutMapAlias,
+    InPointer,
+    OutPointer,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HandleKind {
+    Copy,
+    Move,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CommandLayout {
+    pub name: &'static str,
+    pub id: u32,
+    pub in_raw: &'static [RawKind],
+    pub buffers: &'static [BufferKind],
+    pub in_handles: &'static [HandleKind],
+    pub out_raw: &'static [RawKind],
+    pub out_handles: &'static [HandleKind],
+}
+
+impl CommandLayout {
+    const fn new(name: &'static str, id: u32) -> Self {
+        Self {
+            name,
+            id,
+            in_raw: &[],
+            buffers: &[],
+            in_handles: &[],
+            out_raw: &[],
+            out_handles: &[],
+        }
+    }
+}
+
+// These follow the interfaces in simple-service-server, which the tests below check them against
+
+pub const DEMO_SERVICE_COMMANDS: &[CommandLayout] = &[
+    CommandLayout {
+        in_raw: &[RawKind::U32, RawKind::U64],
+        buffers: &[BufferKind::InAutoSelect, BufferKind::InOutAutoSelect],
+        ..CommandLayout::new("sample_command", 999)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::U32, RawKind::U64],
+        out_raw: &[RawKind::U64, RawKind::U64],
+        ..CommandLayout::new("get_sum_and_product", 1000)
+    },
+    CommandLayout {
+        buffers: &[BufferKind::InMapAlias],
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("sum_map_alias_buffer", 1001)
+    },
+    CommandLayout {
+        buffers: &[BufferKind::InPointer],
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("sum_pointer_buffer", 1002)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::U8],
+        buffers: &[BufferKind::OutAutoSelect],
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("fill_auto_select_buffer", 1003)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::U8],
+        buffers: &[BufferKind::OutMapAlias],
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("fill_map_alias_buffer", 1004)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::U8],
+        buffers: &[BufferKind::OutPointer],
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("fill_pointer_buffer", 1005)
+    },
+    CommandLayout {
+        in_handles: &[HandleKind::Copy],
+        out_raw: &[RawKind::Bool],
+        ..CommandLayout::new("is_event_signaled", 1010)
+    },
+    CommandLayout {
+        out_handles: &[HandleKind::Move],
+        ..CommandLayout::new("create_signaled_event", 1011)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::ProcessId],
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("get_process_id", 1020)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::U64],
+        out_handles: &[HandleKind::Move],
+        ..CommandLayout::new("open_counter", 1030)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::ProcessId],
+        ..CommandLayout::new("register_client", 1040)
+    },
+    CommandLayout {
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("get_client_process_id", 1041)
+    },
+    CommandLayout {
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("increment_session_counter", 1042)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::U32, RawKind::U64],
+        out_raw: &[RawKind::Bool],
+        ..CommandLayout::new("set_entry", 1043)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::U32],
+        out_raw: &[RawKind::Bool, RawKind::U64],
+        ..CommandLayout::new("get_entry", 1044)
+    },
+    CommandLayout {
+        out_raw: &[RawKind::U32],
+        ..CommandLayout::new("get_session_count", 1050)
+    },
+    CommandLayout {
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("increment_shared_counter", 1051)
+    },
+    CommandLayout {
+        out_raw: &[RawKind::U8],
+        ..CommandLayout::new("get_session_protocol", 1060)
+    },
+    CommandLayout {
+        in_raw: &[RawKind::U32, RawKind::U32],
+        out_handles: &[HandleKind::Move],
+        ..CommandLayout::new("start_job", 1070)
+    },
+    CommandLayout {
+        out_raw: &[RawKind::Bool, RawKind::U64],
+        ..CommandLayout::new("get_job_result", 1071)
+    },
+];
+
+pub const COUNTER_COMMANDS: &[CommandLayout] = &[
+    CommandLayout {
+        in_raw: &[RawKind::U64],
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("increment", 0)
+    },
+    CommandLayout {
+        out_raw: &[RawKind::U64],
+        ..CommandLayout::new("get_value", 1)
+    },
+];
+
+pub fn find_command(commands: &'static [CommandLayout], name: &str) -> Option<&'static CommandLayout> {
+    commands.iter().find(|command| command.name == name)
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Buffer {
+    pub address: u64,
+    pub size: u64,
+}
+
+impl Buffer {
+    pub const fn new(address: u64, size: u64) -> Self {
+        Self { address, size }
+    }
+}
+
+// Where every raw argument goes, relative to the end of the CMIF header
+fn raw_offsets(kinds: &[RawKind]) -> impl Iterator<Item = (RawKind, usize)> + '_ {
+    kinds.iter().scan(0usize, |offset, &kind| {
+        let arg_offset = offset.next_multiple_of(kind.get_size());
+        *offset = arg_offset + kind.get_size();
+        Some((kind, arg_offset))
+    })
+}
+
+fn get_raw_size(kinds: &[RawKind]) -> usize {
+    raw_offsets(kinds).last().map_or(0, |(kind, offset)| offset + kind.get_size())
+}
+
+fn write_raw(kinds: &[RawKind], values: &[u64], out: &mut [u8]) {
+    for ((kind, offset), &value) in raw_offsets(kinds).zip(values) {
+        let value = match kind {
+            RawKind::ProcessId => 0,
+            RawKind::Bool => (value != 0) as u64,
+            _ => value,
+        };
+        out[offset..offset + kind.get_size()].copy_from_slice(&value.to_le_bytes()[..kind.get_size()]);
+    }
+}
+
+fn read_raw(kinds: &[RawKind], data: &[u8], values: &mut [u64]) -> Result<(), DecodeError> {
+    for ((kind, offset), value) in raw_offsets(kinds).zip(values) {
+        let bytes = data.get(offset..offset + kind.get_size()).ok_or(DecodeError::UnexpectedEnd)?;
+        let mut value_bytes = [0; 8];
+        value_bytes[..bytes.len()].copy_from_slice(bytes);
+        *value = u64::from_le_bytes(value_bytes);
+    }
+    Ok(())
+}
+
+// Fixed-size list, since there's no allocator here
+struct List<T, const N: usize> {
+    items: [T; N],
+    len: usize,
+}
+
+impl<T: Copy + Default, const N: usize> List<T, N> {
+    fn new() -> Self {
+        Self {
+            items: [T::default(); N],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, item: T) -> Result<(), EncodeError> {
+        *self.items.get_mut(self.len).ok_or(EncodeError::TooManyItems)? = item;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn as_slice(&self) -> &[T] {
+        &self.items[..self.len]
+    }
+}
+
+const MAX_DESCRIPTORS: usize = MAX_BUFFER_ARGS;
+
+/// Arguments of a request, in the order of the command layout
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RequestArgs<'a> {
+    /// Process ID arguments are sent in the special header, with the value given here
+    pub raw: &'a [u64],
+    pub buffers: &'a [Buffer],
+    pub handles: &'a [u32],
+}
+
+/// Encodes a request into `buf`, returning its size
+///
+/// `pointer_buffer_size` is the size of the server's pointer buffer, which decides how auto-select buffers are sent.
+pub fn encode_request(layout: &CommandLayout, args: &RequestArgs, pointer_buffer_size: usize, buf: &mut [u8]) -> Result<usize, EncodeError> {
+    if (args.raw.len() != layout.in_raw.len()) || (args.buffers.len() != layout.buffers.len()) || (args.handles.len() != layout.in_handles.len()) {
+        return Err(EncodeError::ArgumentMismatch);
+    }
+
+    let mut send_statics = List::<StaticDescriptor, MAX_DESCRIPTORS>::new();
+    let mut send_buffers = List::<BufferDescriptor, MAX_DESCRIPTORS>::new();
+    let mut recv_buffers = List::<BufferDescriptor, MAX_DESCRIPTORS>::new();
+    let mut exch_buffers = List::<BufferDescriptor, MAX_DESCRIPTORS>::new();
+    let mut recv_list = List::<RecvListEntry, MAX_DESCRIPTORS>::new();
+    let mut out_pointer_sizes = List::<u16, MAX_DESCRIPTORS>::new();
+
+    let empty_buffer = BufferDescriptor::default();
+    for (&kind, buffer) in layout.buffers.iter().zip(args.buffers) {
+        let fits_pointer_buffer = buffer.size <= pointer_buffer_size as u64;
+        let as_static = StaticDescriptor {
+            index: send_statics.len as u8,
+            address: buffer.address,
+            size: buffer.size as u16,
+        };
+        let as_buffer = BufferDescriptor {
+            address: buffer.address,
+            size: buffer.size,
+            mode: 0,
+        };
+        let as_recv_entry = RecvListEntry {
+            address: buffer.address,
+            size: buffer.size as u16,
+        };
+        match kind {
+            BufferKind::InAutoSelect if fits_pointer_buffer => {
+                send_statics.push(as_static)?;
+                send_buffers.push(empty_buffer)?;
+            }
+            BufferKind::InAutoSelect => {
+                send_statics.push(StaticDescriptor { address: 0, size: 0, ..as_static })?;
+                send_buffers.push(as_buffer)?;
+            }
+            BufferKind::OutAutoSelect if fits_pointer_buffer => {
+                recv_list.push(as_recv_entry)?;
+                out_pointer_sizes.push(buffer.size as u16)?;
+                recv_buffers.push(empty_buffer)?;
+            }
+            BufferKind::OutAutoSelect => {
+                recv_list.push(RecvListEntry::default())?;
+                out_pointer_sizes.push(0)?;
+                recv_buffers.push(as_buffer)?;
+            }
+            BufferKind::InOutAutoSelect => exch_buffers.push(as_buffer)?,
+            BufferKind::InMapAlias => send_buffers.push(as_buffer)?,
+            BufferKind::OutMapAlias => recv_buffers.push(as_buffer)?,
+            BufferKind::InPointer => send_statics.push(as_static)?,
+            BufferKind::OutPointer => {
+                recv_list.push(as_recv_entry)?;
+                out_pointer_sizes.push(buffer.size as u16)?;
+            }
+        }
+    }
+
+    let mut copy_handles = List::<u32, MAX_HANDLE_ARGS>::new();
+    let mut move_handles = List::<u32, MAX_HANDLE_ARGS>::new();
+    for (&kind, &handle) in layout.in_handles.iter().zip(args.handles) {
+        match kind {
+            HandleKind::Copy => copy_handles.push(handle)?,
+            HandleKind::Move => move_handles.push(handle)?,
+        }
+    }
+
+    let process_id = raw_offsets(layout.in_raw)
+        .zip(args.raw)
+        .find(|((kind, _), _)| *kind == RawKind::ProcessId)
+        .map(|(_, &value)| value);
+
+    // CMIF header, raw data and then the output pointer sizes, aligned to 2 bytes
+    let mut data = [0u8; hipc::MESSAGE_SIZE];
+    data[..cmif::HEADER_SIZE].copy_from_slice(&cmif::Header::new_in(layout.id).encode());
+    let raw_end = cmif::HEADER_SIZE + get_raw_size(layout.in_raw);
+    write_raw(layout.in_raw, args.raw, &mut data[cmif::HEADER_SIZE..raw_end]);
+    let mut data_len = raw_end.next_multiple_of(2);
+    for size in out_pointer_sizes.as_slice() {
+        data[data_len..data_len + 2].copy_from_slice(&size.to_le_bytes());
+        data_len += 2;
+    }
+
+    Message {
+        command_type: cmif::COMMAND_TYPE_REQUEST,
+        process_id,
+        copy_handles: copy_handles.as_slice(),
+        move_handles: move_handles.as_slice(),
+        send_statics: send_statics.as_slice(),
+        send_buffers: send_buffers.as_slice(),
+        recv_buffers: recv_buffers.as_slice(),
+        exch_buffers: exch_buffers.as_slice(),
+        data: &data[..data_len],
+        recv_list: recv_list.as_slice(),
+    }
+    .encode(buf)
+}
+
+/// Request as seen by the server, only the first entries of every array (as many as the layout has) are meaningful
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodedRequest {
+    pub raw: [u64; MAX_RAW_ARGS],
+    pub buffers: [Buffer; MAX_BUFFER_ARGS],
+    pub handles: [u32; MAX_HANDLE_ARGS],
+}
+
+fn next<T>(iter: &mut impl Iterator<Item = T>) -> Result<T, DecodeError> {
+    iter.next().ok_or(DecodeError::LayoutMismatch)
+}
+
+// Auto-select buffers come as two descriptors, one of them empty
+fn pick_buffer(pointer: Buffer, map_alias: Buffer) -> Buffer {
+    if pointer.size != 0 {
+        pointer
+    } else {
+        map_alias
+    }
+}
+
+/// Decodes a request for the given command, checking that it matches its layout
+pub fn decode_request(layout: &CommandLayout, buf: &[u8]) -> Result<DecodedRequest, DecodeError> {
+    let view = MessageView::parse(buf)?;
+    let header = cmif::Header::parse(view.get_data(), cmif::IN_HEADER_MAGIC)?;
+    if (view.header.command_type != cmif::COMMAND_TYPE_REQUEST) || (header.value != layout.id) {
+        return Err(DecodeError::LayoutMismatch);
+    }
+
+    let mut request = DecodedRequest::default();
+    read_raw(layout.in_raw, &view.get_data()[cmif::HEADER_SIZE..], &mut request.raw)?;
+    for ((kind, _), value) in raw_offsets(layout.in_raw).zip(request.raw.iter_mut()) {
+        if kind == RawKind::ProcessId {
+            *value = view.process_id.ok_or(DecodeError::LayoutMismatch)?;
+        }
+    }
+
+    let mut send_statics = view.send_statics().map(|desc| Buffer::new(desc.address, desc.size as u64));
+    let mut send_buffers = view.send_buffers().map(|desc| Buffer::new(desc.address, desc.size));
+    let mut recv_buffers = view.recv_buffers().map(|desc| Buffer::new(desc.address, desc.size));
+    let mut exch_buffers = view.exch_buffers().map(|desc| Buffer::new(desc.address, desc.size));
+    let mut recv_list = view.recv_list().map(|entry| Buffer::new(entry.address, entry.size as u64));
+    for (&kind, buffer) in layout.buffers.iter().zip(request.buffers.iter_mut()) {
+        *buffer = match kind {
+            BufferKind::InAutoSelect => pick_buffer(next(&mut send_statics)?, next(&mut send_buffers)?),
+            BufferKind::OutAutoSelect => pick_buffer(next(&mut recv_list)?, next(&mut recv_buffers)?),
+            BufferKind::InOutAutoSelect => next(&mut exch_buffers)?,
+            BufferKind::InMapAlias => next(&mut send_buffers)?,
+            BufferKind::OutMapAlias => next(&mut recv_buffers)?,
+            BufferKind::InPointer => next(&mut send_statics)?,
+            BufferKind::OutPointer => next(&mut recv_list)?,
+        };
+    }
+    if send_statics.next().is_some() || send_buffers.next().is_some() || recv_buffers.next().is_some() || exch_buffers.next().is_some() || recv_list.next().is_some() {
+        return Err(DecodeError::LayoutMismatch);
+    }
+
+    let mut copy_handles = view.copy_handles();
+    let mut move_handles = view.move_handles();
+    for (&kind, handle) in layout.in_handles.iter().zip(request.handles.iter_mut()) {
+        *handle = match kind {
+            HandleKind::Copy => next(&mut copy_handles)?,
+            HandleKind::Move => next(&mut move_handles)?,
+        };
+    }
+    if copy_handles.next().is_some() || move_handles.next().is_some() {
+        return Err(DecodeError::LayoutMismatch);
+    }
+
+    Ok(request)
+}
+
+/// Encodes the response to a command into `buf`, returning its size
+///
+/// Raw values and handles are only sent if `result` is 0 (success), and are ignored otherwise.
+pub fn encode_response(layout: &CommandLayout, result: u32, raw: &[u64], handles: &[u32], buf: &mut [u8]) -> Result<usize, EncodeError> {
+    let succeeded = result == 0;
+    if succeeded && ((raw.len() != layout.out_raw.len()) || (handles.len() != layout.out_handles.len())) {
+        return Err(EncodeError::ArgumentMismatch);
+    }
+
+    let mut copy_handles = List::<u32, MAX_HANDLE_ARGS>::new();
+    let mut move_handles = List::<u32, MAX_HANDLE_ARGS>::new();
+    let mut data = [0u8; hipc::MESSAGE_SIZE];
+    data[..cmif::HEADER_SIZE].copy_from_slice(&cmif::Header::new_out(result).encode());
+    let mut data_len = cmif::HEADER_SIZE;
+    if succeeded {
+        for (&kind, &handle) in layout.out_handles.iter().zip(handles) {
+            match kind {
+                HandleKind::Copy => copy_handles.push(handle)?,
+                HandleKind::Move => move_handles.push(handle)?,
+            }
+        }
+
+        data_len += get_raw_size(layout.out_raw);
+        write_raw(layout.out_raw, raw, &mut data[cmif::HEADER_SIZE..data_len]);
+    }
+
+    Message {
+        command_type: cmif::COMMAND_TYPE_RESPONSE,
+        copy_handles: copy_handles.as_slice(),
+        move_handles: move_handles.as_slice(),
+        data: &data[..data_len],
+        ..Default::default()
+    }
+    .encode(buf)
+}
+
+/// Response as seen by the client, only the first entries of every array (as many as the layout has) are meaningful
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodedResponse {
+    pub result: u32,
+    pub raw: [u64; MAX_RAW_ARGS],
+    pub handles: [u32; MAX_HANDLE_ARGS],
+}
+
+/// Decodes the response to the given command, checking that it matches its layout
+pub fn decode_response(layout: &CommandLayout, buf: &[u8]) -> Result<DecodedResponse, DecodeError> {
+    let view = MessageView::parse(buf)?;
+    let header = cmif::Header::parse(view.get_data(), cmif::OUT_HEADER_MAGIC)?;
+    if view.header.command_type != cmif::COMMAND_TYPE_RESPONSE {
+        return Err(DecodeError::LayoutMismatch);
+    }
+
+    let mut response = DecodedResponse {
+        result: header.value,
+        ..Default::default()
+    };
+    // There's nothing else in failed responses
+    if response.result != 0 {
+        return Ok(response);
+    }
+
+    read_raw(layout.out_raw, &view.get_data()[cmif::HEADER_SIZE..], &mut response.raw)?;
+    let mut copy_handles = view.copy_handles();
+    let mut move_handles = view.move_handles();
+    for (&kind, handle) in layout.out_handles.iter().zip(response.handles.iter_mut()) {
+        *handle = match kind {
+            HandleKind::Copy => next(&mut copy_handles)?,
+            HandleKind::Move => next(&mut move_handles)?,
+        };
+    }
+    if copy_handles.next().is_some() || move_handles.next().is_some() {
+        return Err(DecodeError::LayoutMismatch);
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    // The server crate can only be built for the console, so its interfaces are read from the source instead
+    const SERVER_INTERFACE_SRC: &str = include_str!("../../server/src/lib.rs");
+
+    #[derive(Debug, Default, PartialEq, Eq)]
+    struct ParsedCommand<'a> {
+        name: &'a str,
+        id: u32,
+        in_raw: Vec<RawKind>,
+        buffers: Vec<BufferKind>,
+        in_handles: Vec<HandleKind>,
+        out_raw: Vec<RawKind>,
+        out_handles: Vec<HandleKind>,
+    }
+
+    impl ParsedCommand<'static> {
+        fn from_layout(command: &CommandLayout) -> Self {
+            Self {
+                name: command.name,
+                id: command.id,
+                in_raw: command.in_raw.to_vec(),
+                buffers: command.buffers.to_vec(),
+                in_handles: command.in_handles.to_vec(),
+                out_raw: command.out_raw.to_vec(),
+                out_handles: command.out_handles.to_vec(),
+            }
+        }
+    }
+
+    enum ArgKind {
+        Raw(RawKind),
+        Buffer(BufferKind),
+        Handle(HandleKind),
+    }
+
+    fn get_arg_kind(arg_type: &str) -> ArgKind {
+        let base_type = arg_type.split('<').next().unwrap().trim();
+        match base_type {
+            "u8" => ArgKind::Raw(RawKind::U8),
+            "bool" => ArgKind::Raw(RawKind::Bool),
+            "u32" => ArgKind::Raw(RawKind::U32),
+            "u64" => ArgKind::Raw(RawKind::U64),
+            "sf::ProcessId" => ArgKind::Raw(RawKind::ProcessId),
+            "sf::InAutoSelectBuffer" => ArgKind::Buffer(BufferKind::InAutoSelect),
+            "sf::InOutAutoSelectBuffer" => ArgKind::Buffer(BufferKind::InOutAutoSelect),
+            "sf::OutAutoSelectBuffer" => ArgKind::Buffer(BufferKind::OutAutoSelect),
+            "sf::InMapAliasBuffer" => ArgKind::Buffer(BufferKind::InMapAlias),
+            "sf::OutMapAliasBuffer" => ArgKind::Buffer(BufferKind::OutMapAlias),
+            "sf::InPointerBuffer" => ArgKind::Buffer(BufferKind::InPointer),
+            "sf::OutPointerBuffer" => ArgKind::Buffer(BufferKind::OutPointer),
+            "sf::CopyHandle" => ArgKind::Handle(HandleKind::Copy),
+            "sf::MoveHandle" => ArgKind::Handle(HandleKind::Move),
+            // Sub-interface objects, sent as move handles
+            "Counter" => ArgKind::Handle(HandleKind::Move),
+            _ => panic!("unknown argument type {}", arg_type),
+        }
+    }
+
+    // Splits "(...) rest" into the inside of the parentheses and the rest
+    fn take_group(s: &str) -> (&str, &str) {
+        let s = s.trim_start();
+        assert!(s.starts_with('('), "expected a group at {}", s);
+        let mut depth = 0;
+        for (i, c) in s.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return (&s[1..i], &s[i + 1..]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        panic!("unterminated group at {}", s);
+    }
+
+    // Gets the types of "name: type, ..." arguments, where types can have commas inside angle brackets
+    fn get_arg_types(args: &str) -> Vec<&str> {
+        let mut arg_types = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, c) in args.char_indices().chain([(args.len(), ',')]) {
+            match c {
+                '<' | '(' => depth += 1,
+                '>' | ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    let arg = args[start..i].trim();
+                    if !arg.is_empty() {
+                        arg_types.push(arg.split_once(':').unwrap().1.trim());
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        arg_types
+    }
+
+    fn parse_command(line: &str) -> ParsedCommand<'_> {
+        let (name, rest) = line.split_once('[').unwrap();
+        let (id, rest) = rest.split_once(',').unwrap();
+        let (_, rest) = rest.split_once("]:").unwrap();
+        let (in_args, rest) = take_group(rest);
+        let (out_args, _) = take_group(rest.trim_start().strip_prefix("=>").unwrap());
+
+        let mut command = ParsedCommand {
+            name: name.trim(),
+            id: id.trim().parse().unwrap(),
+            ..Default::default()
+        };
+        for arg_type in get_arg_types(in_args) {
+            match get_arg_kind(arg_type) {
+                ArgKind::Raw(kind) => command.in_raw.push(kind),
+                ArgKind::Buffer(kind) => command.buffers.push(kind),
+                ArgKind::Handle(kind) => command.in_handles.push(kind),
+            }
+        }
+        for arg_type in get_arg_types(out_args) {
+            match get_arg_kind(arg_type) {
+                ArgKind::Raw(kind) => command.out_raw.push(kind),
+                ArgKind::Handle(kind) => command.out_handles.push(kind),
+                ArgKind::Buffer(_) => panic!("buffers can't be outputs, in {}", line),
+            }
+        }
+        command
+    }
+
+    fn parse_interface(name: &str) -> Vec<ParsedCommand<'static>> {
+        let trait_start = std::format!("trait {} {{", name);
+        let mut lines = SERVER_INTERFACE_SRC.lines().map(str::trim).skip_while(|line| *line != trait_start).skip(1);
+        let commands: Vec<_> = lines
+            .by_ref()
+            .take_while(|line| *line != "}")
+            .filter(|line| !line.is_empty() && !line.starts_with("//"))
+            .map(parse_command)
+            .collect();
+        assert!(!commands.is_empty(), "interface {} not found", name);
+        commands
+    }
+
+    fn check_interface(name: &str, commands: &[CommandLayout]) {
+        let interface_commands = parse_interface(name);
+        for (interface_command, command) in interface_commands.iter().zip(commands) {
+            assert_eq!(interface_command, &ParsedCommand::from_layout(command));
+        }
+        assert_eq!(interface_commands.len(), commands.len(), "different amount of commands in {}", name);
+    }
+
+    #[test]
+    fn demo_service_matches_server() {
+        check_interface("DemoService", DEMO_SERVICE_COMMANDS);
+    }
+
+    #[test]
+    fn counter_matches_server() {
+        check_interface("Counter", COUNTER_COMMANDS);
+    }
+
+    #[test]
+    fn pointer_buffer_size_matches_server() {
+        let expected = std::format!("pub const POINTER_BUFFER_SIZE: usize = 0x{:X};", crate::golden::POINTER_BUFFER_SIZE);
+        assert!(SERVER_INTERFACE_SRC.lines().any(|line| line == expected));
+    }
+}
@@ -0,0 +1,381 @@
+//! Golden encodings of some demo requests and responses
+//!
+//! Every vector is encoded from its arguments and compared byte for byte, then decoded back and compared against the same
+//! arguments. The buffer addresses are made up (with bits above 32 set, to cover every address field of the descriptors).
+//! After an intended change to the interface, `codec-check --dump` prints the new encodings to paste here.
+
+use core::fmt;
+
+use crate::demo::{self, Buffer, CommandLayout, RequestArgs, COUNTER_COMMANDS, DEMO_SERVICE_COMMANDS};
+use crate::hipc;
+use crate::{DecodeError, EncodeError};
+
+/// Same pointer buffer size as the demo server
+pub const POINTER_BUFFER_SIZE: usize = 0x400;
+
+const ADDRESS_A: u64 = 0x0000_0123_4567_8000;
+const ADDRESS_B: u64 = 0x0000_0045_6789_A000;
+
+#[derive(Copy, Clone, Debug)]
+pub enum GoldenMessage {
+    Request(RequestArgs<'static>),
+    Response {
+        result: u32,
+        raw: &'static [u64],
+        handles: &'static [u32],
+    },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GoldenVector {
+    pub name: &'static str,
+    pub commands: &'static [CommandLayout],
+    pub command_name: &'static str,
+    pub message: GoldenMessage,
+    pub expected: &'static [u8],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GoldenError {
+    UnknownCommand,
+    Encode(EncodeError),
+    Decode(DecodeError),
+    SizeMismatch { expected: usize, actual: usize },
+    ByteMismatch { offset: usize, expected: u8, actual: u8 },
+    /// Decoding the expected bytes didn't give back the arguments
+    RoundTripMismatch,
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownCommand => write!(f, "unknown command"),
+            Self::Encode(e) => write!(f, "encoding failed: {}", e),
+            Self::Decode(e) => write!(f, "decoding failed: {}", e),
+            Self::SizeMismatch { expected, actual } => write!(f, "size 0x{:X} instead of 0x{:X}", actual, expected),
+            Self::ByteMismatch { offset, expected, actual } => {
+                write!(f, "byte 0x{:02X} instead of 0x{:02X} at offset 0x{:X}", actual, expected, offset)
+            }
+            Self::RoundTripMismatch => write!(f, "decoded message doesn't match the arguments"),
+        }
+    }
+}
+
+impl GoldenVector {
+    pub fn get_command(&self) -> Result<&'static CommandLayout, GoldenError> {
+        demo::find_command(self.commands, self.command_name).ok_or(GoldenError::UnknownCommand)
+    }
+
+    /// Encodes the message from its arguments, returning its size
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, GoldenError> {
+        let command = self.get_command()?;
+        match self.message {
+            GoldenMessage::Request(args) => demo::encode_request(command, &args, POINTER_BUFFER_SIZE, buf),
+            GoldenMessage::Response { result, raw, handles } => demo::encode_response(command, result, raw, handles, buf),
+        }
+        .map_err(GoldenError::Encode)
+    }
+
+    pub fn check(&self) -> Result<(), GoldenError> {
+        let mut buf = [0u8; hipc::MESSAGE_SIZE];
+        let size = self.encode(&mut buf)?;
+        if let Some(offset) = buf[..size].iter().zip(self.expected).position(|(actual, expected)| actual != expected) {
+            return Err(GoldenError::ByteMismatch {
+                offset,
+                expected: self.expected[offset],
+                actual: buf[offset],
+            });
+        }
+        if size != self.expected.len() {
+            return Err(GoldenError::SizeMismatch {
+                expected: self.expected.len(),
+                actual: size,
+            });
+        }
+
+        let command = self.get_command()?;
+        let round_trip_ok = match self.message {
+            GoldenMessage::Request(args) => {
+                let request = demo::decode_request(command, self.expected).map_err(GoldenError::Decode)?;
+                (&request.raw[..args.raw.len()] == args.raw)
+                    && (&request.buffers[..args.buffers.len()] == args.buffers)
+                    && (&request.handles[..args.handles.len()] == args.handles)
+            }
+            GoldenMessage::Response { result, raw, handles } => {
+                let response = demo::decode_response(command, self.expected).map_err(GoldenError::Decode)?;
+                (response.result == result)
+                    && ((result != 0) || ((&response.raw[..raw.len()] == raw) && (&response.handles[..handles.len()] == handles)))
+            }
+        };
+        if !round_trip_ok {
+            return Err(GoldenError::RoundTripMismatch);
+        }
+        Ok(())
+    }
+}
+
+pub const GOLDEN_VECTORS: &[GoldenVector] = &[
+    GoldenVector {
+        name: "sample_command request",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "sample_command",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[0x7, 0x82],
+            buffers: &[Buffer::new(ADDRESS_A, 4), Buffer::new(ADDRESS_B, 0x100)],
+            handles: &[],
+        }),
+        expected: &[
+            0x04, 0x00, 0x11, 0x10, 0x0C, 0x00, 0x00, 0x00, 0x80, 0x34, 0x04, 0x00, 0x00, 0x80, 0x67, 0x45,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            0x00, 0xA0, 0x89, 0x67, 0x10, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0xE7, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x82, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "get_sum_and_product request",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "get_sum_and_product",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[0x7, 0x82],
+            buffers: &[],
+            handles: &[],
+        }),
+        expected: &[
+            0x04, 0x00, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0xE8, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x82, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "get_sum_and_product response",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "get_sum_and_product",
+        message: GoldenMessage::Response {
+            result: 0,
+            raw: &[0x89, 0x38E],
+            handles: &[],
+        },
+        expected: &[
+            0x00, 0x00, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x4F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x89, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x8E, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "sum_map_alias_buffer request",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "sum_map_alias_buffer",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[],
+            buffers: &[Buffer::new(ADDRESS_A, 0x200)],
+            handles: &[],
+        }),
+        expected: &[
+            0x04, 0x00, 0x10, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x80, 0x67, 0x45,
+            0x48, 0x00, 0x00, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0xE9, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "sum_pointer_buffer request",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "sum_pointer_buffer",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[],
+            buffers: &[Buffer::new(ADDRESS_A, 0x200)],
+            handles: &[],
+        }),
+        expected: &[
+            0x04, 0x00, 0x01, 0x00, 0x08, 0x00, 0x00, 0x00, 0x80, 0x34, 0x00, 0x02, 0x00, 0x80, 0x67, 0x45,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0xEA, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "fill_auto_select_buffer request (pointer)",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "fill_auto_select_buffer",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[0xAA],
+            buffers: &[Buffer::new(ADDRESS_B, 0x100)],
+            handles: &[],
+        }),
+        expected: &[
+            0x04, 0x00, 0x00, 0x01, 0x09, 0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0xEB, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xAA, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xA0, 0x89, 0x67, 0x45, 0x00, 0x00, 0x01,
+        ],
+    },
+    GoldenVector {
+        name: "fill_auto_select_buffer request (map-alias)",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "fill_auto_select_buffer",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[0xAA],
+            buffers: &[Buffer::new(ADDRESS_B, 0x800)],
+            handles: &[],
+        }),
+        expected: &[
+            0x04, 0x00, 0x00, 0x01, 0x09, 0x0C, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0xA0, 0x89, 0x67,
+            0x10, 0x00, 0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0xEB, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xAA, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "fill_pointer_buffer request",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "fill_pointer_buffer",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[0xCC],
+            buffers: &[Buffer::new(ADDRESS_B, 0x400)],
+            handles: &[],
+        }),
+        expected: &[
+            0x04, 0x00, 0x00, 0x00, 0x09, 0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0xED, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xCC, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xA0, 0x89, 0x67,
+            0x45, 0x00, 0x00, 0x04,
+        ],
+    },
+    GoldenVector {
+        name: "is_event_signaled request",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "is_event_signaled",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[],
+            buffers: &[],
+            handles: &[0x1234],
+        }),
+        expected: &[
+            0x04, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x80, 0x02, 0x00, 0x00, 0x00, 0x34, 0x12, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0xF2, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "create_signaled_event response",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "create_signaled_event",
+        message: GoldenMessage::Response {
+            result: 0,
+            raw: &[],
+            handles: &[0x5678],
+        },
+        expected: &[
+            0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x80, 0x20, 0x00, 0x00, 0x00, 0x78, 0x56, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x4F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "get_process_id request",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "get_process_id",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[0x84],
+            buffers: &[],
+            handles: &[],
+        }),
+        expected: &[
+            0x04, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x84, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0xFC, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "open_counter response",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "open_counter",
+        message: GoldenMessage::Response {
+            result: 0,
+            raw: &[],
+            handles: &[0x9ABC],
+        },
+        expected: &[
+            0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x80, 0x20, 0x00, 0x00, 0x00, 0xBC, 0x9A, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x4F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "get_entry response",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "get_entry",
+        message: GoldenMessage::Response {
+            result: 0,
+            raw: &[1, 0x100],
+            handles: &[],
+        },
+        expected: &[
+            0x00, 0x00, 0x00, 0x00, 0x0C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x4F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "set_entry failed response",
+        commands: DEMO_SERVICE_COMMANDS,
+        command_name: "set_entry",
+        message: GoldenMessage::Response {
+            result: 0x2A8,
+            raw: &[],
+            handles: &[],
+        },
+        expected: &[
+            0x00, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x4F, 0x00, 0x00, 0x00, 0x00, 0xA8, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+    GoldenVector {
+        name: "counter increment request",
+        commands: COUNTER_COMMANDS,
+        command_name: "increment",
+        message: GoldenMessage::Request(RequestArgs {
+            raw: &[5],
+            buffers: &[],
+            handles: &[],
+        }),
+        expected: &[
+            0x04, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x53, 0x46, 0x43, 0x49, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ],
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    #[test]
+    fn golden_vectors() {
+        for vector in GOLDEN_VECTORS {
+            if let Err(e) = vector.check() {
+                panic!("{}: {}", vector.name, e);
+            }
+        }
+    }
+
+    #[test]
+    fn golden_vectors_change_with_the_command_id() {
+        // Same vector, but with a command table where its ID is off by one
+        let vector = &GOLDEN_VECTORS[0];
+        let mut command = *vector.get_command().unwrap();
+        command.id += 1;
+        let commands: &'static [CommandLayout] = std::boxed::Box::leak(std::boxed::Box::new([command]));
+        let vector = GoldenVector { commands, ..*vector };
+        assert!(matches!(vector.check(), Err(GoldenError::ByteMismatch { .. })));
+    }
+}
@@ -0,0 +1,396 @@
+//! HIPC layout, the part of IPC messages the kernel looks at
+//!
+//! A message starts with a two-word header, an optional special header (followed by the process ID and the copy and move
+//! handles), and the descriptors of every buffer: send statics (X), send (A), receive (B) and exchange (W) buffers. Then come
+//! the data words, and at last the receive list (C) where the receiver wants statics to be copied to.
+//! Address bits past what each descriptor can hold are dropped.
+
+use crate::{DecodeError, EncodeError};
+
+/// Size of the IPC buffer in the thread local region, no message can be bigger
+pub const MESSAGE_SIZE: usize = 0x100;
+
+const STATIC_DESCRIPTOR_SIZE: usize = 8;
+const BUFFER_DESCRIPTOR_SIZE: usize = 12;
+const RECV_LIST_ENTRY_SIZE: usize = 8;
+
+// Counts in the header only have 4 bits
+const MAX_ITEM_COUNT: usize = 0xF;
+
+// The receive static mode for a list of N entries is 2 + N
+const RECV_STATIC_MODE_LIST_BASE: u32 = 2;
+
+fn read_u32(buf: &[u8], offset: usize) -> Result<u32, DecodeError> {
+    buf.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(DecodeError::UnexpectedEnd)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub command_type: u16,
+    pub send_static_count: u8,
+    pub send_buffer_count: u8,
+    pub recv_buffer_count: u8,
+    pub exch_buffer_count: u8,
+    pub data_word_count: u16,
+    pub recv_static_mode: u8,
+    pub has_special_header: bool,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u32; 2] {
+        [
+            (self.command_type as u32)
+                | ((self.send_static_count as u32 & 0xF) << 16)
+                | ((self.send_buffer_count as u32 & 0xF) << 20)
+                | ((self.recv_buffer_count as u32 & 0xF) << 24)
+                | ((self.exch_buffer_count as u32 & 0xF) << 28),
+            (self.data_word_count as u32 & 0x3FF)
+                | ((self.recv_static_mode as u32 & 0xF) << 10)
+                | ((self.has_special_header as u32) << 31),
+        ]
+    }
+
+    pub fn decode(words: [u32; 2]) -> Self {
+        Self {
+            command_type: words[0] as u16,
+            send_static_count: ((words[0] >> 16) & 0xF) as u8,
+            send_buffer_count: ((words[0] >> 20) & 0xF) as u8,
+            recv_buffer_count: ((words[0] >> 24) & 0xF) as u8,
+            exch_buffer_count: ((words[0] >> 28) & 0xF) as u8,
+            data_word_count: (words[1] & 0x3FF) as u16,
+            recv_static_mode: ((words[1] >> 10) & 0xF) as u8,
+            has_special_header: (words[1] >> 31) != 0,
+        }
+    }
+
+    pub fn get_recv_list_len(&self) -> usize {
+        (self.recv_static_mode as usize).saturating_sub(RECV_STATIC_MODE_LIST_BASE as usize)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpecialHeader {
+    pub send_process_id: bool,
+    pub copy_handle_count: u8,
+    pub move_handle_count: u8,
+}
+
+impl SpecialHeader {
+    pub fn encode(&self) -> u32 {
+        (self.send_process_id as u32) | ((self.copy_handle_count as u32 & 0xF) << 1) | ((self.move_handle_count as u32 & 0xF) << 5)
+    }
+
+    pub fn decode(word: u32) -> Self {
+        Self {
+            send_process_id: (word & 1) != 0,
+            copy_handle_count: ((word >> 1) & 0xF) as u8,
+            move_handle_count: ((word >> 5) & 0xF) as u8,
+        }
+    }
+}
+
+/// Send static (X) descriptor, the kernel copies the data into the receiver's matching receive list entry
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StaticDescriptor {
+    pub index: u8,
+    pub address: u64,
+    pub size: u16,
+}
+
+impl StaticDescriptor {
+    pub fn encode(&self) -> [u32; 2] {
+        [
+            (self.index as u32 & 0x3F)
+                | ((((self.address >> 36) & 0x3F) as u32) << 6)
+                | ((((self.address >> 32) & 0xF) as u32) << 12)
+                | ((self.size as u32) << 16),
+            self.address as u32,
+        ]
+    }
+
+    pub fn decode(words: [u32; 2]) -> Self {
+        Self {
+            index: (words[0] & 0x3F) as u8,
+            address: (words[1] as u64) | ((((words[0] >> 12) & 0xF) as u64) << 32) | ((((words[0] >> 6) & 0x3F) as u64) << 36),
+            size: (words[0] >> 16) as u16,
+        }
+    }
+}
+
+/// Send (A), receive (B) or exchange (W) descriptor, the buffer gets mapped into the receiver
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BufferDescriptor {
+    pub address: u64,
+    pub size: u64,
+    /// Memory state the buffer is allowed to have, 0 for regular memory
+    pub mode: u8,
+}
+
+impl BufferDescriptor {
+    pub fn encode(&self) -> [u32; 3] {
+        [
+            self.size as u32,
+            self.address as u32,
+            (self.mode as u32 & 0x3)
+                | ((((self.address >> 36) & 0x3FFFFF) as u32) << 2)
+                | ((((self.size >> 32) & 0xF) as u32) << 24)
+                | ((((self.address >> 32) & 0xF) as u32) << 28),
+        ]
+    }
+
+    pub fn decode(words: [u32; 3]) -> Self {
+        Self {
+            address: (words[1] as u64) | (((words[2] >> 28) as u64) << 32) | ((((words[2] >> 2) & 0x3FFFFF) as u64) << 36),
+            size: (words[0] as u64) | ((((words[2] >> 24) & 0xF) as u64) << 32),
+            mode: (words[2] & 0x3) as u8,
+        }
+    }
+}
+
+/// Receive list (C) entry, where the receiver wants the data of the matching send static
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecvListEntry {
+    pub address: u64,
+    pub size: u16,
+}
+
+impl RecvListEntry {
+    pub fn encode(&self) -> [u32; 2] {
+        [self.address as u32, (((self.address >> 32) & 0xFFFF) as u32) | ((self.size as u32) << 16)]
+    }
+
+    pub fn decode(words: [u32; 2]) -> Self {
+        Self {
+            address: (words[0] as u64) | (((words[1] & 0xFFFF) as u64) << 32),
+            size: (words[1] >> 16) as u16,
+        }
+    }
+}
+
+/// Everything making up a message, `data` being the data words without the alignment padding before them
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Message<'a> {
+    pub command_type: u16,
+    pub process_id: Option<u64>,
+    pub copy_handles: &'a [u32],
+    pub move_handles: &'a [u32],
+    pub send_statics: &'a [StaticDescriptor],
+    pub send_buffers: &'a [BufferDescriptor],
+    pub recv_buffers: &'a [BufferDescriptor],
+    pub exch_buffers: &'a [BufferDescriptor],
+    pub data: &'a [u8],
+    pub recv_list: &'a [RecvListEntry],
+}
+
+struct MessageWriter<'a> {
+    buf: &'a mut [u8],
+    offset: usize,
+}
+
+impl MessageWriter<'_> {
+    fn write_bytes(&mut self, data: &[u8]) -> Result<(), EncodeError> {
+        let end = self.offset + data.len();
+        let dest = self.buf.get_mut(self.offset..end).ok_or(EncodeError::MessageTooBig(end))?;
+        dest.copy_from_slice(data);
+        self.offset = end;
+        Ok(())
+    }
+
+    fn write_words(&mut self, words: &[u32]) -> Result<(), EncodeError> {
+        words.iter().try_for_each(|word| self.write_bytes(&word.to_le_bytes()))
+    }
+}
+
+/// Gets where the data words start given where they would start without padding, they're aligned to 16 bytes
+pub const fn get_aligned_data_offset(offset: usize) -> usize {
+    (offset + 0xF) & !0xF
+}
+
+impl Message<'_> {
+    /// Writes the message into `buf`, returning its size
+    ///
+    /// Like official code, the data words always make room for 16 bytes of alignment padding, whatever padding is actually needed.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, EncodeError> {
+        let counts = [
+            self.copy_handles.len(),
+            self.move_handles.len(),
+            self.send_statics.len(),
+            self.send_buffers.len(),
+            self.recv_buffers.len(),
+            self.exch_buffers.len(),
+        ];
+        if counts.iter().any(|&count| count > MAX_ITEM_COUNT) || (self.recv_list.len() > MAX_ITEM_COUNT - RECV_STATIC_MODE_LIST_BASE as usize) {
+            return Err(EncodeError::TooManyItems);
+        }
+
+        let has_special_header = self.process_id.is_some() || !self.copy_handles.is_empty() || !self.move_handles.is_empty();
+        let data_word_count = (0x10 + self.data.len()).div_ceil(4);
+        let header = Header {
+            command_type: self.command_type,
+            send_static_count: self.send_statics.len() as u8,
+            send_buffer_count: self.send_buffers.len() as u8,
+            recv_buffer_count: self.recv_buffers.len() as u8,
+            exch_buffer_count: self.exch_buffers.len() as u8,
+            data_word_count: data_word_count as u16,
+            recv_static_mode: match self.recv_list.len() {
+                0 => 0,
+                len => (RECV_STATIC_MODE_LIST_BASE as usize + len) as u8,
+            },
+            has_special_header,
+        };
+
+        let mut writer = MessageWriter { buf, offset: 0 };
+        writer.write_words(&header.encode())?;
+        if has_special_header {
+            let special_header = SpecialHeader {
+                send_process_id: self.process_id.is_some(),
+                copy_handle_count: self.copy_handles.len() as u8,
+                move_handle_count: self.move_handles.len() as u8,
+            };
+            writer.write_words(&[special_header.encode()])?;
+            if let Some(process_id) = self.process_id {
+                writer.write_bytes(&process_id.to_le_bytes())?;
+            }
+            writer.write_words(self.copy_handles)?;
+            writer.write_words(self.move_handles)?;
+        }
+        for send_static in self.send_statics {
+            writer.write_words(&send_static.encode())?;
+        }
+        for buffer in self.send_buffers.iter().chain(self.recv_buffers).chain(self.exch_buffers) {
+            writer.write_words(&buffer.encode())?;
+        }
+
+        let data_words_offset = writer.offset;
+        let data_offset = get_aligned_data_offset(data_words_offset);
+        writer.write_bytes(&[0; 0x10][..data_offset - data_words_offset])?;
+        writer.write_bytes(self.data)?;
+        // Whatever alignment padding wasn't needed goes after the data
+        let recv_list_offset = data_words_offset + data_word_count * 4;
+        while writer.offset < recv_list_offset {
+            writer.write_bytes(&[0])?;
+        }
+
+        for entry in self.recv_list {
+            writer.write_words(&entry.encode())?;
+        }
+
+        if writer.offset > MESSAGE_SIZE {
+            return Err(EncodeError::MessageTooBig(writer.offset));
+        }
+        Ok(writer.offset)
+    }
+}
+
+/// Parsed message, with everything still in the original buffer
+#[derive(Copy, Clone, Debug)]
+pub struct MessageView<'a> {
+    buf: &'a [u8],
+    pub header: Header,
+    pub special_header: Option<SpecialHeader>,
+    pub process_id: Option<u64>,
+    copy_handles_offset: usize,
+    move_handles_offset: usize,
+    send_statics_offset: usize,
+    send_buffers_offset: usize,
+    data_words_offset: usize,
+    recv_list_offset: usize,
+    size: usize,
+}
+
+impl<'a> MessageView<'a> {
+    pub fn parse(buf: &'a [u8]) -> Result<Self, DecodeError> {
+        let header = Header::decode([read_u32(buf, 0)?, read_u32(buf, 4)?]);
+        let mut offset = 8;
+
+        let mut special_header = None;
+        let mut process_id = None;
+        if header.has_special_header {
+            let special = SpecialHeader::decode(read_u32(buf, offset)?);
+            offset += 4;
+            if special.send_process_id {
+                process_id = Some((read_u32(buf, offset)? as u64) | ((read_u32(buf, offset + 4)? as u64) << 32));
+                offset += 8;
+            }
+            special_header = Some(special);
+        }
+
+        let copy_handles_offset = offset;
+        let move_handles_offset = copy_handles_offset + 4 * special_header.map_or(0, |special| special.copy_handle_count as usize);
+        let send_statics_offset = move_handles_offset + 4 * special_header.map_or(0, |special| special.move_handle_count as usize);
+        let send_buffers_offset = send_statics_offset + STATIC_DESCRIPTOR_SIZE * header.send_static_count as usize;
+        let buffer_count = header.send_buffer_count as usize + header.recv_buffer_count as usize + header.exch_buffer_count as usize;
+        let data_words_offset = send_buffers_offset + BUFFER_DESCRIPTOR_SIZE * buffer_count;
+        let recv_list_offset = data_words_offset + 4 * header.data_word_count as usize;
+        let size = recv_list_offset + RECV_LIST_ENTRY_SIZE * header.get_recv_list_len();
+        if (size > buf.len()) || (get_aligned_data_offset(data_words_offset) > recv_list_offset) {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        Ok(Self {
+            buf,
+            header,
+            special_header,
+            process_id,
+            copy_handles_offset,
+            move_handles_offset,
+            send_statics_offset,
+            send_buffers_offset,
+            data_words_offset,
+            recv_list_offset,
+            size,
+        })
+    }
+
+    /// Size of the whole message
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    fn words<const N: usize>(&self, offset: usize, count: usize) -> impl Iterator<Item = [u32; N]> + 'a {
+        // Everything was bounds-checked while parsing
+        self.buf[offset..offset + 4 * N * count].chunks_exact(4 * N).map(|chunk| {
+            core::array::from_fn(|i| u32::from_le_bytes([chunk[4 * i], chunk[4 * i + 1], chunk[4 * i + 2], chunk[4 * i + 3]]))
+        })
+    }
+
+    pub fn copy_handles(&self) -> impl Iterator<Item = u32> + 'a {
+        let count = self.special_header.map_or(0, |special| special.copy_handle_count as usize);
+        self.words::<1>(self.copy_handles_offset, count).map(|[handle]| handle)
+    }
+
+    pub fn move_handles(&self) -> impl Iterator<Item = u32> + 'a {
+        let count = self.special_header.map_or(0, |special| special.move_handle_count as usize);
+        self.words::<1>(self.move_handles_offset, count).map(|[handle]| handle)
+    }
+
+    pub fn send_statics(&self) -> impl Iterator<Item = StaticDescriptor> + 'a {
+        self.words::<2>(self.send_statics_offset, self.header.send_static_count as usize).map(StaticDescriptor::decode)
+    }
+
+    pub fn send_buffers(&self) -> impl Iterator<Item = BufferDescriptor> + 'a {
+        self.words::<3>(self.send_buffers_offset, self.header.send_buffer_count as usize).map(BufferDescriptor::decode)
+    }
+
+    pub fn recv_buffers(&self) -> impl Iterator<Item = BufferDescriptor> + 'a {
+        let offset = self.send_buffers_offset + BUFFER_DESCRIPTOR_SIZE * self.header.send_buffer_count as usize;
+        self.words::<3>(offset, self.header.recv_buffer_count as usize).map(BufferDescriptor::decode)
+    }
+
+    pub fn exch_buffers(&self) -> impl Iterator<Item = BufferDescriptor> + 'a {
+        let offset = self.send_buffers_offset
+            + BUFFER_DESCRIPTOR_SIZE * (self.header.send_buffer_count as usize + self.header.recv_buffer_count as usize);
+        self.words::<3>(offset, self.header.exch_buffer_count as usize).map(BufferDescriptor::decode)
+    }
+
+    pub fn recv_list(&self) -> impl Iterator<Item = RecvListEntry> + 'a {
+        self.words::<2>(self.recv_list_offset, self.header.get_recv_list_len()).map(RecvListEntry::decode)
+    }
+
+    /// Gets the data words past the alignment padding, along with the unused padding left at their end
+    pub fn get_data(&self) -> &'a [u8] {
+        &self.buf[get_aligned_data_offset(self.data_words_offset)..self.recv_list_offset]
+    }
+}
@@ -0,0 +1,60 @@
+//! Host-side encoding and decoding of the IPC messages of the `simple-service` demo interface
+//!
+//! On the console all of this is done by the `nx` macros, this crate lays out the same messages by hand so they can be
+//! checked anywhere: `hipc` covers the part handled by the kernel (header, handles and buffer descriptors), `cmif` the
+//! header of the data words, and `demo` the commands of the demo interface. The `golden` module holds byte-for-byte
+//! encodings of a few requests and responses, which the `codec-check` host tool compares against.
+//! Only CMIF messages are covered, not TIPC ones.
+//!
+//! The command tables in `demo` follow the interfaces in `simple-service-server`, and the tests check them against its
+//! source (the crate itself only builds for the console). The golden vectors are checked by the tests too, and by the
+//! `codec-check` tool, which can also print new ones.
+
+#![no_std]
+
+use core::fmt;
+
+pub mod cmif;
+pub mod demo;
+pub mod golden;
+pub mod hipc;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The message doesn't fit in the IPC buffer
+    MessageTooBig(usize),
+    /// More descriptors or handles of some kind than the header can hold
+    TooManyItems,
+    /// The arguments don't match the command layout
+    ArgumentMismatch,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MessageTooBig(size) => write!(f, "message too big (0x{:X} bytes)", size),
+            Self::TooManyItems => write!(f, "too many descriptors or handles"),
+            Self::ArgumentMismatch => write!(f, "arguments don't match the command layout"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The message ended before the expected amount of data could be read
+    UnexpectedEnd,
+    /// The CMIF header magic is not the expected one
+    InvalidMagic(u32),
+    /// The message doesn't have the descriptors, handles or data the command layout expects
+    LayoutMismatch,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of data"),
+            Self::InvalidMagic(magic) => write!(f, "invalid CMIF magic 0x{:08X}", magic),
+            Self::LayoutMismatch => write!(f, "message doesn't match the command layout"),
+        }
+    }
+}
@@ -1,21 +1,111 @@
+//! IPC interface of the demo service, shared by its server and client
+//!
+//! Every command covers a different part of what IPC commands can carry: raw data in both directions, the several kinds of
+//! buffers, copied and moved handles, the sender's process ID and sub-interface objects.
+//! Every session also has its own state (a counter, a small key/value store and the client's process ID), released as soon
+//! as the session is closed, while some other state is shared by all of them.
+//!
+//! Jobs show how a server hands out an event it signals later on: the client gets the event when starting the job, waits
+//! on it while a background server thread runs the job, and only then asks for the result. A cancelled job also signals its
+//! event, but there's no result to get then.
+//!
+//! The demo service can be used over both CMIF and TIPC, the latter lacking pointer buffers and sub-interface objects.
+//!
+//! The domain variant of the service hands out the same sub-interface objects, but its sessions are converted to domains,
+//! where every object is just an ID within the one session instead of a session of its own.
+
 #![no_std]
 
 use nx::ipc::sf;
+use nx::ipc::CommandProtocol;
 use nx::result::Result;
 use nx::service::{self, sm};
 use nx::version;
 use nx::{ipc_sf_define_default_client_for_interface, ipc_sf_define_interface_trait};
 
+pub const DEMO_SERVICE_NAME: &str = "dmo-srv";
+pub const DEMO_DOMAIN_SERVICE_NAME: &str = "dmo-dom";
+
+/// Sessions the domain service accepts at once, no matter how many objects each domain holds
+pub const DEMO_DOMAIN_SERVICE_MAX_SESSIONS: i32 = 2;
+
+/// Size of the server's pointer buffer, pointer buffers can't be bigger than this (map-alias buffers have no such limit)
+pub const POINTER_BUFFER_SIZE: usize = 0x400;
+
+/// Longest a job can be asked to take, longer delays are cut down to this
+pub const MAX_JOB_DELAY_MS: u32 = 5000;
+
+/// What a job started with `value` computes (the sum of every number up to it), so that clients can check the result
+pub const fn get_job_result_for(value: u32) -> u64 {
+    let value = value as u64;
+    value * (value + 1) / 2
+}
+
+// Protocol IDs returned by get_session_protocol
+pub const PROTOCOL_ID_CMIF: u8 = 0;
+pub const PROTOCOL_ID_TIPC: u8 = 1;
+
+pub const fn get_protocol_id(protocol: CommandProtocol) -> u8 {
+    match protocol {
+        CommandProtocol::Cmif => PROTOCOL_ID_CMIF,
+        CommandProtocol::Tipc => PROTOCOL_ID_TIPC,
+    }
+}
+
+/// Entries the key/value store of each session can hold
+pub const MAX_SESSION_ENTRIES: usize = 0x10;
+
+ipc_sf_define_default_client_for_interface!(Counter);
+ipc_sf_define_interface_trait! {
+    trait Counter {
+        increment [0, version::VersionInterval::all(), mut ]: (amount: u64) => (value: u64) (value: u64);
+        get_value [1, version::VersionInterval::all(), mut ]: () => (value: u64) (value: u64);
+    }
+}
+
 ipc_sf_define_default_client_for_interface!(DemoService);
 ipc_sf_define_interface_trait! {
     trait DemoService {
+        // Writes the reversed `c` into `d`
         sample_command [999, version::VersionInterval::all(), mut ]: (a: u32, b: u64, c: sf::InAutoSelectBuffer<'_, u8>, d: sf::InOutAutoSelectBuffer<'_, u8>) => () ();
+        get_sum_and_product [1000, version::VersionInterval::all(), mut ]: (a: u32, b: u64) => (sum: u64, product: u64) (sum: u64, product: u64);
+        sum_map_alias_buffer [1001, version::VersionInterval::all(), mut ]: (buf: sf::InMapAliasBuffer<'_, u8>) => (sum: u64) (sum: u64);
+        sum_pointer_buffer [1002, version::VersionInterval::all(), mut ]: (buf: sf::InPointerBuffer<'_, u8>) => (sum: u64) (sum: u64);
+        // The fill commands write `value` all over the buffer, returning how many bytes that was
+        fill_auto_select_buffer [1003, version::VersionInterval::all(), mut ]: (value: u8, out_buf: sf::OutAutoSelectBuffer<'_, u8>) => (size: u64) (size: u64);
+        fill_map_alias_buffer [1004, version::VersionInterval::all(), mut ]: (value: u8, out_buf: sf::OutMapAliasBuffer<'_, u8>) => (size: u64) (size: u64);
+        fill_pointer_buffer [1005, version::VersionInterval::all(), mut ]: (value: u8, out_buf: sf::OutPointerBuffer<'_, u8>) => (size: u64) (size: u64);
+        // The server gets its own copy of the handle, the sender's one stays valid
+        is_event_signaled [1010, version::VersionInterval::all(), mut ]: (event: sf::CopyHandle) => (signaled: bool) (signaled: bool);
+        // The handle is moved to the caller, which has to close it
+        create_signaled_event [1011, version::VersionInterval::all(), mut ]: () => (event: sf::MoveHandle) (event: sf::MoveHandle);
+        // The process ID is filled in by the kernel, whatever the sender passes
+        get_process_id [1020, version::VersionInterval::all(), mut ]: (process_id: sf::ProcessId) => (process_id: u64) (process_id: u64);
+        open_counter [1030, version::VersionInterval::all(), mut ]: (initial_value: u64) => (counter: Counter) (counter: impl ICounterServer + 'static);
+        // Per-session state: the process ID is only known after this
+        register_client [1040, version::VersionInterval::all(), mut ]: (process_id: sf::ProcessId) => () ();
+        // Zero if the client didn't register
+        get_client_process_id [1041, version::VersionInterval::all(), mut ]: () => (process_id: u64) (process_id: u64);
+        increment_session_counter [1042, version::VersionInterval::all(), mut ]: () => (value: u64) (value: u64);
+        // New keys aren't stored once the store is full, existing ones can always be updated
+        set_entry [1043, version::VersionInterval::all(), mut ]: (key: u32, value: u64) => (stored: bool) (stored: bool);
+        get_entry [1044, version::VersionInterval::all(), mut ]: (key: u32) => (found: bool, value: u64) (found: bool, value: u64);
+        // State shared by all the sessions
+        get_session_count [1050, version::VersionInterval::all(), mut ]: () => (count: u32) (count: u32);
+        increment_shared_counter [1051, version::VersionInterval::all(), mut ]: () => (value: u64) (value: u64);
+        // Protocol of the last request the session got (this one), as one of the protocol IDs above
+        get_session_protocol [1060, version::VersionInterval::all(), mut ]: () => (protocol_id: u8) (protocol_id: u8);
+        // The event is moved to the caller and signaled once the job is done. Jobs which didn't start yet get cancelled (their
+        // event signaled without a result) when another one is started in the same session or if too many are queued already
+        start_job [1070, version::VersionInterval::all(), mut ]: (value: u32, delay_ms: u32) => (event: sf::MoveHandle) (event: sf::MoveHandle);
+        // Result of the last job started in this session, only valid once it's done
+        get_job_result [1071, version::VersionInterval::all(), mut ]: () => (done: bool, result: u64) (done: bool, result: u64);
     }
 }
 
 impl service::IService for DemoService {
     fn get_name() -> sm::ServiceName {
-        sm::ServiceName::new("dmo-srv")
+        sm::ServiceName::new(DEMO_SERVICE_NAME)
     }
 
     fn as_domain() -> bool {
@@ -26,3 +116,26 @@ impl service::IService for DemoService {
         Ok(())
     }
 }
+
+ipc_sf_define_default_client_for_interface!(DemoDomainService);
+ipc_sf_define_interface_trait! {
+    trait DemoDomainService {
+        open_counter [0, version::VersionInterval::all(), mut ]: (initial_value: u64) => (counter: Counter) (counter: impl ICounterServer + 'static);
+        // Counters opened through this session which haven't been closed yet
+        get_open_counter_count [1, version::VersionInterval::all(), mut ]: () => (count: u32) (count: u32);
+    }
+}
+
+impl service::IService for DemoDomainService {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new(DEMO_DOMAIN_SERVICE_NAME)
+    }
+
+    fn as_domain() -> bool {
+        true
+    }
+
+    fn post_initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
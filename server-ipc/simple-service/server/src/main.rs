@@ -15,14 +15,186 @@ use nx::ipc::server;
 use nx::ipc::sf;
 use nx::result::*;
 use nx::service::sm;
+use nx::svc;
+use nx::sync::Mutex;
+use nx::thread;
 use nx::util;
+use nx::wait;
 
-use simple_service_server::IDemoServiceServer;
+use simple_service_server::{
+    get_job_result_for, get_protocol_id, ICounterServer, IDemoDomainServiceServer, IDemoServiceServer,
+    DEMO_DOMAIN_SERVICE_MAX_SESSIONS, DEMO_DOMAIN_SERVICE_NAME, DEMO_SERVICE_NAME, MAX_JOB_DELAY_MS, MAX_SESSION_ENTRIES,
+    POINTER_BUFFER_SIZE, PROTOCOL_ID_CMIF,
+};
 
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::panic;
 use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicU32, Ordering};
 
-pub struct DemoServiceServer;
+pub struct CounterServer {
+    value: u64,
+    // Shared with the domain service session which opened it, if any
+    open_count: Option<Arc<AtomicU32>>,
+}
+
+impl CounterServer {
+    fn new(value: u64, open_count: Option<Arc<AtomicU32>>) -> Self {
+        if let Some(open_count) = open_count.as_ref() {
+            open_count.fetch_add(1, Ordering::SeqCst);
+        }
+        Self { value, open_count }
+    }
+}
+
+impl Drop for CounterServer {
+    fn drop(&mut self) {
+        // In a domain this happens when the client closes the object, while the session itself stays open
+        if let Some(open_count) = self.open_count.as_ref() {
+            open_count.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl ICounterServer for CounterServer {
+    fn increment(&mut self, amount: u64) -> Result<u64> {
+        self.value = self.value.wrapping_add(amount);
+        Ok(self.value)
+    }
+
+    fn get_value(&mut self) -> Result<u64> {
+        Ok(self.value)
+    }
+}
+
+impl server::ISessionObject for CounterServer {
+    fn try_handle_request_by_id(
+        &mut self,
+        req_id: u32,
+        protocol: nx::ipc::CommandProtocol,
+        server_ctx: &mut server::ServerContext,
+    ) -> Option<Result<()>> {
+        <Self as ICounterServer>::try_handle_request_by_id(self, req_id, protocol, server_ctx)
+    }
+}
+
+fn sum_bytes(buf: &[u8]) -> u64 {
+    buf.iter().map(|&byte| byte as u64).sum()
+}
+
+struct SharedState {
+    session_count: u32,
+    counter: u64,
+}
+
+// The server handles every session, so it's the one place where they can share anything
+static G_SHARED_STATE: Mutex<SharedState> = Mutex::new(SharedState {
+    session_count: 0,
+    counter: 0,
+});
+
+// Filled in by the job thread, the session which started the job keeps the other reference
+type JobResult = Arc<Mutex<Option<u64>>>;
+
+struct Job {
+    value: u32,
+    delay_ms: u32,
+    // The client got the readable end, we only keep this one to signal it
+    writable_event: svc::Handle,
+    result: JobResult,
+}
+
+impl Job {
+    // Wakes up the client without a result, so it doesn't wait forever for a job which will never run
+    fn cancel(self) {
+        let _ = svc::signal_event(self.writable_event);
+        let _ = svc::close_handle(self.writable_event);
+    }
+}
+
+// Every session has at most one job queued (starting another one replaces it), but there can be lots of sessions and
+// every job holds a kernel event, so the queue has its own limit too
+const MAX_PENDING_JOBS: usize = 8;
+
+struct JobQueue {
+    jobs: Vec<Job>,
+    // Writable end of the event the job thread waits on while there are no jobs, created before any session exists
+    wakeup_event: svc::Handle,
+}
+
+impl JobQueue {
+    // Removes the job of the given session which didn't start yet, if any
+    fn take_session_job(&mut self, result: &JobResult) -> Option<Job> {
+        let job_idx = self.jobs.iter().position(|job| Arc::ptr_eq(&job.result, result))?;
+        Some(self.jobs.remove(job_idx))
+    }
+}
+
+static G_JOB_QUEUE: Mutex<JobQueue> = Mutex::new(JobQueue {
+    jobs: Vec::new(),
+    wakeup_event: 0,
+});
+
+fn run_job(job: Job) {
+    // Stands for whatever actually takes time, the IPC thread is free to handle other requests meanwhile
+    let _ = thread::sleep(job.delay_ms as i64 * 1_000_000);
+    *job.result.lock() = Some(get_job_result_for(job.value));
+
+    // The result has to be there before the client wakes up
+    let _ = svc::signal_event(job.writable_event);
+    let _ = svc::close_handle(job.writable_event);
+}
+
+fn job_thread(wakeup_event: svc::Handle) {
+    loop {
+        let job = {
+            let mut job_queue = G_JOB_QUEUE.lock();
+            if job_queue.jobs.is_empty() {
+                None
+            } else {
+                Some(job_queue.jobs.remove(0))
+            }
+        };
+
+        match job {
+            Some(job) => run_job(job),
+            None => {
+                // Jobs queued after the queue was checked leave the event signaled, so none of them can be missed
+                let _ = wait::wait_handles(&[wakeup_event], -1);
+                let _ = svc::clear_event(wakeup_event);
+            }
+        }
+    }
+}
+
+// Every session gets its own one of these, dropped as soon as the session is closed
+pub struct DemoServiceServer {
+    client_process_id: Option<u64>,
+    counter: u64,
+    entries: Vec<(u32, u64)>,
+    protocol_id: u8,
+    // Jobs still run (and signal their event) if the session is closed before they're done
+    job_result: Option<JobResult>,
+}
+
+impl Drop for DemoServiceServer {
+    fn drop(&mut self) {
+        let session_count = {
+            let mut shared_state = G_SHARED_STATE.lock();
+            shared_state.session_count -= 1;
+            shared_state.session_count
+        };
+        diag_log!(LmLogger { LogSeverity::Trace, true } => "Session of process {:#X} closed with {} entries, {} sessions left", self.client_process_id.unwrap_or(0), self.entries.len(), session_count);
+
+        // Nobody is left to ask for its result
+        if let Some(job_result) = self.job_result.as_ref() {
+            if let Some(job) = G_JOB_QUEUE.lock().take_session_job(job_result) {
+                job.cancel();
+            }
+        }
+    }
+}
 
 impl IDemoServiceServer for DemoServiceServer {
     fn sample_command(
@@ -37,8 +209,151 @@ impl IDemoServiceServer for DemoServiceServer {
         diag_log!(LmLogger { LogSeverity::Trace, true } => "c len: {}", c.get_string().len());
         diag_log!(LmLogger { LogSeverity::Trace, true } => "d len: {}", d.get_string().len());
 
+        let in_buf = unsafe { core::slice::from_raw_parts(c.get_address(), c.get_size()) };
+        let inout_buf = unsafe { core::slice::from_raw_parts_mut(d.get_address() as *mut u8, d.get_size()) };
+        for (inout_byte, &in_byte) in inout_buf.iter_mut().zip(in_buf.iter().rev()) {
+            *inout_byte = in_byte;
+        }
+
         Ok(())
     }
+
+    fn get_sum_and_product(&mut self, a: u32, b: u64) -> Result<(u64, u64)> {
+        Ok(((a as u64).wrapping_add(b), (a as u64).wrapping_mul(b)))
+    }
+
+    fn sum_map_alias_buffer(&mut self, buf: sf::InMapAliasBuffer<'_, u8>) -> Result<u64> {
+        let buf = unsafe { core::slice::from_raw_parts(buf.get_address(), buf.get_size()) };
+        Ok(sum_bytes(buf))
+    }
+
+    fn sum_pointer_buffer(&mut self, buf: sf::InPointerBuffer<'_, u8>) -> Result<u64> {
+        let buf = unsafe { core::slice::from_raw_parts(buf.get_address(), buf.get_size()) };
+        Ok(sum_bytes(buf))
+    }
+
+    fn fill_auto_select_buffer(&mut self, value: u8, out_buf: sf::OutAutoSelectBuffer<'_, u8>) -> Result<u64> {
+        let out_buf = unsafe { core::slice::from_raw_parts_mut(out_buf.get_address() as *mut u8, out_buf.get_size()) };
+        out_buf.fill(value);
+        Ok(out_buf.len() as u64)
+    }
+
+    fn fill_map_alias_buffer(&mut self, value: u8, out_buf: sf::OutMapAliasBuffer<'_, u8>) -> Result<u64> {
+        let out_buf = unsafe { core::slice::from_raw_parts_mut(out_buf.get_address() as *mut u8, out_buf.get_size()) };
+        out_buf.fill(value);
+        Ok(out_buf.len() as u64)
+    }
+
+    fn fill_pointer_buffer(&mut self, value: u8, out_buf: sf::OutPointerBuffer<'_, u8>) -> Result<u64> {
+        let out_buf = unsafe { core::slice::from_raw_parts_mut(out_buf.get_address() as *mut u8, out_buf.get_size()) };
+        out_buf.fill(value);
+        Ok(out_buf.len() as u64)
+    }
+
+    fn is_event_signaled(&mut self, event: sf::CopyHandle) -> Result<bool> {
+        // Waiting with no timeout just checks the current state
+        let signaled = wait::wait_handles(&[event.handle], 0).is_ok();
+
+        // This copy is ours, so we have to close it
+        svc::close_handle(event.handle)?;
+        Ok(signaled)
+    }
+
+    fn create_signaled_event(&mut self) -> Result<sf::MoveHandle> {
+        let (writable_handle, readable_handle) = svc::create_event()?;
+        svc::signal_event(writable_handle)?;
+        svc::close_handle(writable_handle)?;
+
+        // Once moved we're no longer responsible for closing it
+        Ok(sf::MoveHandle { handle: readable_handle })
+    }
+
+    fn get_process_id(&mut self, process_id: sf::ProcessId) -> Result<u64> {
+        diag_log!(LmLogger { LogSeverity::Trace, true } => "Sender process ID: {:#X}", process_id.process_id);
+        Ok(process_id.process_id)
+    }
+
+    fn open_counter(&mut self, initial_value: u64) -> Result<impl ICounterServer + 'static + server::ISessionObject> {
+        Ok(CounterServer::new(initial_value, None))
+    }
+
+    fn register_client(&mut self, process_id: sf::ProcessId) -> Result<()> {
+        self.client_process_id = Some(process_id.process_id);
+        Ok(())
+    }
+
+    fn get_client_process_id(&mut self) -> Result<u64> {
+        Ok(self.client_process_id.unwrap_or(0))
+    }
+
+    fn increment_session_counter(&mut self) -> Result<u64> {
+        self.counter += 1;
+        Ok(self.counter)
+    }
+
+    fn set_entry(&mut self, key: u32, value: u64) -> Result<bool> {
+        match self.entries.iter_mut().find(|(entry_key, _)| *entry_key == key) {
+            Some((_, entry_value)) => *entry_value = value,
+            None if self.entries.len() < MAX_SESSION_ENTRIES => self.entries.push((key, value)),
+            // Our heap is tiny, don't let clients eat it
+            None => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn get_entry(&mut self, key: u32) -> Result<(bool, u64)> {
+        Ok(match self.entries.iter().find(|(entry_key, _)| *entry_key == key) {
+            Some(&(_, value)) => (true, value),
+            None => (false, 0),
+        })
+    }
+
+    fn get_session_count(&mut self) -> Result<u32> {
+        Ok(G_SHARED_STATE.lock().session_count)
+    }
+
+    fn increment_shared_counter(&mut self) -> Result<u64> {
+        let mut shared_state = G_SHARED_STATE.lock();
+        shared_state.counter += 1;
+        Ok(shared_state.counter)
+    }
+
+    fn get_session_protocol(&mut self) -> Result<u8> {
+        Ok(self.protocol_id)
+    }
+
+    fn start_job(&mut self, value: u32, delay_ms: u32) -> Result<sf::MoveHandle> {
+        let (writable_event, readable_event) = svc::create_event()?;
+        let result = Arc::new(Mutex::new(None));
+        let job = Job {
+            value,
+            delay_ms: delay_ms.min(MAX_JOB_DELAY_MS),
+            writable_event,
+            result: result.clone(),
+        };
+
+        let mut job_queue = G_JOB_QUEUE.lock();
+        // A job which already started just runs until it's done, only its result is forgotten
+        let prev_job = self.job_result.as_ref().and_then(|job_result| job_queue.take_session_job(job_result));
+        if let Some(prev_job) = prev_job {
+            prev_job.cancel();
+        }
+        if job_queue.jobs.len() < MAX_PENDING_JOBS {
+            job_queue.jobs.push(job);
+            let _ = svc::signal_event(job_queue.wakeup_event);
+        } else {
+            job.cancel();
+        }
+        drop(job_queue);
+        self.job_result = Some(result);
+
+        Ok(sf::MoveHandle { handle: readable_event })
+    }
+
+    fn get_job_result(&mut self) -> Result<(bool, u64)> {
+        let result = self.job_result.as_ref().and_then(|result| *result.lock());
+        Ok((result.is_some(), result.unwrap_or(0)))
+    }
 }
 
 impl server::ISessionObject for DemoServiceServer {
@@ -48,25 +363,77 @@ impl server::ISessionObject for DemoServiceServer {
         protocol: nx::ipc::CommandProtocol,
         server_ctx: &mut server::ServerContext,
     ) -> Option<Result<()>> {
+        // Clients choose the protocol, the same session object handles both
+        self.protocol_id = get_protocol_id(protocol);
         <Self as IDemoServiceServer>::try_handle_request_by_id(self, req_id, protocol, server_ctx)
     }
 }
 
 impl server::IServerObject for DemoServiceServer {
     fn new() -> Self {
-        Self
+        // Called for every new session
+        G_SHARED_STATE.lock().session_count += 1;
+        Self {
+            client_process_id: None,
+            counter: 0,
+            entries: Vec::new(),
+            protocol_id: PROTOCOL_ID_CMIF,
+            job_result: None,
+        }
     }
 }
 
 impl server::IService for DemoServiceServer {
     fn get_name() -> sm::ServiceName {
-        sm::ServiceName::new("dmo-srv")
+        sm::ServiceName::new(DEMO_SERVICE_NAME)
     }
     fn get_max_sesssions() -> i32 {
         20
     }
 }
 
+pub struct DemoDomainServiceServer {
+    open_count: Arc<AtomicU32>,
+}
+
+impl IDemoDomainServiceServer for DemoDomainServiceServer {
+    fn open_counter(&mut self, initial_value: u64) -> Result<impl ICounterServer + 'static + server::ISessionObject> {
+        Ok(CounterServer::new(initial_value, Some(self.open_count.clone())))
+    }
+
+    fn get_open_counter_count(&mut self) -> Result<u32> {
+        Ok(self.open_count.load(Ordering::SeqCst))
+    }
+}
+
+impl server::ISessionObject for DemoDomainServiceServer {
+    fn try_handle_request_by_id(
+        &mut self,
+        req_id: u32,
+        protocol: nx::ipc::CommandProtocol,
+        server_ctx: &mut server::ServerContext,
+    ) -> Option<Result<()>> {
+        <Self as IDemoDomainServiceServer>::try_handle_request_by_id(self, req_id, protocol, server_ctx)
+    }
+}
+
+impl server::IServerObject for DemoDomainServiceServer {
+    fn new() -> Self {
+        Self {
+            open_count: Arc::new(AtomicU32::new(0)),
+        }
+    }
+}
+
+impl server::IService for DemoDomainServiceServer {
+    fn get_name() -> sm::ServiceName {
+        sm::ServiceName::new(DEMO_DOMAIN_SERVICE_NAME)
+    }
+    fn get_max_sesssions() -> i32 {
+        DEMO_DOMAIN_SERVICE_MAX_SESSIONS
+    }
+}
+
 // We're using 128KB of heap
 const CUSTOM_HEAP_LEN: usize = 0x20000;
 static mut CUSTOM_HEAP: [u8; CUSTOM_HEAP_LEN] = [0; CUSTOM_HEAP_LEN];
@@ -78,10 +445,18 @@ pub fn initialize_heap(_hbl_heap: util::PointerAndSize) -> util::PointerAndSize
 
 #[no_mangle]
 pub fn main() {
-    const POINTER_BUF_SIZE: usize = 0x400;
-    let mut manager: server::ServerManager<POINTER_BUF_SIZE> = server::ServerManager::new().unwrap();
+    let (writable_wakeup_event, readable_wakeup_event) = svc::create_event().unwrap();
+    G_JOB_QUEUE.lock().wakeup_event = writable_wakeup_event;
+    let _job_thread = thread::Builder::new()
+        .name("dmo.Jobs")
+        .stack_size(0x2000)
+        .spawn(move || job_thread(readable_wakeup_event))
+        .unwrap();
+
+    let mut manager: server::ServerManager<POINTER_BUFFER_SIZE> = server::ServerManager::new().unwrap();
 
     manager.register_service_server::<DemoServiceServer>().unwrap();
+    manager.register_service_server::<DemoDomainServiceServer>().unwrap();
     manager.loop_process().unwrap();
 }
 